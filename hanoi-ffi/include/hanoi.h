@@ -0,0 +1,53 @@
+/* The index of hanoi in the process of a C or C++ program, see src/lib.rs.
+ * Link with libhanoi.so or libhanoi.a from target/release.
+ *
+ * Strings passed in are UTF-8 and NUL-terminated. The strings of a match
+ * belong to its results, errors to the caller, who frees them with
+ * hanoi_string_free. An index may be searched from several threads, but
+ * results are read from one thread at a time. Failures inside hanoi, panics
+ * included, are reported through error or NULL and never unwind into the
+ * caller. */
+#ifndef HANOI_H
+#define HANOI_H
+
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct hanoi_index hanoi_index;
+typedef struct hanoi_results hanoi_results;
+
+typedef struct {
+    const char *path;
+    /* Counted from 1 */
+    size_t line;
+    const char *text;
+} hanoi_match;
+
+/* Indexes the files of root that its .hanoi lets in. NULL on failure, with
+ * the reason in *error unless error is NULL. */
+hanoi_index *hanoi_index_build(const char *root, char **error);
+
+/* Applies changes to the files of the root until the index is freed.
+ * 0 on success, -1 with the reason in *error otherwise. */
+int hanoi_index_watch(hanoi_index *index, char **error);
+
+/* The lines with term, only where it is a whole identifier unless word is
+ * 0. NULL when index or term is, or the search failed. */
+hanoi_results *hanoi_index_search(const hanoi_index *index, const char *term, int word);
+
+/* The next match, valid until the next call or hanoi_results_free. NULL
+ * once every match was handed out. */
+const hanoi_match *hanoi_results_next(hanoi_results *results);
+
+void hanoi_results_free(hanoi_results *results);
+void hanoi_index_free(hanoi_index *index);
+void hanoi_string_free(char *string);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
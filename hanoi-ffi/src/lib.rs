@@ -0,0 +1,199 @@
+use hanoi_core::{Index, Match, Watch};
+
+use std::{
+    any::Any,
+    ffi::{c_char, c_int, CStr, CString},
+    panic::{self, AssertUnwindSafe},
+    path::PathBuf,
+    ptr, vec,
+};
+
+// A C ABI over Index for editors and tools in C or C++, built into the
+// cdylib and staticlib of this crate:
+//   hanoi_index *index = hanoi_index_build("/src/project", &error);
+//   hanoi_results *results = hanoi_index_search(index, "fn main", 0);
+//   for (const hanoi_match *found; (found = hanoi_results_next(results));)
+//       printf("%s:%zu: %s\n", found->path, found->line, found->text);
+//   hanoi_results_free(results);
+//   hanoi_index_free(index);
+// Strings passed in are UTF-8 and NUL-terminated. Strings handed out are
+// owned by the object they come from, except errors, which the caller frees
+// with hanoi_string_free. A panic never unwinds into C, it is reported as
+// an error or NULL.
+pub struct HanoiIndex {
+    index: Index,
+    watch: Option<Watch>,
+}
+
+#[repr(C)]
+pub struct HanoiMatch {
+    path: *const c_char,
+    line: usize,
+    text: *const c_char,
+}
+
+pub struct HanoiResults {
+    matches: vec::IntoIter<Match>,
+    // What the last HanoiMatch handed out points into
+    strings: (CString, CString),
+    current: HanoiMatch,
+}
+
+/// Indexes the files of `root` that its .hanoi lets in. NULL on failure,
+/// with the reason in `*error`.
+///
+/// # Safety
+///
+/// `root` is NULL or a NUL-terminated string. `error` is NULL or points to
+/// a `char *` the reason is written to, which the caller then owns and
+/// frees with hanoi_string_free. The index returned is freed with
+/// hanoi_index_free.
+#[no_mangle]
+pub unsafe extern "C" fn hanoi_index_build(root: *const c_char, error: *mut *mut c_char) -> *mut HanoiIndex {
+    let built = catching(|| c_str(root).ok_or_else(|| String::from("root is not a UTF-8 string")).and_then(|root| Index::build(PathBuf::from(root)).map_err(|e| e.to_string())));
+    match built {
+        Ok(index) => Box::into_raw(Box::new(HanoiIndex { index, watch: None })),
+        Err(e) => {
+            set_error(error, e);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Keeps the index current until it is freed. 0 on success, -1 with the
+/// reason in `*error` otherwise.
+///
+/// # Safety
+///
+/// `index` is NULL or an index of hanoi_index_build not freed yet, which no
+/// other thread uses during the call. `error` is as for hanoi_index_build.
+#[no_mangle]
+pub unsafe extern "C" fn hanoi_index_watch(index: *mut HanoiIndex, error: *mut *mut c_char) -> c_int {
+    let Some(index) = index.as_mut() else {
+        return -1;
+    };
+    match catching(|| index.index.watch().map_err(|e| e.to_string())) {
+        Ok(watch) => {
+            index.watch = Some(watch);
+            0
+        }
+        Err(e) => {
+            set_error(error, e);
+            -1
+        }
+    }
+}
+
+/// The matches of `term`, whole identifiers only unless `word` is 0. NULL
+/// when `index` or `term` is, or the search failed.
+///
+/// # Safety
+///
+/// `index` is NULL or an index of hanoi_index_build not freed yet, and may
+/// be searched from several threads at once. `term` is NULL or a
+/// NUL-terminated string. The results returned are freed with
+/// hanoi_results_free, before the index is.
+#[no_mangle]
+pub unsafe extern "C" fn hanoi_index_search(index: *const HanoiIndex, term: *const c_char, word: c_int) -> *mut HanoiResults {
+    let (Some(index), Some(term)) = (index.as_ref(), c_str(term)) else {
+        return ptr::null_mut();
+    };
+    let Ok(matches) = catching(|| Ok(if word != 0 { index.index.search_word(term) } else { index.index.search(term) })) else {
+        return ptr::null_mut();
+    };
+    Box::into_raw(Box::new(HanoiResults {
+        matches: matches.into_iter(),
+        strings: (CString::default(), CString::default()),
+        current: HanoiMatch { path: ptr::null(), line: 0, text: ptr::null() },
+    }))
+}
+
+/// The next match, valid until the next call or hanoi_results_free. NULL
+/// once they are all handed out.
+///
+/// # Safety
+///
+/// `results` is NULL or results of hanoi_index_search not freed yet, which
+/// no other thread uses during the call. The match and its strings belong
+/// to `results`, the caller doesn't free them.
+#[no_mangle]
+pub unsafe extern "C" fn hanoi_results_next(results: *mut HanoiResults) -> *const HanoiMatch {
+    let Some(results) = results.as_mut() else {
+        return ptr::null();
+    };
+    let Some(found) = results.matches.next() else {
+        return ptr::null();
+    };
+    results.strings = (to_c_string(&found.path.to_string_lossy()), to_c_string(&found.text));
+    results.current = HanoiMatch { path: results.strings.0.as_ptr(), line: found.line, text: results.strings.1.as_ptr() };
+    &results.current
+}
+
+/// # Safety
+///
+/// `results` is NULL or results of hanoi_index_search not freed yet. The
+/// matches handed out from them are no longer valid afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn hanoi_results_free(results: *mut HanoiResults) {
+    if !results.is_null() {
+        let _ = catching(|| {
+            drop(Box::from_raw(results));
+            Ok(())
+        });
+    }
+}
+
+/// Stops the watch of the index, if any, and frees it.
+///
+/// # Safety
+///
+/// `index` is NULL or an index of hanoi_index_build not freed yet, with
+/// none of its results left and no other thread using it.
+#[no_mangle]
+pub unsafe extern "C" fn hanoi_index_free(index: *mut HanoiIndex) {
+    if !index.is_null() {
+        let _ = catching(|| {
+            drop(Box::from_raw(index));
+            Ok(())
+        });
+    }
+}
+
+/// # Safety
+///
+/// `string` is NULL or an error handed out by this library, not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn hanoi_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+// Runs `body`, with a panic turned into an error rather than unwinding into
+// the C caller.
+fn catching<T>(body: impl FnOnce() -> Result<T, String>) -> Result<T, String> {
+    panic::catch_unwind(AssertUnwindSafe(body)).unwrap_or_else(|panic| Err(panic_message(panic.as_ref())))
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> String {
+    let message = panic.downcast_ref::<&str>().copied().or_else(|| panic.downcast_ref::<String>().map(String::as_str));
+    format!("hanoi panicked: {}", message.unwrap_or("unknown cause"))
+}
+
+unsafe fn c_str<'a>(string: *const c_char) -> Option<&'a str> {
+    if string.is_null() {
+        return None;
+    }
+    CStr::from_ptr(string).to_str().ok()
+}
+
+// NUL bytes of binary files would cut the string short.
+fn to_c_string(string: &str) -> CString {
+    CString::new(string.replace('\0', "")).unwrap_or_default()
+}
+
+unsafe fn set_error(error: *mut *mut c_char, message: String) {
+    if !error.is_null() {
+        *error = to_c_string(&message).into_raw();
+    }
+}
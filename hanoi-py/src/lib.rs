@@ -0,0 +1,91 @@
+use hanoi_core::{Error, Index, Match, Watch};
+use pyo3::{
+    exceptions::{PyOSError, PyRuntimeError, PyValueError},
+    prelude::*,
+};
+
+use std::{path::PathBuf, sync::Mutex};
+
+// The index of hanoi_core for Python, so code queries can be scripted in
+// notebooks without a server or parsing the output of the client:
+//   import hanoi
+//   index = hanoi.build("/src/project")
+//   index.watch()
+//   for found in index.search("fn main", word=True):
+//       print(found.path, found.line, found.text)
+#[pyclass(name = "Index", module = "hanoi", frozen)]
+struct PyIndex {
+    index: Index,
+    watch: Mutex<Option<Watch>>,
+}
+
+#[pyclass(name = "Match", module = "hanoi", frozen, get_all)]
+struct PyMatch {
+    path: String,
+    // Counted from 1
+    line: usize,
+    text: String,
+}
+
+impl From<Match> for PyMatch {
+    fn from(found: Match) -> PyMatch {
+        PyMatch { path: found.path.to_string_lossy().into_owned(), line: found.line, text: found.text }
+    }
+}
+
+#[pymethods]
+impl PyMatch {
+    fn __repr__(&self) -> String {
+        format!("Match({:?}, {}, {:?})", self.path, self.line, self.text)
+    }
+}
+
+// Indexes the files of `root` that its .hanoi lets in.
+#[pyfunction]
+fn build(py: Python<'_>, root: PathBuf) -> PyResult<PyIndex> {
+    let index = py.allow_threads(|| Index::build(root)).map_err(to_py_err)?;
+    Ok(PyIndex { index, watch: Mutex::new(None) })
+}
+
+#[pymethods]
+impl PyIndex {
+    #[new]
+    fn new(py: Python<'_>, root: PathBuf) -> PyResult<PyIndex> {
+        build(py, root)
+    }
+
+    // The lines with `term`, only where it is a whole identifier with word.
+    #[pyo3(signature = (term, word = false))]
+    fn search(&self, py: Python<'_>, term: &str, word: bool) -> Vec<PyMatch> {
+        let matches = py.allow_threads(|| if word { self.index.search_word(term) } else { self.index.search(term) });
+        matches.into_iter().map(PyMatch::from).collect()
+    }
+
+    fn files(&self) -> Vec<String> {
+        self.index.files().iter().map(|path| path.to_string_lossy().into_owned()).collect()
+    }
+
+    // Applies changes to the files of the root for as long as the index is
+    // around.
+    fn watch(&self) -> PyResult<()> {
+        let watch = self.index.watch().map_err(to_py_err)?;
+        *self.watch.lock().unwrap_or_else(|e| e.into_inner()) = Some(watch);
+        Ok(())
+    }
+}
+
+fn to_py_err(e: Error) -> PyErr {
+    match e {
+        Error::Read(..) => PyOSError::new_err(e.to_string()),
+        Error::Config(..) => PyValueError::new_err(e.to_string()),
+        _ => PyRuntimeError::new_err(e.to_string()),
+    }
+}
+
+#[pymodule]
+fn hanoi(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyIndex>()?;
+    module.add_class::<PyMatch>()?;
+    module.add_function(wrap_pyfunction!(build, module)?)?;
+    Ok(())
+}
@@ -0,0 +1,69 @@
+use crate::vfs::Vfs;
+
+use flate2::read::GzDecoder;
+
+use std::{
+    io::{self, Cursor, Read},
+    path::{Path, PathBuf},
+};
+
+// Entries inside archives are indexed as "<archive>!<inner path>".
+pub const SEPARATOR: char = '!';
+
+pub fn is_archive(path: &Path) -> bool {
+    let name = path.file_name().and_then(|name| name.to_str()).unwrap_or("").to_ascii_lowercase();
+    name.ends_with(".zip") || name.ends_with(".tar.gz") || name.ends_with(".tgz")
+}
+
+pub fn entry_path(archive: &Path, inner: &str) -> PathBuf {
+    PathBuf::from(format!("{}{}{}", archive.display(), SEPARATOR, inner))
+}
+
+pub fn is_entry_of(path: &Path, archive: &Path) -> bool {
+    let prefix = format!("{}{}", archive.display(), SEPARATOR);
+    path.display().to_string().starts_with(&prefix)
+}
+
+// The archive holding the entry `path`, if it is one.
+pub fn archive_of(path: &Path) -> Option<PathBuf> {
+    let path_str = path.display().to_string();
+    path_str
+        .match_indices(SEPARATOR)
+        .map(|(index, _)| PathBuf::from(&path_str[..index]))
+        .find(|archive| is_archive(archive))
+}
+
+// Returns the text files of the archive. Binary entries are skipped.
+pub fn read_archive(vfs: &dyn Vfs, path: &Path) -> io::Result<Vec<(PathBuf, String)>> {
+    let bytes = vfs.read(path)?;
+    let name = path.display().to_string().to_ascii_lowercase();
+    let mut entries = Vec::new();
+    if name.ends_with(".zip") {
+        let mut archive = zip::ZipArchive::new(Cursor::new(bytes)).map_err(io::Error::other)?;
+        for index in 0..archive.len() {
+            let mut file = archive.by_index(index).map_err(io::Error::other)?;
+            if !file.is_file() {
+                continue;
+            }
+            let inner = file.name().to_string();
+            let mut text = String::new();
+            if file.read_to_string(&mut text).is_ok() {
+                entries.push((entry_path(path, &inner), text));
+            }
+        }
+    } else {
+        let mut archive = tar::Archive::new(GzDecoder::new(Cursor::new(bytes)));
+        for file in archive.entries()? {
+            let mut file = file?;
+            if !file.header().entry_type().is_file() {
+                continue;
+            }
+            let inner = file.path()?.display().to_string();
+            let mut text = String::new();
+            if file.read_to_string(&mut text).is_ok() {
+                entries.push((entry_path(path, &inner), text));
+            }
+        }
+    }
+    Ok(entries)
+}
@@ -0,0 +1,52 @@
+use crate::{convert_path, runtime};
+
+use rand::{distributions::Alphanumeric, Rng};
+
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+// Servers only answer requests carrying the secret they write here when they
+// start. The file is only readable by its owner, so other users on the
+// machine can't query the index through the socket.
+fn token_path(address: &Path) -> PathBuf {
+    runtime::runtime_dir().join("tokens").join(convert_path(address))
+}
+
+pub fn create_token(address: &Path) -> io::Result<(String, PathBuf)> {
+    let token: String = rand::thread_rng().sample_iter(&Alphanumeric).take(32).map(char::from).collect();
+    let path = token_path(address);
+    runtime::create_dir("tokens")?;
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+
+        options.mode(0o600);
+    }
+    options.open(&path)?.write_all(token.as_bytes())?;
+    Ok((token, path))
+}
+
+// The token of the server at `address`, empty if it can't be read.
+pub fn read_token(address: &Path) -> String {
+    fs::read_to_string(token_path(address)).unwrap_or_default()
+}
+
+// Who sent a request, from the token it carries, never from anything the
+// client says of itself. Only the owner can read the token of the local
+// socket, other users reach tenants over --listen or --http with theirs.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Caller {
+    // The user the server runs as, or whoever has its token
+    Owner,
+    User(String),
+}
+
+// Compares in constant time so the token can't be guessed a byte at a time.
+pub fn matches(expected: &str, given: &str) -> bool {
+    expected.len() == given.len() && expected.bytes().zip(given.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
@@ -0,0 +1,67 @@
+use crate::vfs::{OsVfs, Vfs};
+
+use std::{collections::HashMap, sync::Arc};
+
+// Who last changed the line of each result for --blame, as "name date" of
+// the commit, read with libgit2 from the repository of the file. Files are
+// blamed as they are on disk, lines changed since the last commit are "not
+// committed". Results of files that are not in a repository, or that can't
+// be read from here, are left as they are.
+pub struct Blamer {
+    // The blame of every line of the files seen so far, from line 1
+    files: HashMap<String, Option<Vec<Option<String>>>>,
+    vfs: Arc<dyn Vfs>,
+}
+
+impl Default for Blamer {
+    fn default() -> Blamer {
+        Blamer { files: HashMap::new(), vfs: Arc::new(OsVfs) }
+    }
+}
+
+impl Blamer {
+    pub fn line(&mut self, path: &str, line_num: usize) -> Option<&str> {
+        let lines = self.files.entry(path.to_string()).or_insert_with(|| blame_file(self.vfs.as_ref(), path)).as_ref()?;
+        lines.get(line_num.checked_sub(1)?)?.as_deref()
+    }
+}
+
+#[cfg(feature = "blame")]
+fn blame_file(vfs: &dyn Vfs, path: &str) -> Option<Vec<Option<String>>> {
+    use crate::format_system_time;
+
+    use git2::Repository;
+
+    use std::{
+        path::Path,
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    let path = Path::new(path);
+    let repository = Repository::discover(path.parent()?).ok()?;
+    let relative_path = path.strip_prefix(repository.workdir()?).ok()?;
+    let contents = vfs.read(path).ok()?;
+    let committed = repository.blame_file(relative_path, None).ok()?;
+    let blame = committed.blame_buffer(&contents).ok()?;
+    let mut lines = vec![None; contents.split(|byte| *byte == b'\n').count()];
+    for hunk in blame.iter() {
+        // The signature of lines only in the buffer is not set
+        let annotation = if hunk.final_commit_id().is_zero() {
+            String::from("not committed")
+        } else {
+            let signature = hunk.final_signature();
+            let date = format_system_time(UNIX_EPOCH + Duration::from_secs(signature.when().seconds().max(0) as u64));
+            format!("{} {}", signature.name().unwrap_or("?"), &date[..10])
+        };
+        let start = hunk.final_start_line().saturating_sub(1);
+        for line in lines.iter_mut().skip(start).take(hunk.lines_in_hunk()) {
+            *line = Some(annotation.clone());
+        }
+    }
+    Some(lines)
+}
+
+#[cfg(not(feature = "blame"))]
+fn blame_file(_vfs: &dyn Vfs, _path: &str) -> Option<Vec<Option<String>>> {
+    None
+}
@@ -0,0 +1,83 @@
+use crate::error::Error;
+
+use bincode::{config, Decode, Encode};
+use clap::ValueEnum;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+// How the Args of a request and the frames of its replies are encoded. The
+// header in front of every request is always bincode so any server can read
+// its version; the encoding it names is used for everything after it.
+// Servers ask each other in bincode, so only the replies to the client that
+// picked another --codec are encoded with it.
+pub trait Codec {
+    fn encode<T: Encode + Serialize>(&self, value: &T) -> Result<Vec<u8>, Error>;
+    fn decode<T: Decode + DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, Error>;
+}
+
+pub struct Bincode;
+
+impl Codec for Bincode {
+    fn encode<T: Encode + Serialize>(&self, value: &T) -> Result<Vec<u8>, Error> {
+        bincode::encode_to_vec(value, config::standard()).map_err(|e| Error::Encode(e.to_string()))
+    }
+
+    fn decode<T: Decode + DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, Error> {
+        bincode::decode_from_slice(bytes, config::standard()).map(|(value, _)| value).map_err(|e| Error::Decode(e.to_string()))
+    }
+}
+
+// Structs are maps keyed by field name, enums maps from the variant name to
+// its fields, or just the name for variants without any.
+pub struct MessagePack;
+
+impl Codec for MessagePack {
+    fn encode<T: Encode + Serialize>(&self, value: &T) -> Result<Vec<u8>, Error> {
+        rmp_serde::to_vec_named(value).map_err(|e| Error::Encode(e.to_string()))
+    }
+
+    fn decode<T: Decode + DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, Error> {
+        rmp_serde::from_slice(bytes).map_err(|e| Error::Decode(e.to_string()))
+    }
+}
+
+// Laid out like MessagePack.
+pub struct Cbor;
+
+impl Codec for Cbor {
+    fn encode<T: Encode + Serialize>(&self, value: &T) -> Result<Vec<u8>, Error> {
+        let mut encoded = Vec::new();
+        ciborium::into_writer(value, &mut encoded).map_err(|e| Error::Encode(e.to_string()))?;
+        Ok(encoded)
+    }
+
+    fn decode<T: Decode + DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, Error> {
+        ciborium::from_reader(bytes).map_err(|e| Error::Decode(e.to_string()))
+    }
+}
+
+// The codec picked with --codec and named in the header of a request.
+#[derive(Encode, Decode, Serialize, Deserialize, ValueEnum, Clone, Copy, PartialEq, Debug, Default)]
+pub enum Encoding {
+    #[default]
+    Bincode,
+    Msgpack,
+    Cbor,
+}
+
+impl Codec for Encoding {
+    fn encode<T: Encode + Serialize>(&self, value: &T) -> Result<Vec<u8>, Error> {
+        match self {
+            Encoding::Bincode => Bincode.encode(value),
+            Encoding::Msgpack => MessagePack.encode(value),
+            Encoding::Cbor => Cbor.encode(value),
+        }
+    }
+
+    fn decode<T: Decode + DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, Error> {
+        match self {
+            Encoding::Bincode => Bincode.decode(bytes),
+            Encoding::Msgpack => MessagePack.decode(bytes),
+            Encoding::Cbor => Cbor.decode(bytes),
+        }
+    }
+}
@@ -0,0 +1,46 @@
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    mem,
+    time::SystemTime,
+};
+
+// Maps keep their capacity after removals, so heavy churn leaves memory
+// behind. Compaction runs on its own once the slack is this large and more
+// than the space used by live entries.
+pub const AUTO_COMPACT_MIN_SLACK: usize = 1 << 20;
+
+#[derive(Default)]
+pub struct CompactionStats {
+    pub runs: usize,
+    pub reclaimed: u64,
+    pub last: Option<SystemTime>,
+}
+
+impl CompactionStats {
+    pub fn record(&mut self, reclaimed: usize) {
+        self.runs += 1;
+        self.reclaimed += reclaimed as u64;
+        self.last = Some(SystemTime::now());
+    }
+}
+
+// Bytes held by the unused slots of `map`.
+pub fn slack<K, V>(map: &HashMap<K, V>) -> usize {
+    (map.capacity() - map.len()) * mem::size_of::<(K, V)>()
+}
+
+pub fn used<K, V>(map: &HashMap<K, V>) -> usize {
+    map.len() * mem::size_of::<(K, V)>()
+}
+
+// Shrinks `map` to its entries and returns the bytes released.
+pub fn shrink<K: Eq + Hash, V>(map: &mut HashMap<K, V>) -> usize {
+    let before = slack(map);
+    map.shrink_to_fit();
+    before.saturating_sub(slack(map))
+}
+
+pub fn needs_compaction(slack: usize, used: usize) -> bool {
+    slack >= AUTO_COMPACT_MIN_SLACK && slack > used
+}
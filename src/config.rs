@@ -0,0 +1,101 @@
+use crate::{
+    messages::message,
+    options::{parse_bool, parse_option},
+    output::OutputFormat,
+    vfs::Vfs,
+    Args,
+};
+
+use clap::ValueEnum;
+
+use std::{env, path::PathBuf};
+
+// Client defaults, so preferences don't need a shell alias each. One
+// `key = value` per line in the TOML of ~/.config/hanoi/config.toml:
+//   # Lines starting with # are comments
+//   format = "vimgrep"
+//   heading = true
+//   preview = 2
+//   editor = "code --wait --goto"
+// Flags on the command line take precedence, as with the [options] of
+// .hanoi files. Sections such as [client] are accepted and ignored.
+pub fn path() -> Option<PathBuf> {
+    let base = if cfg!(target_os = "windows") {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else {
+        env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+    };
+    base.map(|base| base.join("hanoi").join("config.toml"))
+}
+
+// Fills in what the command line left out. Lines that can't be parsed are
+// reported on stderr, away from the results, and the rest still applies.
+pub fn apply(vfs: &dyn Vfs, args: &mut Args) {
+    let Some(config_path) = path() else {
+        return;
+    };
+    let Ok(config) = vfs.read_to_string(&config_path) else {
+        return;
+    };
+    for line in config.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') || line.starts_with('[') {
+            continue;
+        }
+        if let Err(e) = parse_client_option(line, args) {
+            eprintln!("{}", message!(ConfigError, config_path.display(), e));
+        }
+    }
+}
+
+fn parse_client_option(line: &str, args: &mut Args) -> Result<(), String> {
+    let (key, value) = line.split_once('=').ok_or_else(|| format!("expected \"key = value\", found \"{}\"", line))?;
+    let (key, value) = (key.trim(), unquote(value.trim()));
+    match key {
+        "format" => {
+            let format = parse_option(key, value, |value| OutputFormat::from_str(value, true))?;
+            if args.format == OutputFormat::Plain {
+                args.format = format;
+            }
+        }
+        "heading" => {
+            args.heading |= parse_option(key, value, parse_bool)?;
+        }
+        "json" => {
+            args.json |= parse_option(key, value, parse_bool)?;
+        }
+        "ascii" => {
+            args.ascii |= parse_option(key, value, parse_bool)?;
+        }
+        "accessible" => {
+            args.accessible |= parse_option(key, value, parse_bool)?;
+        }
+        "compress" => {
+            args.compress |= parse_option(key, value, parse_bool)?;
+        }
+        "stats" => {
+            args.stats |= parse_option(key, value, parse_bool)?;
+        }
+        "preview" => {
+            let preview = parse_option(key, value, |v| v.parse::<usize>().map_err(|e| e.to_string()))?;
+            args.preview.get_or_insert(preview);
+        }
+        "pager" => {
+            args.no_pager |= !parse_option(key, value, parse_bool)?;
+        }
+        "editor" => {
+            args.editor.get_or_insert_with(|| value.to_string());
+        }
+        _ => return Err(format!("unknown option \"{}\"", key)),
+    }
+    Ok(())
+}
+
+// TOML strings are quoted, the other values are not.
+fn unquote(value: &str) -> &str {
+    ["\"", "'"]
+        .iter()
+        .find_map(|quote| value.strip_prefix(quote).and_then(|value| value.strip_suffix(quote)))
+        .unwrap_or(value)
+}
@@ -0,0 +1,322 @@
+use crate::{compaction, vfs::VfsMetadata};
+
+use bincode::{Decode, Encode};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use std::{
+    borrow::Cow,
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
+    hash::{Hash, Hasher},
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::SystemTime,
+};
+
+#[derive(Encode, Decode, Serialize, Deserialize, ValueEnum, Clone, Copy, PartialEq, Debug, Default)]
+pub enum Compression {
+    #[default]
+    None,
+    Lz4,
+    Zstd,
+}
+
+// File contents as stored in the index. Compressed contents are inflated on
+// every access, so a query costs one decompression per file it touches.
+// Clones share the bytes.
+#[derive(Clone)]
+pub struct FileContent {
+    data: Arc<[u8]>,
+    original_len: usize,
+    compression: Compression,
+}
+
+impl FileContent {
+    const ZSTD_LEVEL: i32 = 3;
+
+    pub fn new(text: String, compression: Compression) -> FileContent {
+        let original_len = text.len();
+        let compressed = match compression {
+            Compression::None => None,
+            Compression::Lz4 => lz4::block::compress(text.as_bytes(), None, false).ok(),
+            Compression::Zstd => zstd::bulk::compress(text.as_bytes(), Self::ZSTD_LEVEL).ok(),
+        };
+        match compressed {
+            Some(data) => FileContent {
+                data: Arc::from(data),
+                original_len,
+                compression,
+            },
+            // Keep the plain text if the compressor failed
+            None => FileContent {
+                data: Arc::from(text.into_bytes()),
+                original_len,
+                compression: Compression::None,
+            },
+        }
+    }
+
+    pub fn text(&self) -> Cow<'_, str> {
+        let bytes = match self.compression {
+            Compression::None => return Cow::Borrowed(std::str::from_utf8(&self.data).unwrap_or_default()),
+            Compression::Lz4 => lz4::block::decompress(&self.data, Some(self.original_len as i32)),
+            Compression::Zstd => zstd::bulk::decompress(&self.data, self.original_len),
+        };
+        match bytes.ok().and_then(|bytes| String::from_utf8(bytes).ok()) {
+            Some(text) => Cow::Owned(text),
+            None => Cow::Borrowed(""),
+        }
+    }
+
+    pub fn stored_len(&self) -> usize {
+        self.data.len()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct ContentId(u64);
+
+struct StoredContent {
+    // None once evicted to stay under --max-memory. The hash is kept so the
+    // text read back from disk can be told apart from a changed file.
+    content: Option<FileContent>,
+    hash: u64,
+    refs: usize,
+    // The clock of the store when a query last matched it
+    last_matched: AtomicU64,
+}
+
+impl StoredContent {
+    fn new(content: Option<FileContent>, hash: u64, refs: usize, last_matched: u64) -> StoredContent {
+        StoredContent { content, hash, refs, last_matched: AtomicU64::new(last_matched) }
+    }
+}
+
+// Identical contents (hard links, vendored copies, generated files) are
+// stored once and shared by every path that has them. Ids start at the hash
+// of the text and move to the next free id on collisions.
+#[derive(Default)]
+pub struct ContentStore {
+    contents: HashMap<u64, StoredContent>,
+    // Released ids with contents after them that collided, so find keeps
+    // probing past them. New contents may take them.
+    tombstones: HashSet<u64>,
+    // Counts matches, so the least recently matched contents are evicted
+    // first. New contents count as just matched.
+    clock: AtomicU64,
+}
+
+impl ContentStore {
+    fn hash(text: &str) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        text.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn is_used(&self, id: u64) -> bool {
+        self.contents.contains_key(&id) || self.tombstones.contains(&id)
+    }
+
+    fn find(&self, hash: u64, text: &str) -> Option<u64> {
+        let mut id = hash;
+        while self.is_used(id) {
+            let stored = self.contents.get(&id);
+            if stored.is_some_and(|stored| stored.hash == hash && stored.content.as_ref().is_some_and(|content| content.original_len == text.len() && content.text() == text)) {
+                return Some(id);
+            }
+            id = id.wrapping_add(1);
+        }
+        None
+    }
+
+    fn add(&mut self, hash: u64, content: Option<FileContent>, refs: usize) -> ContentId {
+        let mut id = hash;
+        while self.contents.contains_key(&id) {
+            id = id.wrapping_add(1);
+        }
+        self.tombstones.remove(&id);
+        let last_matched = self.clock.fetch_add(1, Ordering::Relaxed);
+        self.contents.insert(id, StoredContent::new(content, hash, refs, last_matched));
+        ContentId(id)
+    }
+
+    pub fn insert(&mut self, text: String, compression: Compression) -> ContentId {
+        let hash = Self::hash(&text);
+        match self.find(hash, &text) {
+            Some(id) => {
+                self.contents.get_mut(&id).unwrap().refs += 1;
+                ContentId(id)
+            }
+            None => self.add(hash, Some(FileContent::new(text, compression)), 1),
+        }
+    }
+
+    pub fn release(&mut self, id: ContentId) {
+        let Some(stored) = self.contents.get_mut(&id.0) else {
+            return;
+        };
+        stored.refs -= 1;
+        if stored.refs > 0 {
+            return;
+        }
+        self.contents.remove(&id.0);
+        if self.is_used(id.0.wrapping_add(1)) {
+            self.tombstones.insert(id.0);
+            return;
+        }
+        // The chain ends here now, the tombstones right before are no longer
+        // in the way of anything
+        let mut before = id.0.wrapping_sub(1);
+        while self.tombstones.remove(&before) {
+            before = before.wrapping_sub(1);
+        }
+    }
+
+    // None if the content was evicted.
+    pub fn text(&self, id: ContentId) -> Option<Cow<'_, str>> {
+        let stored = self.contents.get(&id.0)?;
+        Some(stored.content.as_ref()?.text())
+    }
+
+    pub fn is_evicted(&self, id: ContentId) -> bool {
+        self.contents.get(&id.0).is_some_and(|stored| stored.content.is_none())
+    }
+
+    pub fn touch(&self, id: ContentId) {
+        if let Some(stored) = self.contents.get(&id.0) {
+            stored.last_matched.store(self.clock.fetch_add(1, Ordering::Relaxed), Ordering::Relaxed);
+        }
+    }
+
+    // Drops the least recently matched contents, except the ones in `keep`,
+    // until the stored ones fit in `budget` bytes. Returns how many were
+    // evicted.
+    pub fn evict(&mut self, budget: usize, keep: &HashSet<ContentId>) -> usize {
+        let mut stored_len = self.stored_len();
+        if stored_len <= budget {
+            return 0;
+        }
+        let mut candidates: Vec<(u64, u64)> = self.contents
+            .iter()
+            .filter(|(id, stored)| stored.content.is_some() && !keep.contains(&ContentId(**id)))
+            .map(|(id, stored)| (stored.last_matched.load(Ordering::Relaxed), *id))
+            .collect();
+        candidates.sort_unstable();
+        let mut evicted = 0;
+        for (_, id) in candidates {
+            if stored_len <= budget {
+                break;
+            }
+            if let Some(content) = self.contents.get_mut(&id).and_then(|stored| stored.content.take()) {
+                stored_len -= content.stored_len();
+                evicted += 1;
+            }
+        }
+        evicted
+    }
+
+    // Takes back the text of an evicted content, read again from disk. It is
+    // ignored if the file changed since.
+    pub fn restore(&mut self, id: ContentId, text: String, compression: Compression) {
+        if let Some(stored) = self.contents.get_mut(&id.0).filter(|stored| stored.content.is_none() && stored.hash == Self::hash(&text)) {
+            stored.content = Some(FileContent::new(text, compression));
+            stored.last_matched.store(self.clock.fetch_add(1, Ordering::Relaxed), Ordering::Relaxed);
+        }
+    }
+
+    pub fn evicted_len(&self) -> usize {
+        self.contents.values().filter(|stored| stored.content.is_none()).count()
+    }
+
+    // Moves the contents of `other` into this store and returns how the ids
+    // of `other` map to ids of this store.
+    pub fn merge(&mut self, other: ContentStore) -> HashMap<ContentId, ContentId> {
+        let mut ids = HashMap::with_capacity(other.contents.len());
+        for (id, stored) in other.contents {
+            // Contents are only evicted from the store of the index
+            let found = stored.content.as_ref().and_then(|content| self.find(stored.hash, &content.text()));
+            let new_id = match found {
+                Some(found) => {
+                    self.contents.get_mut(&found).unwrap().refs += stored.refs;
+                    ContentId(found)
+                }
+                None => self.add(stored.hash, stored.content, stored.refs),
+            };
+            ids.insert(ContentId(id), new_id);
+        }
+        ids
+    }
+
+    pub fn len(&self) -> usize {
+        self.contents.len()
+    }
+
+    pub fn slack(&self) -> usize {
+        compaction::slack(&self.contents)
+    }
+
+    pub fn used(&self) -> usize {
+        compaction::used(&self.contents)
+    }
+
+    pub fn shrink(&mut self) -> usize {
+        compaction::shrink(&mut self.contents)
+    }
+
+    pub fn stored_len(&self) -> usize {
+        self.contents.values().filter_map(|stored| stored.content.as_ref()).map(FileContent::stored_len).sum()
+    }
+}
+
+pub struct IndexedFile {
+    // None until a lazily indexed file is first read
+    pub content: Option<ContentId>,
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+}
+
+impl IndexedFile {
+    pub fn new(content: ContentId, metadata: &VfsMetadata) -> IndexedFile {
+        IndexedFile {
+            content: Some(content),
+            size: metadata.len,
+            modified: metadata.modified,
+        }
+    }
+
+    pub fn unread(metadata: &VfsMetadata) -> IndexedFile {
+        IndexedFile {
+            content: None,
+            size: metadata.len,
+            modified: metadata.modified,
+        }
+    }
+
+    pub fn extension(path: &Path) -> &str {
+        path.extension().and_then(|extension| extension.to_str()).unwrap_or("")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn collisions_are_found_past_released_ids() {
+        let mut store = ContentStore::default();
+        let content = |text: &str| Some(FileContent::new(String::from(text), Compression::None));
+        // Three texts put on the same chain, as if their hashes collided
+        let first = store.add(7, content("first"), 1);
+        let second = store.add(7, content("second"), 1);
+        let third = store.add(7, content("third"), 1);
+        store.release(first);
+        store.release(second);
+        assert_eq!(store.find(7, "third"), Some(third.0));
+        assert_eq!(store.add(7, content("fourth"), 1).0, 7);
+        store.release(third);
+        assert!(store.tombstones.is_empty());
+    }
+}
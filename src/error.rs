@@ -0,0 +1,56 @@
+use crate::{messages::message, protocol::Frame};
+
+use std::{fmt, io, path::PathBuf};
+
+// What can go wrong while a server starts or answers a request. Requests
+// that fail are answered with the error instead of a dropped connection, so
+// the client can tell the user what happened.
+#[derive(Debug)]
+pub enum Error {
+    Read(PathBuf, io::Error),
+    Write(PathBuf, io::Error),
+    // The connection to a client or another server broke or timed out
+    Pipe(io::Error),
+    // A request or reply that its codec can't read
+    Decode(String),
+    Encode(String),
+    // A length prefix beyond MAX_MESSAGE_LEN, most likely garbage
+    TooLarge(usize),
+    // Starting the server of a shard or additional directory
+    Spawn(PathBuf, io::Error),
+    Watch(String),
+    // The [options] of a .hanoi that can't be parsed
+    Config(PathBuf),
+}
+
+impl Error {
+    // The reply telling a client that its request failed.
+    pub fn frame(&self) -> Frame {
+        Frame::Error(message!(ServerFailed, self))
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            Error::Read(path, e) => message!(ErrorRead, path.display(), e),
+            Error::Write(path, e) => message!(ErrorWrite, path.display(), e),
+            Error::Pipe(e) => message!(ErrorPipe, e),
+            Error::Decode(e) => message!(ErrorDecode, e),
+            Error::Encode(e) => message!(ErrorEncode, e),
+            Error::TooLarge(len) => message!(ErrorTooLarge, len),
+            Error::Spawn(root, e) => message!(ErrorSpawn, root.display(), e),
+            Error::Watch(e) => message!(ErrorWatch, e),
+            Error::Config(path) => message!(ErrorConfig, path.display()),
+        };
+        f.write_str(&text)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Pipe(e)
+    }
+}
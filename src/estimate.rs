@@ -0,0 +1,89 @@
+use crate::{
+    archive,
+    content::{Compression, IndexedFile},
+    filter_file, in_shard, read_root_config, read_tracked,
+    options::ByteSize,
+    visit_dirs,
+    vfs::{OsVfs, Vfs},
+    Args, Filter, WalkState, EXIT_ERROR,
+};
+
+use clap::ValueEnum;
+
+use std::{
+    mem,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+// Typical compressed sizes of source code relative to the plain text.
+fn compression_ratio(compression: Compression) -> f64 {
+    match compression {
+        Compression::None => 1.0,
+        Compression::Lz4 => 0.5,
+        Compression::Zstd => 0.3,
+    }
+}
+
+// Bytes per file besides its contents: the map entry, the content store entry
+// and the spare capacity of both maps.
+const PER_FILE_OVERHEAD: u64 = 2 * (mem::size_of::<(PathBuf, IndexedFile)>() as u64 + 64);
+
+#[derive(Default)]
+struct Walked {
+    files: u64,
+    text_bytes: u64,
+    path_bytes: u64,
+    too_large: u64,
+    archives: u64,
+}
+
+// Walks the metadata of the root with the filters and options of its .hanoi
+// and prints how much memory the index would take, without reading any file.
+pub fn estimate_main(args: &Args) -> ExitCode {
+    let mut args = args.clone();
+    let root = PathBuf::from(args.root.first().cloned().unwrap_or_else(|| String::from(".")));
+    let vfs = OsVfs;
+    let Some(root_config) = read_root_config(&vfs, &root, &mut args) else {
+        return ExitCode::from(EXIT_ERROR);
+    };
+    let max_file_size = args.max_file_size.map(|size| size.0);
+    let mut walked = Walked::default();
+    let mut filters = root_config.filters;
+    let tracked = args.tracked_only.then(|| read_tracked(&root)).flatten();
+    let mut count = |path: &Path, filters: &[Filter]| {
+        if !filter_file(filters, path, &root, tracked.as_deref()) || !in_shard(args.shard, path, &root) {
+            return;
+        }
+        let Ok(metadata) = vfs.metadata(path) else {
+            return;
+        };
+        if max_file_size.is_some_and(|max_file_size| metadata.len > max_file_size) {
+            walked.too_large += 1;
+            return;
+        }
+        if args.archives && archive::is_archive(path) {
+            walked.archives += 1;
+        }
+        walked.files += 1;
+        walked.text_bytes += metadata.len;
+        walked.path_bytes += path.as_os_str().len() as u64;
+    };
+    let _ = visit_dirs(&vfs, &root, &mut count, &root, &mut filters, &mut WalkState::new(args.follow_symlinks, args.hidden).tracking(tracked.clone()));
+
+    let fixed = walked.files * PER_FILE_OVERHEAD + walked.path_bytes;
+    println!("root: {}", root.display());
+    println!("files: {} ({} of text, {} over --max-file-size)", walked.files, ByteSize(walked.text_bytes), walked.too_large);
+    if walked.archives > 0 {
+        // Archives are counted at their compressed size
+        println!("archives: {} (their unpacked contents will take more)", walked.archives);
+    }
+    for compression in [Compression::None, Compression::Lz4, Compression::Zstd] {
+        let contents = (walked.text_bytes as f64 * compression_ratio(compression)) as u64;
+        let current = if compression == args.compression { " (current)" } else { "" };
+        let lazy = if args.lazy { format!(", {} before the first query", ByteSize(fixed)) } else { String::new() };
+        println!("memory with --compression={}: ~{}{}{}", compression.to_possible_value().unwrap().get_name(), ByteSize(fixed + contents), lazy, current);
+    }
+    println!("disk: none, the index is kept in memory");
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,38 @@
+use crate::replies::SharedReplyStream;
+
+use serde_json::{json, Value};
+
+use std::{
+    io::Write,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+// The clients following the server's lifecycle events with --events. Every
+// event is one JSON object per line, e.g.
+//   {"event":"index_completed","time":1700000000,"root":"/src","files":1234}
+static LISTENERS: Mutex<Vec<SharedReplyStream>> = Mutex::new(Vec::new());
+
+pub fn listen(client_reader: SharedReplyStream) {
+    LISTENERS.lock().unwrap_or_else(|e| e.into_inner()).push(client_reader);
+}
+
+// Sends `event` with `fields` to every listener and forgets the ones that
+// went away.
+pub fn emit(event: &str, fields: Value) {
+    let mut listeners = LISTENERS.lock().unwrap_or_else(|e| e.into_inner());
+    if listeners.is_empty() {
+        return;
+    }
+    let mut line = json!({
+        "event": event,
+        "time": SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_secs()),
+    });
+    if let (Some(line), Value::Object(fields)) = (line.as_object_mut(), fields) {
+        line.extend(fields);
+    }
+    listeners.retain(|listener| {
+        let mut client_reader = listener.lock().unwrap_or_else(|e| e.into_inner());
+        writeln!(client_reader, "{}", line).and_then(|_| client_reader.flush()).is_ok()
+    });
+}
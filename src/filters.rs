@@ -0,0 +1,184 @@
+use crate::{
+    deciding_filter, is_hidden, matching_filters, read_nested_filters, read_root_config, read_tracked,
+    vcs::Tracked,
+    vfs::{OsVfs, Vfs},
+    Args, Filter, WalkState, EXIT_ERROR,
+};
+
+use std::{
+    env,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+#[derive(Default)]
+struct Checked {
+    included: u64,
+    excluded: u64,
+}
+
+// Prints the [filters] of the root .hanoi in the order they are applied, or
+// with --check walks the root like a server would and prints every file
+// that would be indexed (+) or left out (-) and the filter that decided:
+//   + src/main.rs (*.rs in .hanoi)
+//   - target/ (!target/ in .hanoi)
+//   - src/gen/parser.rs (!* in src/gen/.hanoi)
+//   - README.md (no filter matches, files are left out by default)
+// Directories are only listed when left out, with everything below them.
+pub fn filters_main(args: &Args) -> ExitCode {
+    let mut args = args.clone();
+    let root = PathBuf::from(args.root.first().cloned().unwrap_or_else(|| String::from(".")));
+    let vfs = OsVfs;
+    let Some(root_config) = read_root_config(&vfs, &root, &mut args) else {
+        return ExitCode::from(EXIT_ERROR);
+    };
+    let mut filters = root_config.filters;
+    if !args.check {
+        if args.tracked_only {
+            println!("tracked_only: only the files git tracks are indexed, unless a filter leaves them out");
+        } else if filters.is_empty() {
+            println!("{} has no filters, no file is indexed", root.join(".hanoi").display());
+        }
+        for filter in &filters {
+            println!("{}", filter);
+        }
+        println!("The last filter that matches a path decides, see --check");
+        return ExitCode::SUCCESS;
+    }
+    let mut checked = Checked::default();
+    let tracked = args.tracked_only.then(|| read_tracked(&root)).flatten();
+    check_dir(&vfs, &root, &root, &mut filters, &mut WalkState::new(args.follow_symlinks, args.hidden).tracking(tracked), &mut checked);
+    println!("{} files would be indexed, {} left out", checked.included, checked.excluded);
+    ExitCode::SUCCESS
+}
+
+// Tells why `hanoi explain <path>` is indexed or not, like git check-ignore
+// -v: every filter that matches it in the order they are applied, and the
+// directories on the way that would keep the walk from reaching it:
+//   src/gen/parser.rs
+//     *.rs in .hanoi: include
+//     !* in src/gen/.hanoi: exclude
+//   left out, excluded by !* in src/gen/.hanoi
+pub fn explain_main(args: &Args) -> ExitCode {
+    let mut args = args.clone();
+    let (Ok(cwd), Some(explained)) = (env::current_dir(), args.explain.clone()) else {
+        return ExitCode::from(EXIT_ERROR);
+    };
+    let path = cwd.join(&explained);
+    let root = args.root.first().map_or_else(|| cwd.clone(), |root| cwd.join(root));
+    let Ok(rel_path) = path.strip_prefix(&root) else {
+        eprintln!("{} is not below the root {}", explained, root.display());
+        return ExitCode::from(EXIT_ERROR);
+    };
+    let vfs = OsVfs;
+    let Some(root_config) = read_root_config(&vfs, &root, &mut args) else {
+        return ExitCode::from(EXIT_ERROR);
+    };
+    let mut filters = root_config.filters;
+    let tracked = args.tracked_only.then(|| read_tracked(&root)).flatten();
+    let tracked = tracked.as_deref();
+    println!("{}", rel_path.display());
+    // The walk has to get through every directory above the path
+    let mut dir = root.clone();
+    for component in rel_path.parent().into_iter().flat_map(Path::components) {
+        read_nested_filters(&vfs, &dir, &root, &mut filters);
+        dir.push(component);
+        if let Some(reason) = left_out(&vfs, &dir, &root, &filters, &args, tracked, true) {
+            println!("left out with {}/, {}", dir.strip_prefix(&root).unwrap_or(&dir).display(), reason);
+            return ExitCode::SUCCESS;
+        }
+    }
+    read_nested_filters(&vfs, &dir, &root, &mut filters);
+    for filter in matching_filters(&filters, &path, &root) {
+        println!("  {}: {}", describe(filter), if filter.should_include { "include" } else { "exclude" });
+    }
+    let is_dir = vfs.metadata(&path).is_ok_and(|metadata| metadata.is_dir);
+    match left_out(&vfs, &path, &root, &filters, &args, tracked, is_dir) {
+        Some(reason) => println!("left out, {}", reason),
+        None if is_dir => println!("walked, {}", deciding_filter(&filters, &path, &root).map_or_else(|| String::from("no filter matches"), describe)),
+        None => println!("indexed by {}", deciding_filter(&filters, &path, &root).map_or_else(|| String::from("tracked_only, git tracks it"), describe)),
+    }
+    ExitCode::SUCCESS
+}
+
+// Why the walk of a server leaves `path` out, if it does.
+fn left_out(vfs: &dyn Vfs, path: &Path, root: &Path, filters: &[Filter], args: &Args, tracked: Option<&Tracked>, is_dir: bool) -> Option<String> {
+    if !args.hidden && is_hidden(path) {
+        return Some(String::from("hidden, see --hidden"));
+    }
+    if !args.follow_symlinks && vfs.symlink_metadata(path).is_ok_and(|metadata| metadata.is_symlink) {
+        return Some(String::from("a symlink, see --follow-symlinks"));
+    }
+    if let Some(reason) = tracked.and_then(|tracked| untracked(tracked, path, is_dir)) {
+        return Some(String::from(reason));
+    }
+    match deciding_filter(filters, path, root) {
+        Some(filter) if !filter.should_include => Some(format!("excluded by {}", describe(filter))),
+        None if !is_dir && tracked.is_none() => Some(String::from("no filter matches, files are left out by default")),
+        _ => None,
+    }
+}
+
+// Why tracked_only leaves `path` out, if it does.
+fn untracked(tracked: &Tracked, path: &Path, is_dir: bool) -> Option<&'static str> {
+    if is_dir {
+        (!tracked.has_below(path)).then_some("git tracks no file below it, see tracked_only")
+    } else {
+        (!tracked.contains(path)).then_some("not tracked by git, see tracked_only")
+    }
+}
+
+// The filter and the .hanoi it is from.
+fn describe(filter: &Filter) -> String {
+    let config = if filter.base.as_os_str().is_empty() { PathBuf::from(".hanoi") } else { filter.base.join(".hanoi") };
+    format!("{} in {}", filter, config.display())
+}
+
+fn check_dir(vfs: &dyn Vfs, dir: &Path, root: &Path, filters: &mut Vec<Filter>, walk: &mut WalkState, checked: &mut Checked) {
+    if !vfs.metadata(dir).is_ok_and(|metadata| metadata.is_dir) || !walk.enter(vfs, dir) {
+        return;
+    }
+    read_nested_filters(vfs, dir, root, filters);
+    let Ok(mut paths) = vfs.read_dir(dir) else {
+        return;
+    };
+    paths.sort();
+    for path in paths {
+        let is_dir = vfs.metadata(&path).is_ok_and(|metadata| metadata.is_dir);
+        let shown = format!("{}{}", path.strip_prefix(root).unwrap_or(&path).display(), if is_dir { "/" } else { "" });
+        if !walk.hidden && is_hidden(&path) {
+            println!("- {} (hidden, see --hidden)", shown);
+            checked.excluded += 1;
+            continue;
+        }
+        if !walk.follow_symlinks && vfs.symlink_metadata(&path).is_ok_and(|metadata| metadata.is_symlink) {
+            println!("- {} (symlink, see --follow-symlinks)", shown);
+            checked.excluded += 1;
+            continue;
+        }
+        if let Some(reason) = walk.tracked.as_deref().and_then(|tracked| untracked(tracked, &path, is_dir)) {
+            println!("- {} ({})", shown, reason);
+            checked.excluded += 1;
+            continue;
+        }
+        let filter = deciding_filter(filters, &path, root);
+        let reason = match filter {
+            Some(filter) => describe(filter),
+            None if is_dir => String::new(),
+            None if walk.tracked.is_some() => String::from("tracked by git"),
+            None => String::from("no filter matches, files are left out by default"),
+        };
+        // Directories no filter matches are walked, and so are the files git
+        // tracks with tracked_only
+        let included = filter.map_or(is_dir || walk.tracked.is_some(), |filter| filter.should_include);
+        if is_dir && included {
+            check_dir(vfs, &path, root, filters, walk, checked);
+        } else if included {
+            println!("+ {} ({})", shown, reason);
+            checked.included += 1;
+        } else {
+            println!("- {} ({})", shown, reason);
+            checked.excluded += 1;
+        }
+    }
+}
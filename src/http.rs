@@ -0,0 +1,249 @@
+use crate::{auth::Caller, messages::message, protocol::Frame, reads_only, scheduler::Scheduler, tenants::Tenants, transport, Cli};
+
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        ConnectInfo, Query, State,
+    },
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use rand::Rng;
+use serde_json::{json, Value};
+use tokio::sync::mpsc::{self, Sender};
+
+use std::{
+    collections::HashMap,
+    io,
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+};
+
+// Frames of a /ws client waiting to be sent. Once that many are, its queries
+// wait for the page to catch up, and stop reading the server meanwhile.
+const MAX_QUEUED_FRAMES: usize = 256;
+
+// Answers plain HTTP for curl users and scripts:
+//   GET /search?q=term  the matches of a search
+//   GET /files          every indexed file
+//   GET /stats          the --status of every server
+//   GET /ws             a WebSocket for live search pages, see below
+// Requests need "Authorization: Bearer <token>" with the server's token, or
+// with a tenant token for the queries of its tenants, see tenants.rs. Those
+// name the tenant with &tenant=user/label, or "--tenant" on /ws.
+// Replies are {"results": [lines], "errors": [messages], "warnings":
+// [messages], "stats": [...]},
+// with the stats of every server for /stats, as in protocol.rs.
+//
+// On /ws, which browsers can also open with ?token=<token>, every text
+// message is a query {"id": 1, "args": ["--", "term"]} with the arguments
+// of the hanoi client, or {"cancel": 1} to stop one. Its replies are the
+// JSON frames of protocol.rs with the "id" of their query added, so the
+// results of a search, --events and --subscribe all stream to the page as
+// they come.
+struct Endpoint {
+    local_address: PathBuf,
+    token: String,
+    tenants: Arc<Tenants>,
+    // The one of --listen, so requests wait their turn whichever way they
+    // came
+    scheduler: Arc<Scheduler>,
+}
+
+type Reply = (StatusCode, Json<Value>);
+
+fn bearer(headers: &HeaderMap) -> Option<&str> {
+    headers.get(header::AUTHORIZATION).and_then(|value| value.to_str().ok()).and_then(|value| value.strip_prefix("Bearer "))
+}
+
+fn error_reply(status: StatusCode, message: String) -> Reply {
+    (status, Json(json!({ "results": [], "errors": [message], "warnings": [], "stats": [] })))
+}
+
+impl Endpoint {
+    // Runs a request of `client` the way the relay of --listen does, with
+    // `client_args` read like the arguments of the hanoi client.
+    async fn run(self: Arc<Self>, headers: &HeaderMap, client: IpAddr, client_args: &[&str]) -> Reply {
+        let Some(caller) = self.tenants.caller_of_token(&self.token, bearer(headers).unwrap_or_default()) else {
+            return error_reply(StatusCode::UNAUTHORIZED, message!(AccessDenied));
+        };
+        let args = match Cli::try_parse_remote(client_args.iter().map(|arg| arg.to_string())) {
+            Ok(args) => args,
+            Err(e) => return error_reply(StatusCode::BAD_REQUEST, message!(InvalidRequest, e.to_string().trim_end())),
+        };
+        // The local server sees this endpoint as its owner
+        if let Err(e) = self.tenants.authorize(&caller, args.tenant.as_deref(), reads_only(&args)) {
+            return error_reply(StatusCode::FORBIDDEN, e);
+        }
+        let trace_id = rand::thread_rng().gen();
+        let collected = tokio::task::spawn_blocking(move || {
+            let _turn = self.scheduler.wait_turn(client)?;
+            let mut results = Vec::new();
+            let mut errors = Vec::new();
+            let mut warnings = Vec::new();
+            let mut stats = Vec::new();
+            transport::forward(args, trace_id, &self.local_address, self.token.clone(), |frame| {
+                match frame {
+                    Frame::ResultLine(line) if !line.trim().is_empty() => results.push(line.trim_end().to_string()),
+                    Frame::Error(message) => errors.push(message.clone()),
+                    Frame::Warning(message) => warnings.push(message.clone()),
+                    Frame::Stats(server) => stats.push(server.to_json()),
+                    _ => {}
+                }
+                true
+            });
+            Some((results, errors, warnings, stats))
+        })
+        .await;
+        match collected {
+            Ok(Some((results, errors, warnings, stats))) => (StatusCode::OK, Json(json!({ "results": results, "errors": errors, "warnings": warnings, "stats": stats }))),
+            Ok(None) => error_reply(StatusCode::TOO_MANY_REQUESTS, message!(TooManyRequests)),
+            Err(e) => error_reply(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+        }
+    }
+
+    // Answers the queries of a /ws client until it goes away.
+    async fn stream(self: Arc<Self>, mut socket: WebSocket, client: IpAddr, caller: Caller) {
+        let (frames, mut outgoing) = mpsc::channel::<Value>(MAX_QUEUED_FRAMES);
+        let mut queries: HashMap<u64, Arc<AtomicBool>> = HashMap::new();
+        loop {
+            tokio::select! {
+                Some(frame) = outgoing.recv() => {
+                    if socket.send(Message::Text(frame.to_string())).await.is_err() {
+                        break;
+                    }
+                }
+                incoming = socket.recv() => {
+                    let Some(Ok(incoming)) = incoming else {
+                        break;
+                    };
+                    let Message::Text(text) = incoming else {
+                        continue;
+                    };
+                    let request: Value = serde_json::from_str(&text).unwrap_or_default();
+                    if let Some(id) = request["cancel"].as_u64() {
+                        if let Some(cancelled) = queries.remove(&id) {
+                            cancelled.store(true, Ordering::Relaxed);
+                        }
+                        continue;
+                    }
+                    let (Some(id), Some(client_args)) = (request["id"].as_u64(), request["args"].as_array()) else {
+                        // A client this far behind isn't reading, it is dropped
+                        if frames.try_send(Frame::Error(message!(InvalidRequest, "expected {\"id\": number, \"args\": [strings]}")).to_json()).is_err() {
+                            break;
+                        }
+                        continue;
+                    };
+                    let client_args = client_args.iter().filter_map(|arg| arg.as_str().map(String::from)).collect();
+                    let cancelled = Arc::new(AtomicBool::new(false));
+                    if let Some(replaced) = queries.insert(id, Arc::clone(&cancelled)) {
+                        replaced.store(true, Ordering::Relaxed);
+                    }
+                    self.start(id, client, client_args, caller.clone(), frames.clone(), cancelled);
+                }
+            }
+        }
+        // Streams like --events only notice at their next frame
+        for cancelled in queries.values() {
+            cancelled.store(true, Ordering::Relaxed);
+        }
+    }
+
+    // Sends the frames of query `id` to `frames` until it ends or is
+    // cancelled, waiting while `frames` is full.
+    fn start(self: &Arc<Self>, id: u64, client: IpAddr, client_args: Vec<String>, caller: Caller, frames: Sender<Value>, cancelled: Arc<AtomicBool>) {
+        let endpoint = Arc::clone(self);
+        let trace_id = rand::thread_rng().gen();
+        tokio::task::spawn_blocking(move || {
+            let reply = move |frame: &Frame| {
+                let mut value = frame.to_json();
+                value["id"] = id.into();
+                !cancelled.load(Ordering::Relaxed) && frames.blocking_send(value).is_ok()
+            };
+            let args = match Cli::try_parse_remote(client_args) {
+                Ok(args) => args,
+                Err(e) => {
+                    reply(&Frame::Error(message!(InvalidRequest, e.to_string().trim_end())));
+                    reply(&Frame::EndOfResults { last: true });
+                    return;
+                }
+            };
+            if let Err(e) = endpoint.tenants.authorize(&caller, args.tenant.as_deref(), reads_only(&args)) {
+                reply(&Frame::Error(e));
+                reply(&Frame::EndOfResults { last: true });
+                return;
+            }
+            // Each query takes its turn as a request of --listen would
+            let attached = transport::stays_attached(&args);
+            let turn = if attached { None } else { endpoint.scheduler.wait_turn(client) };
+            if !attached && turn.is_none() {
+                reply(&Frame::Error(message!(TooManyRequests)));
+                reply(&Frame::EndOfResults { last: true });
+                return;
+            }
+            transport::forward(args, trace_id, &endpoint.local_address, endpoint.token.clone(), reply);
+        });
+    }
+}
+
+// The arguments for the tenant of the tenant parameter, if any.
+fn tenant_args(params: &HashMap<String, String>) -> Vec<&str> {
+    params.get("tenant").map_or_else(Vec::new, |tenant| vec!["--tenant", tenant])
+}
+
+async fn search(State(endpoint): State<Arc<Endpoint>>, ConnectInfo(address): ConnectInfo<SocketAddr>, headers: HeaderMap, Query(params): Query<HashMap<String, String>>) -> Reply {
+    match params.get("q") {
+        Some(term) => {
+            let mut client_args = tenant_args(&params);
+            // After "--" so terms starting with a dash aren't read as flags
+            client_args.extend(["--", term]);
+            endpoint.run(&headers, address.ip(), &client_args).await
+        }
+        None => error_reply(StatusCode::BAD_REQUEST, message!(InvalidRequest, "missing the q parameter")),
+    }
+}
+
+async fn files(State(endpoint): State<Arc<Endpoint>>, ConnectInfo(address): ConnectInfo<SocketAddr>, headers: HeaderMap, Query(params): Query<HashMap<String, String>>) -> Reply {
+    let mut client_args = tenant_args(&params);
+    client_args.push("--files");
+    endpoint.run(&headers, address.ip(), &client_args).await
+}
+
+async fn stats(State(endpoint): State<Arc<Endpoint>>, ConnectInfo(address): ConnectInfo<SocketAddr>, headers: HeaderMap, Query(params): Query<HashMap<String, String>>) -> Reply {
+    let mut client_args = tenant_args(&params);
+    client_args.push("--status");
+    endpoint.run(&headers, address.ip(), &client_args).await
+}
+
+async fn live(State(endpoint): State<Arc<Endpoint>>, ConnectInfo(address): ConnectInfo<SocketAddr>, headers: HeaderMap, Query(params): Query<HashMap<String, String>>, upgrade: WebSocketUpgrade) -> Response {
+    // Browsers can't set headers on a WebSocket
+    let given = bearer(&headers).or(params.get("token").map(String::as_str));
+    let Some(caller) = endpoint.tenants.caller_of_token(&endpoint.token, given.unwrap_or_default()) else {
+        return error_reply(StatusCode::UNAUTHORIZED, message!(AccessDenied)).into_response();
+    };
+    upgrade.on_upgrade(move |socket| endpoint.stream(socket, address.ip(), caller))
+}
+
+pub fn serve(address: &str, local_address: PathBuf, token: String, tenants: Arc<Tenants>, scheduler: Arc<Scheduler>) -> io::Result<()> {
+    let runtime = tokio::runtime::Builder::new_multi_thread().enable_io().build()?;
+    let listener = runtime.block_on(tokio::net::TcpListener::bind(address))?;
+    let app = Router::new()
+        .route("/search", get(search))
+        .route("/files", get(files))
+        .route("/stats", get(stats))
+        .route("/ws", get(live))
+        .with_state(Arc::new(Endpoint { local_address, token, tenants, scheduler }));
+    thread::spawn(move || {
+        runtime.block_on(async {
+            let _ = axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await;
+        })
+    });
+    Ok(())
+}
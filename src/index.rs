@@ -0,0 +1,155 @@
+use crate::{
+    content::Compression,
+    error::Error,
+    options::ByteSize,
+    read_loaded, read_root_config, read_tracked,
+    vfs::{OsVfs, Vfs, WatchGuard},
+    watchdog, Args, FoundLine, Indexer2, MatchSink,
+};
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+// The index of one directory in this process, for Rust tools that embed the
+// indexer rather than start a server and talk to it through a pipe:
+//   let index = hanoi_core::Index::build("/src/project")?;
+//   let _watch = index.watch()?;
+//   for found in index.search("fn main") {
+//       println!("{}:{}: {}", found.path.display(), found.line, found.text);
+//   }
+// The .hanoi of the root applies as it does for a server. Its additional
+// directories, shards and tenants are left out, they need servers.
+pub struct Index {
+    indexer: Arc<RwLock<Indexer2>>,
+    args: Args,
+}
+
+// A line with the term. Lines count from 1.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Match {
+    pub path: PathBuf,
+    pub line: usize,
+    pub text: String,
+}
+
+// How an Index reads the files of its root, as the flags of a server do.
+// The [options] of the root .hanoi add to these.
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+    // How the contents are kept in memory
+    pub compression: Compression,
+    // Files of more bytes are left out
+    pub max_file_size: Option<u64>,
+    pub follow_symlinks: bool,
+    // Also index the files in hidden directories
+    pub hidden: bool,
+    // Also index the text files inside zip and tar.gz archives
+    pub archives: bool,
+    // Only index the files git tracks
+    pub tracked_only: bool,
+}
+
+impl Options {
+    fn args(&self) -> Args {
+        Args {
+            compression: self.compression,
+            max_file_size: self.max_file_size.map(ByteSize),
+            follow_symlinks: self.follow_symlinks,
+            hidden: self.hidden,
+            archives: self.archives,
+            tracked_only: self.tracked_only,
+            ..Args::default()
+        }
+    }
+}
+
+// Keeps the index current until it is dropped, see Index::watch.
+pub struct Watch {
+    _guard: WatchGuard,
+}
+
+impl Index {
+    // Reads every file of `root` the filters let in.
+    pub fn build(root: impl Into<PathBuf>) -> Result<Index, Error> {
+        Index::build_with(root, &Options::default())
+    }
+
+    pub fn build_with(root: impl Into<PathBuf>, options: &Options) -> Result<Index, Error> {
+        let root = root.into();
+        OsVfs.metadata(&root).map_err(|e| Error::Read(root.clone(), e))?;
+        let mut args = options.args();
+        let indexer = local_index(Arc::new(OsVfs), &mut args, &root).ok_or_else(|| Error::Config(root.join(".hanoi")))?;
+        Ok(Index { indexer: Arc::new(RwLock::new(indexer)), args })
+    }
+
+    pub fn search(&self, term: &str) -> Vec<Match> {
+        self.find(term, false)
+    }
+
+    // Only the matches that are not part of a longer identifier, as --word.
+    pub fn search_word(&self, term: &str) -> Vec<Match> {
+        self.find(term, true)
+    }
+
+    // The indexed files, sorted.
+    pub fn files(&self) -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> = watchdog::read("indexer", &self.indexer).files.keys().map(|path| path.to_path_buf()).collect();
+        files.sort();
+        files
+    }
+
+    // Applies the changes to the files of the root from now on. Changes to
+    // the root .hanoi take a new Index.
+    pub fn watch(&self) -> Result<Watch, Error> {
+        let (root, vfs) = {
+            let indexer = watchdog::read("indexer", &self.indexer);
+            (indexer.root.clone(), Arc::clone(&indexer.vfs))
+        };
+        let indexer = Arc::clone(&self.indexer);
+        let guard = vfs
+            .watch(&root, Duration::from_millis(200), Box::new(move |events| {
+                if let Ok(events) = events {
+                    watchdog::write("indexer", &indexer).handle_events(events);
+                }
+            }))
+            .map_err(|e| Error::Watch(e.to_string()))?;
+        Ok(Watch { _guard: guard })
+    }
+
+    fn find(&self, term: &str, word: bool) -> Vec<Match> {
+        let mut args = self.args.clone();
+        args.term = Some(term.to_string());
+        args.word = word;
+        let mut matches = Vec::new();
+        read_loaded(&self.indexer).find_matches(&args, &mut matches);
+        matches
+    }
+}
+
+impl MatchSink for Vec<Match> {
+    fn found(&mut self, path: &Path, lines: Vec<FoundLine>) {
+        self.extend(lines.into_iter().map(|found| Match { path: path.to_path_buf(), line: found.line, text: found.text }));
+    }
+}
+
+// An index of `root` in `vfs` built in this process with the settings of
+// its .hanoi, None when its [options] can't be parsed.
+pub fn local_index(vfs: Arc<dyn Vfs>, args: &mut Args, root: &Path) -> Option<Indexer2> {
+    let root_config = read_root_config(vfs.as_ref(), root, args)?;
+    let mut indexer2 = Indexer2 {
+        compression: args.compression,
+        max_file_size: args.max_file_size.map(|size| size.0),
+        filters: root_config.filters,
+        follow_symlinks: args.follow_symlinks,
+        hidden: args.hidden,
+        archives: args.archives,
+        tracked: args.tracked_only.then(|| read_tracked(root)).flatten(),
+        vfs,
+        ..Default::default()
+    };
+    indexer2.build(root, Arc::default());
+    Some(indexer2)
+}
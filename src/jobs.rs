@@ -0,0 +1,168 @@
+use crate::{replies::ReplyStream, vfs::Vfs, watchdog, Indexer2};
+
+use tracing::{error, info};
+
+use std::{
+    env,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+// Jobs scan the index in chunks so queries can still get the lock between
+// chunks. Progress is saved after every chunk.
+const FILES_PER_CHUNK: usize = 256;
+
+// Every job keeps its state in its own directory so it can be resumed by the
+// next server if this one stops:
+//   terms    one search term per line
+//   paths    the files to scan, fixed when the job starts
+//   state    "next=<index>", "total=<count>" and "done=<bool>" lines
+//   results  "path:line: text" lines found so far
+pub fn jobs_dir(root_name: &Path) -> PathBuf {
+    let base = if cfg!(target_os = "windows") {
+        env::var_os("LOCALAPPDATA").map(PathBuf::from)
+    } else {
+        env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+    };
+    base.unwrap_or_else(env::temp_dir).join("hanoi").join("jobs").join(root_name)
+}
+
+struct JobState {
+    next: usize,
+    total: usize,
+    done: bool,
+}
+
+impl JobState {
+    fn read(vfs: &dyn Vfs, job_dir: &Path) -> io::Result<JobState> {
+        let mut state = JobState { next: 0, total: 0, done: false };
+        for line in vfs.read_to_string(&job_dir.join("state"))?.lines() {
+            match line.split_once('=') {
+                Some(("next", value)) => state.next = value.parse().unwrap_or(0),
+                Some(("total", value)) => state.total = value.parse().unwrap_or(0),
+                Some(("done", value)) => state.done = value == "true",
+                _ => {}
+            }
+        }
+        Ok(state)
+    }
+
+    fn write(&self, vfs: &dyn Vfs, job_dir: &Path) -> io::Result<()> {
+        // Write then rename so a crash never leaves a torn state file
+        let tmp_path = job_dir.join("state.tmp");
+        vfs.write(&tmp_path, format!("next={}\ntotal={}\ndone={}\n", self.next, self.total, self.done).as_bytes())?;
+        vfs.rename(&tmp_path, &job_dir.join("state"))
+    }
+}
+
+pub fn start_job(vfs: Arc<dyn Vfs>, jobs_dir: &Path, name: &str, terms: &[String], indexer: &Arc<RwLock<Indexer2>>) -> io::Result<String> {
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_secs());
+    let mut id = format!("{}-{}", name, secs);
+    let mut suffix = 1;
+    while vfs.metadata(&jobs_dir.join(&id)).is_ok() {
+        suffix += 1;
+        id = format!("{}-{}-{}", name, secs, suffix);
+    }
+    let job_dir = jobs_dir.join(&id);
+    vfs.create_dir_all(&job_dir)?;
+
+    let mut paths: Vec<String> = {
+        let mut locked = watchdog::write("indexer", indexer);
+        locked.load_pending();
+        locked.files.keys().map(|path| path.display().to_string()).collect()
+    };
+    paths.sort();
+    vfs.write(&job_dir.join("terms"), terms.join("\n").as_bytes())?;
+    vfs.write(&job_dir.join("paths"), paths.join("\n").as_bytes())?;
+    vfs.write(&job_dir.join("results"), b"")?;
+    JobState { next: 0, total: paths.len(), done: false }.write(vfs.as_ref(), &job_dir)?;
+
+    spawn_job(vfs, job_dir, Arc::clone(indexer));
+    Ok(id)
+}
+
+// Picks up the jobs a previous server left unfinished.
+pub fn resume_jobs(vfs: Arc<dyn Vfs>, jobs_dir: &Path, indexer: &Arc<RwLock<Indexer2>>) {
+    let Ok(job_dirs) = vfs.read_dir(jobs_dir) else {
+        return;
+    };
+    for job_dir in job_dirs {
+        if JobState::read(vfs.as_ref(), &job_dir).is_ok_and(|state| !state.done) {
+            info!("resuming job {}", job_dir.display());
+            spawn_job(Arc::clone(&vfs), job_dir, Arc::clone(indexer));
+        }
+    }
+}
+
+fn spawn_job(vfs: Arc<dyn Vfs>, job_dir: PathBuf, indexer: Arc<RwLock<Indexer2>>) {
+    thread::spawn(move || {
+        if let Err(e) = run_job(vfs.as_ref(), &job_dir, &indexer) {
+            error!("job {} failed: {}", job_dir.display(), e);
+        }
+    });
+}
+
+fn run_job(vfs: &dyn Vfs, job_dir: &Path, indexer: &RwLock<Indexer2>) -> io::Result<()> {
+    let terms_str = vfs.read_to_string(&job_dir.join("terms"))?;
+    let terms: Vec<&str> = terms_str.lines().filter(|term| !term.is_empty()).collect();
+    let paths_str = vfs.read_to_string(&job_dir.join("paths"))?;
+    let paths: Vec<&str> = paths_str.lines().collect();
+    let mut state = JobState::read(vfs, job_dir)?;
+    let results = job_dir.join("results");
+    // Jobs resumed at startup may run before any query read a lazy index
+    watchdog::write("indexer", indexer).load_pending();
+
+    while state.next < paths.len() {
+        let chunk_end = usize::min(state.next + FILES_PER_CHUNK, paths.len());
+        let mut found = String::new();
+        {
+            let indexer = watchdog::read("indexer", indexer);
+            for path in &paths[state.next..chunk_end] {
+                if let Some(text) = indexer.files.get(Path::new(path)).and_then(|file| file.content).and_then(|content| indexer.text(Path::new(path), content)) {
+                    if !terms.iter().any(|term| text.contains(term)) {
+                        continue;
+                    }
+                    for (line_index, line) in text.lines().enumerate() {
+                        if terms.iter().any(|term| line.contains(term)) {
+                            found.push_str(&format!("{}:{}: {}\n", path, line_index + 1, line));
+                        }
+                    }
+                }
+            }
+        }
+        vfs.append(&results, found.as_bytes())?;
+        state.next = chunk_end;
+        state.write(vfs, job_dir)?;
+    }
+    state.done = true;
+    state.write(vfs, job_dir)
+}
+
+pub fn job_status(vfs: &dyn Vfs, jobs_dir: &Path, id: &str, reader: &mut ReplyStream) {
+    match JobState::read(vfs, &jobs_dir.join(id)) {
+        Ok(state) => {
+            let percent = (state.next * 100).checked_div(state.total).unwrap_or(100);
+            let status = if state.done { "done" } else { "running" };
+            let _ = writeln!(reader, "job {}: {} ({} of {} files, {}%)", id, status, state.next, state.total, percent);
+        }
+        Err(_) => {
+            let _ = writeln!(reader, "job {}: not found", id);
+        }
+    }
+}
+
+pub fn job_results(vfs: &dyn Vfs, jobs_dir: &Path, id: &str, reader: &mut ReplyStream) {
+    match vfs.read_to_string(&jobs_dir.join(id).join("results")) {
+        Ok(results) => {
+            let _ = reader.write_all(results.as_bytes());
+        }
+        Err(_) => {
+            let _ = writeln!(reader, "job {}: not found", id);
+        }
+    }
+}
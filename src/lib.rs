@@ -0,0 +1,3217 @@
+mod archive;
+mod auth;
+mod blame;
+mod codec;
+mod compaction;
+mod config;
+mod content;
+mod error;
+mod estimate;
+mod events;
+mod filters;
+#[cfg(feature = "http")]
+mod http;
+mod index;
+mod jobs;
+mod logging;
+mod lsp;
+mod matcher;
+mod messages;
+mod oneshot;
+mod options;
+mod output;
+#[cfg(feature = "tree-sitter")]
+mod parsers;
+#[cfg(unix)]
+mod pager;
+mod preview;
+mod progress;
+mod protocol;
+mod publish;
+mod read_failures;
+mod replies;
+mod runtime;
+mod scheduler;
+mod shards;
+mod stats;
+mod stdio;
+mod supervisor;
+mod symbols;
+mod tags;
+mod tenants;
+mod trace;
+mod transport;
+#[cfg(unix)]
+mod tui;
+mod vcs;
+mod vfs;
+mod watchdog;
+mod words;
+
+// The API for tools that embed the indexer, see index.rs. Everything else
+// is the hanoi command line.
+pub use content::Compression;
+pub use error::Error;
+pub use index::{Index, Match, Options, Watch};
+
+use bincode::{
+    self,
+    Decode,
+    Encode
+};
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use interprocess::local_socket::{LocalSocketListener, LocalSocketStream};
+use memchr::{memchr, memchr_iter, memrchr};
+use notify::{
+    event::{Event, EventKind, ModifyKind, RenameMode},
+    Result,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::json;
+use rand::{self, Rng};
+use tracing::{debug, error, info, info_span, warn};
+
+use auth::Caller;
+use codec::{Bincode, Codec, Encoding};
+use compaction::CompactionStats;
+use content::{ContentId, ContentStore, IndexedFile};
+use logging::LogLevel;
+use matcher::Matcher;
+use messages::{message, Locale};
+use options::{parse_bool, parse_option, parse_percent, ByteSize, HumanDuration};
+use output::{OutputFormat, Printer, ResultKind, SortKey};
+use preview::Previewer;
+use progress::Progress;
+use protocol::{decompress_frames, read_frame, write_frame, Frame, Header, JsonRequest, Protocol, MAX_MESSAGE_LEN, PROTOCOL_VERSION, RELEASE};
+use publish::{send_results, Publications};
+use read_failures::{read_file, stat_file, ReadFailure, ReadFailures};
+use replies::{ReplyStream, SharedWriter};
+use scheduler::Scheduler;
+use shards::Shard;
+use stats::{resident_memory, ServerStats};
+use supervisor::{supervise, ChildServers};
+use symbols::{extract_symbols, SymbolIndex};
+use tenants::Tenants;
+use trace::{QueryCounts, Trace, TraceReport};
+use transport::Transport;
+use vcs::Tracked;
+use vfs::{OsVfs, Vfs, VfsMetadata};
+use watchdog::Locked;
+use words::WordChars;
+
+use std::{
+    borrow::Cow,
+    cmp::{self},
+    collections::hash_map::DefaultHasher,
+    collections::{HashMap, HashSet},
+    fmt,
+    hash::Hasher,
+    io::{self, BufRead, BufReader, ErrorKind, IsTerminal, Read, Write},
+    iter,
+    mem::{self},
+    net::TcpStream,
+    path::{Path, PathBuf},
+    str::FromStr,
+    process::{self, Child, Command, ExitCode},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc,
+        Arc, Condvar, Mutex, RwLock, RwLockReadGuard,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+    thread,
+};
+
+#[derive(Encode, Decode, Serialize, Deserialize, ValueEnum, Clone, Default)]
+enum OperatingMode {
+    Server,
+    #[default]
+    Client,
+    // Predict the memory an index of --root would take without building it
+    Estimate,
+    // Show how the filters of --root apply without building an index
+    Filters,
+    // Tell why a path is indexed or not
+    Explain,
+    // Answer an editor over stdin and stdout, see lsp.rs
+    Lsp,
+}
+
+#[derive(Clone)]
+struct Filter {
+    // Directory of the .hanoi the filter comes from, relative to the root.
+    // The filter only applies below it.
+    base: PathBuf,
+    should_include: bool,
+    should_start_with: bool,
+    should_end_with: bool,
+    only_dir: bool,
+    pattern: String,
+}
+
+// The line of the .hanoi the filter was parsed from.
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let exclude = if self.should_include { "" } else { "!" };
+        let start = if self.should_start_with { "" } else { "*" };
+        let dir = if self.only_dir { "/" } else { "" };
+        let end = if self.should_end_with { "" } else { "*" };
+        write!(f, "{}{}{}{}{}", exclude, start, self.pattern, dir, end)
+    }
+}
+
+struct WorkQueue {
+    paths: Vec<PathBuf>,
+    has_stopped: bool,
+}
+
+// The command line: a subcommand taking the flags of Args, or those flags
+// alone for a search, as before there were subcommands. Only the Args go
+// to the server.
+#[derive(Parser)]
+#[command(args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<CliCommand>,
+
+    #[command(flatten)]
+    args: Args,
+}
+
+#[derive(Subcommand)]
+enum CliCommand {
+    #[command(about = "Index --root and answer clients")]
+    Serve(Args),
+    #[command(about = "Search the index of the current directory for a term")]
+    Search(Args),
+    #[command(about = "List the indexed files")]
+    Files(Args),
+    #[command(about = "Show what the server for the current directory is doing")]
+    Status(Args),
+    #[command(about = "Stop the server for the current directory")]
+    Stop(Args),
+    #[command(about = "Rebuild the index from disk")]
+    Reindex(Args),
+    #[command(about = "Predict the memory an index of --root would take")]
+    Estimate(Args),
+    #[command(about = "Search as you type, and open results in $EDITOR")]
+    Tui(Args),
+    #[command(about = "Keep printing the matches that appear or go away as files change")]
+    Watch(Args),
+    #[command(about = "Write the symbols of the index to a ctags file")]
+    Tags(Args),
+    #[command(about = "List the filters of --root, or with --check what they index")]
+    Filters(Args),
+    #[command(about = "Serve the Language Server Protocol over stdin and stdout")]
+    Lsp(Args),
+    #[command(about = "Tell which filters decide whether a path is indexed")]
+    Explain {
+        path: String,
+        #[command(flatten)]
+        args: Args,
+    },
+}
+
+impl Cli {
+    // Parses the arguments of a remote client, which the HANOI_ variables
+    // of this server have no say in.
+    fn try_parse_remote(client_args: impl IntoIterator<Item = String>) -> std::result::Result<Args, clap::Error> {
+        let command = Cli::command().mut_args(|arg| arg.env(None)).mut_subcommands(|subcommand| subcommand.mut_args(|arg| arg.env(None)));
+        let matches = command.try_get_matches_from(std::iter::once(String::from("hanoi")).chain(client_args))?;
+        Ok(Cli::from_arg_matches(&matches)?.into_args())
+    }
+
+    // The Args of the command line, with the first -e as the term when there
+    // is no other and it isn't read from stdin.
+    fn into_args(self) -> Args {
+        let mut args = self.command_args();
+        if args.term.is_none() && !args.query_from_stdin && !args.terms.is_empty() {
+            args.term = Some(args.terms.remove(0));
+        }
+        args
+    }
+
+    // The Args the subcommand stands for.
+    fn command_args(self) -> Args {
+        let Some(command) = self.command else {
+            return self.args;
+        };
+        match command {
+            CliCommand::Serve(args) => Args { mode: OperatingMode::Server, ..args },
+            CliCommand::Search(args) => args,
+            CliCommand::Files(args) => Args { files: true, ..args },
+            CliCommand::Status(args) => Args { status: true, ..args },
+            CliCommand::Stop(args) => Args { stop: true, ..args },
+            CliCommand::Reindex(args) => Args { reindex: true, ..args },
+            CliCommand::Estimate(args) => Args { mode: OperatingMode::Estimate, ..args },
+            CliCommand::Tui(args) => Args { tui: true, ..args },
+            CliCommand::Watch(args) => Args { watch: true, ..args },
+            CliCommand::Tags(args) => Args { tags: true, ..args },
+            CliCommand::Filters(args) => Args { mode: OperatingMode::Filters, ..args },
+            CliCommand::Lsp(args) => Args { mode: OperatingMode::Lsp, ..args },
+            CliCommand::Explain { path, args } => Args { mode: OperatingMode::Explain, explain: Some(path), ..args },
+        }
+    }
+}
+
+// Settings can also come from HANOI_<NAME> environment variables, such as
+// HANOI_MAX_MEMORY=2G or HANOI_HIDDEN=true, for deployments without a
+// wrapper script. Flags on the command line take precedence.
+#[derive(Encode, Decode, Serialize, Deserialize, Parser, Clone, Default)]
+struct Args {
+    // Set by the subcommands, still accepted for scripts that predate them
+    #[clap(value_enum, default_value_t = OperatingMode::Client)]
+    #[arg(long, hide = true)]
+    mode: OperatingMode,
+
+    // Server: the directories to index. The server of the first one also
+    // answers for the others, which are served by this process too rather
+    // than by child servers.
+    #[arg(long, env = "HANOI_ROOT")]
+    root: Vec<String>,
+
+    #[clap(default_value_t = false)]
+    #[arg(long)]
+    files: bool,
+
+    #[clap(default_value_t = false)]
+    #[arg(long)]
+    long: bool,
+
+    // Client: print nothing and stop at the first result, for the exit code
+    #[clap(default_value_t = false)]
+    #[arg(long, short)]
+    quiet: bool,
+
+    // Client: print the path once above its matches rather than on every
+    // line
+    #[clap(default_value_t = false)]
+    #[arg(long, env = "HANOI_HEADING")]
+    heading: bool,
+
+    // Client: print every file with a match once instead of the matches,
+    // however many servers have it:
+    //   hanoi -l -0 old_name | xargs -0 sed -i 's/old_name/new_name/g'
+    #[clap(default_value_t = false)]
+    #[arg(long, short = 'l')]
+    files_with_matches: bool,
+
+    // Client: end the paths of --files and -l with NUL rather than a
+    // newline, for xargs -0. Paths are sent unsplit, so newlines in them
+    // survive.
+    #[clap(default_value_t = false)]
+    #[arg(long, short = '0')]
+    null: bool,
+
+    // More terms to search for, a line matches if it has any of them. Files
+    // are scanned once for all of them, see matcher.rs:
+    //   hanoi old_name -e OldName -e OLD_NAME
+    // Without another term the first -e becomes the term (Cli::into_args),
+    // so "hanoi -e foo -e bar" searches for both.
+    #[arg(long = "term", short = 'e')]
+    terms: Vec<String>,
+
+    // Only matches that are not part of a longer identifier, by the rules of
+    // the language of each file, see words.rs
+    #[clap(default_value_t = false)]
+    #[arg(long, short)]
+    word: bool,
+
+    #[clap(default_value_t = false)]
+    #[arg(long, short)]
+    main_server: bool,
+
+    #[clap(value_enum, default_value_t = Compression::None)]
+    #[arg(long, env = "HANOI_COMPRESSION")]
+    compression: Compression,
+
+    #[arg(long, env = "HANOI_MAX_FILE_SIZE")]
+    max_file_size: Option<ByteSize>,
+
+    // Most memory the file contents may take. Beyond it the least recently
+    // matched ones are dropped and read from disk when a query needs them.
+    #[arg(long, env = "HANOI_MAX_MEMORY")]
+    max_memory: Option<ByteSize>,
+
+    // Refuse to start the server if more than this percentage of the files
+    // could not be read.
+    #[arg(long, env = "HANOI_MAX_UNREADABLE_PERCENT", value_parser = parse_percent)]
+    max_unreadable_percent: Option<f64>,
+
+    // Drop clients that stop sending or reading for this long (default 10s)
+    #[arg(long, env = "HANOI_PIPE_TIMEOUT")]
+    pipe_timeout: Option<HumanDuration>,
+
+    // Symlinked files and directories are skipped unless this is set
+    #[clap(default_value_t = false)]
+    #[arg(long, env = "HANOI_FOLLOW_SYMLINKS")]
+    follow_symlinks: bool,
+
+    // Also index dotfiles and dot directories, which are skipped by default
+    #[clap(default_value_t = false)]
+    #[arg(long, env = "HANOI_HIDDEN")]
+    hidden: bool,
+
+    // Server: only index the files git tracks, unless a filter leaves them
+    // out. tracked_only = true in [options]
+    #[clap(default_value_t = false)]
+    #[arg(long = "vcs", env = "HANOI_VCS")]
+    tracked_only: bool,
+
+    // Only collect paths while building and read the contents on the first
+    // query that needs them
+    #[clap(default_value_t = false)]
+    #[arg(long, env = "HANOI_LAZY")]
+    lazy: bool,
+
+    // Split the root between this many child servers, each indexing the
+    // files whose path hashes to it. Results are merged for the client.
+    #[arg(long, env = "HANOI_SHARDS")]
+    shards: Option<usize>,
+
+    // Set on the child servers started for --shards
+    #[arg(long)]
+    shard: Option<Shard>,
+
+    // Server: a name for this server, so it can run next to other servers
+    // for the same or overlapping directories. Clients only reach it with
+    // --server.
+    #[arg(long, env = "HANOI_NAME")]
+    name: Option<String>,
+
+    // Client: talk to the server started with this --name instead of the
+    // one for the current directory
+    #[arg(long, env = "HANOI_SERVER")]
+    server: Option<String>,
+
+    // Index the text files inside .zip, .tar.gz and .tgz archives that pass
+    // the filters, reported as "archive.zip!inner/path"
+    #[clap(default_value_t = false)]
+    #[arg(long, env = "HANOI_ARCHIVES")]
+    archives: bool,
+
+    // Activities running longer than this dump the server's thread and lock
+    // states (default 30s)
+    #[arg(long, env = "HANOI_HANG_TIMEOUT")]
+    hang_timeout: Option<HumanDuration>,
+
+    // How long watcher events are collected before the index is updated
+    #[arg(long, env = "HANOI_WATCH_DEBOUNCE")]
+    watch_debounce: Option<HumanDuration>,
+
+    #[clap(default_value_t = false)]
+    #[arg(long)]
+    status: bool,
+
+    // Client: shut down the server for the current directory and its child
+    // servers
+    #[clap(default_value_t = false)]
+    #[arg(long)]
+    stop: bool,
+
+    // Client: start a server for the project of the current directory when
+    // there is none, and query it once it's ready
+    #[clap(default_value_t = false)]
+    #[arg(long, env = "HANOI_AUTO_START")]
+    auto_start: bool,
+
+    // Client: list every server running for this user, shards and child
+    // servers included. --daemon picks one of them for other requests.
+    #[clap(default_value_t = false)]
+    #[arg(long)]
+    servers: bool,
+
+    // Client: only check that the server answers
+    #[clap(default_value_t = false)]
+    #[arg(long)]
+    ping: bool,
+
+    // Client: print the release and protocol version of the server next to
+    // the ones of this client
+    #[clap(default_value_t = false)]
+    #[arg(long)]
+    server_version: bool,
+
+    // Client: refuse to send the request to a server of another release
+    // instead of only warning about it
+    #[clap(default_value_t = false)]
+    #[arg(long)]
+    strict_version: bool,
+
+    // Server: print indexing progress every second while building.
+    // Client: with --stats, break the time down by server and stage.
+    #[clap(default_value_t = false)]
+    #[arg(long)]
+    verbose: bool,
+
+    // Print what the servers searched and how long each took once the
+    // results are in, see trace.rs
+    #[clap(default_value_t = false)]
+    #[arg(long)]
+    stats: bool,
+
+    // Give memory left behind by removed files back to the allocator. Also
+    // done automatically once enough of it piles up.
+    #[clap(default_value_t = false)]
+    #[arg(long)]
+    compact: bool,
+
+    // Keep printing the server's lifecycle events (index started or
+    // completed, rescans, compactions, child servers exiting) as JSON lines
+    #[clap(default_value_t = false)]
+    #[arg(long)]
+    events: bool,
+
+    // Rebuild the index from disk, e.g. after the watcher missed changes
+    #[clap(default_value_t = false)]
+    #[arg(long)]
+    reindex: bool,
+
+    // With --reindex, build the new index while the old one keeps answering
+    // and swap them once it is done
+    #[clap(default_value_t = false)]
+    #[arg(long)]
+    background: bool,
+
+    // Files or directories the user is working on, sent by editor
+    // integrations. Replaces the previous focus; --clear-focus resets it.
+    #[arg(long, num_args = 1..)]
+    focus: Vec<String>,
+
+    #[clap(default_value_t = false)]
+    #[arg(long)]
+    clear_focus: bool,
+
+    // Stop applying watcher events during a bulk operation (checkouts,
+    // search and replace). The server resumes by itself after the given time
+    // (default 10m) in case the caller never does.
+    #[arg(long, num_args = 0..=1, default_missing_value = "10m")]
+    suspend_watch: Option<HumanDuration>,
+
+    // Apply the changes made while suspended by rescanning the given paths
+    // and the ones the watcher reported
+    #[arg(long, num_args = 0..)]
+    resume_watch: Option<Vec<String>>,
+
+    // Publish the query under a name so other clients can --subscribe to
+    // its results
+    #[arg(long)]
+    publish: Option<String>,
+
+    // Attach to a published query and keep receiving its results as the
+    // index changes
+    #[arg(long)]
+    subscribe: Option<String>,
+
+    // Run a saved search from the [saved_searches] section of .hanoi in the
+    // background and print the job id
+    #[arg(long)]
+    job_start: Option<String>,
+
+    #[arg(long)]
+    job_status: Option<String>,
+
+    #[arg(long)]
+    job_results: Option<String>,
+
+    // Announce every result with its position and fields, e.g.
+    // "match 3 of 40, file src/foo.rs, line 12: ..."
+    #[clap(default_value_t = false)]
+    #[arg(long, env = "HANOI_VERBOSE_LABELS")]
+    verbose_labels: bool,
+
+    // Escape non-ASCII characters in the output
+    #[clap(default_value_t = false)]
+    #[arg(long, env = "HANOI_ASCII")]
+    ascii: bool,
+
+    // Client: print one JSON object per line, see output.rs
+    #[clap(default_value_t = false)]
+    #[arg(long, env = "HANOI_JSON")]
+    json: bool,
+
+    // Client: vimgrep prints matches as path:line:col:text relative to the
+    // working directory, see output.rs. Server: matches are sent with their
+    // offsets, as for --json.
+    #[clap(value_enum, default_value_t = OutputFormat::Plain)]
+    #[arg(long, env = "HANOI_FORMAT")]
+    format: OutputFormat,
+
+    // Client: print candidates for fzf and nothing else on stdout, matches
+    // as "path:line:col:text" and files relative to the working directory:
+    //   hanoi --files --fzf | fzf --preview 'cat {}'
+    //   fzf --disabled --delimiter : --bind "change:reload:printf %s {q} | hanoi --fzf --query-from-stdin"
+    // Errors and warnings go to stderr. Server: as for --format vimgrep.
+    #[clap(default_value_t = false)]
+    #[arg(long)]
+    fzf: bool,
+
+    // Client: read the term from stdin rather than the command line, for
+    // reload commands that can't know whether the query starts with '-'. It
+    // replaces any term of the command line, terms given with -e are still
+    // searched for. An empty term prints nothing.
+    #[clap(default_value_t = false)]
+    #[arg(long)]
+    query_from_stdin: bool,
+
+    // Print the given number of lines (default 2) around every match,
+    // syntax highlighted on terminals with inline image support
+    #[arg(long, env = "HANOI_PREVIEW", num_args = 0..=1, default_missing_value = "2")]
+    preview: Option<usize>,
+
+    // Client: the command the tui opens results with, followed by +line and
+    // the path. $VISUAL or $EDITOR by default.
+    #[arg(long, env = "HANOI_EDITOR")]
+    editor: Option<String>,
+
+    // Client: the order of results, merged across the servers. Servers sort
+    // their own by path when this isn't given.
+    #[arg(long, env = "HANOI_SORT")]
+    sort: Option<SortKey>,
+
+    // Client: with --sort, the last path or the newest, largest or most
+    // matched file first
+    #[clap(default_value_t = false)]
+    #[arg(long, env = "HANOI_REVERSE")]
+    reverse: bool,
+
+    // Client: print results straight to the terminal rather than through
+    // $PAGER, less by default
+    #[clap(default_value_t = false)]
+    #[arg(long, env = "HANOI_NO_PAGER")]
+    no_pager: bool,
+
+    // Screen reader friendly preset: --ascii --verbose-labels
+    #[clap(default_value_t = false)]
+    #[arg(long, env = "HANOI_ACCESSIBLE")]
+    accessible: bool,
+
+    // Client: who last changed the line of each match and when, from git,
+    // see blame.rs
+    #[clap(default_value_t = false)]
+    #[arg(long, env = "HANOI_BLAME")]
+    blame: bool,
+
+    #[arg(long)]
+    symbol: Option<String>,
+
+    // The symbols of one file in the order they are defined, indented by
+    // how deep they are nested
+    #[arg(long)]
+    outline: Option<String>,
+
+    // Only matches on lines that define a symbol whose name has the term
+    #[clap(default_value_t = false)]
+    #[arg(long)]
+    definitions_only: bool,
+
+    // Check every file with a match against the disk before reporting it,
+    // so the printed lines are current. Small files are always read again.
+    #[clap(default_value_t = false)]
+    #[arg(long)]
+    verify_fresh: bool,
+
+    // Query a namespace hosted by a shared daemon, as "user/label". The
+    // daemon is found from --daemon or the current directory.
+    #[arg(long)]
+    tenant: Option<String>,
+
+    #[arg(long, env = "HANOI_DAEMON")]
+    daemon: Option<String>,
+
+    // Client: answer the query without a server, indexing the current
+    // directory for it alone. For scripts and CI.
+    #[clap(default_value_t = false)]
+    #[arg(long)]
+    no_daemon: bool,
+
+    // Server: also accept clients over TCP on "addr:port". They must
+    // present the server's token with --token. Traffic is not encrypted, so
+    // only listen on trusted networks.
+    #[arg(long, env = "HANOI_LISTEN")]
+    listen: Option<String>,
+
+    // Server: how many requests of --listen and --http clients are answered
+    // at once (default 8), and how many of them for one client address
+    // (default 2). The others wait their turn, taken by each client in
+    // rotation.
+    #[arg(long, env = "HANOI_MAX_REMOTE_QUERIES")]
+    max_remote_queries: Option<usize>,
+
+    #[arg(long, env = "HANOI_MAX_CLIENT_QUERIES")]
+    max_client_queries: Option<usize>,
+
+    // Server: also answer HTTP on "addr:port", see http.rs. Only in builds
+    // with the http feature.
+    #[arg(long, env = "HANOI_HTTP")]
+    http: Option<String>,
+
+    // Server: run in the background with the output going to a log file,
+    // printing the process id
+    #[clap(default_value_t = false)]
+    #[arg(long)]
+    detach: bool,
+
+    // Client: answer an editor plugin with JSON-RPC over stdin and stdout
+    // until stdin closes, see stdio.rs
+    #[clap(default_value_t = false)]
+    #[arg(long)]
+    stdio: bool,
+
+    // Server: how much to log, see logging.rs
+    #[clap(value_enum, default_value_t = LogLevel::Info)]
+    #[arg(long, env = "HANOI_LOG_LEVEL")]
+    log_level: LogLevel,
+
+    // Server: append the log to this file instead of printing it
+    #[arg(long, env = "HANOI_LOG_FILE")]
+    log_file: Option<String>,
+
+    // Client: query the server listening on "addr:port" instead of the one
+    // for the current directory
+    #[arg(long, env = "HANOI_CONNECT")]
+    connect: Option<String>,
+
+    // Client: the token of the --connect server, found in the file it
+    // names when it starts listening
+    #[arg(long, env = "HANOI_TOKEN")]
+    token: Option<String>,
+
+    // Client: how to talk to the --connect server, json being the format
+    // documented in protocol.rs for tools written in other languages
+    #[clap(value_enum, default_value_t = Protocol::Bincode)]
+    #[arg(long, env = "HANOI_PROTOCOL")]
+    protocol: Protocol,
+
+    // Client: how the request and its replies are encoded, for integrations
+    // that can't read bincode, see codec.rs
+    #[clap(value_enum, default_value_t = Encoding::Bincode)]
+    #[arg(long, env = "HANOI_CODEC")]
+    codec: Encoding,
+
+    // Client: have the replies zstd compressed, which pays off for big
+    // results over --connect. Ignored with --protocol=json.
+    #[clap(default_value_t = false)]
+    #[arg(long, env = "HANOI_COMPRESS")]
+    compress: bool,
+
+    // Client: keep the connection open for more requests, answered as they
+    // come with frames tagged by request, see Frame::Tagged. For editor
+    // plugins talking to the local socket themselves.
+    #[clap(default_value_t = false)]
+    #[arg(long)]
+    session: bool,
+
+    // With the filters subcommand, walk --root and tell for every file
+    // whether it would be indexed and which filter decided, see filters.rs
+    #[clap(default_value_t = false)]
+    #[arg(long)]
+    check: bool,
+
+    // Client: search as you type, see tui.rs
+    #[clap(default_value_t = false)]
+    #[arg(long, hide = true)]
+    tui: bool,
+
+    // Client: stay attached to the query and print the matches that appear
+    // (+) or go away (-) whenever the index changes, see publish.rs
+    #[clap(default_value_t = false)]
+    #[arg(long, hide = true)]
+    watch: bool,
+
+    // Client: write every symbol of the index to a ctags file, see tags.rs
+    #[clap(default_value_t = false)]
+    #[arg(long, hide = true)]
+    tags: bool,
+
+    // Client: the file tags writes, "tags" by default, - for stdout
+    #[arg(long)]
+    output: Option<String>,
+
+    // The path of the explain subcommand
+    #[arg(skip)]
+    explain: Option<String>,
+
+    term: Option<String>,
+}
+
+// What the client exits with when nothing matched and when something went
+// wrong, as grep does. It exits with 0 when there were results.
+const EXIT_NO_RESULTS: u8 = 1;
+const EXIT_ERROR: u8 = 2;
+
+// How many files a thread of find searches before the results so far are
+// sent. Fewer than this are searched without threads.
+const FIND_FILES_PER_THREAD: usize = 256;
+
+// How long --auto-start waits for the server it started to take
+// connections, and how often it asks whether the index is ready.
+const AUTO_START_TIMEOUT: Duration = Duration::from_secs(10);
+const AUTO_START_POLL: Duration = Duration::from_millis(200);
+
+fn write_to_pipe<T: Encode + Serialize, S: Transport>(reader: &mut BufReader<S>, v: T, codec: &impl Codec) -> std::result::Result<(), Error> {
+    let encoded: Vec<u8> = codec.encode(&v)?;
+    reader.get_mut().write_all(&(encoded.len() as u32).to_le_bytes())?;
+    reader.get_mut().write_all(encoded.as_slice())?;
+    Ok(())
+}
+
+fn read_from_pipe<T: Decode + DeserializeOwned, S: Transport>(reader: &mut BufReader<S>, codec: &impl Codec) -> std::result::Result<T, Error> {
+    let mut struct_len_buffer = [0; 4];
+    reader.read_exact(&mut struct_len_buffer)?;
+    let struct_len = u32::from_le_bytes(struct_len_buffer) as usize;
+    if struct_len > MAX_MESSAGE_LEN {
+        return Err(Error::TooLarge(struct_len));
+    }
+    let mut buffer = vec![0u8; struct_len];
+    reader.read_exact(&mut buffer)?;
+    codec.decode(buffer.as_slice())
+}
+
+// The header is always bincode, the Args are encoded with their --codec.
+fn write_request<S: Transport>(reader: &mut BufReader<S>, args: &Args, trace_id: u64, token: String) -> std::result::Result<(), Error> {
+    let header = Header { encoding: args.codec, ..Header::new(args.main_server, trace_id, token) };
+    write_to_pipe(reader, header, &Bincode)?;
+    write_to_pipe(reader, args.clone(), &args.codec)
+}
+
+// Reads the header and Args of a request. Requests that can't be read, are
+// from another protocol version or lack the server's token are answered
+// with an error and None is returned, and so are the ones for the version
+// only.
+fn read_request(reader: &mut BufReader<LocalSocketStream>, token: &str) -> Option<(Header, Args)> {
+    let (reply, main_server, encoding) = match read_from_pipe::<Header, _>(reader, &Bincode) {
+        // A peer that connected only to see whether the server runs
+        Err(Error::Pipe(e)) if e.kind() == ErrorKind::UnexpectedEof => return None,
+        Err(e) => (e.frame(), true, Encoding::Bincode),
+        Ok(header) if header.version_only => (Frame::Version { release: String::from(RELEASE), protocol: PROTOCOL_VERSION }, true, Encoding::Bincode),
+        Ok(header) if header.version != PROTOCOL_VERSION => (Frame::Error(message!(ProtocolMismatch, header.version, PROTOCOL_VERSION)), header.main_server, Encoding::Bincode),
+        // The token is checked once all of the request is read, so the
+        // client isn't cut off while it is still sending
+        Ok(header) => match read_from_pipe(reader, &header.encoding) {
+            Ok(_) if !auth::matches(token, &header.token) => (Frame::Error(message!(AccessDenied)), header.main_server, header.encoding),
+            Ok(args) => {
+                debug!("request {:016x}: {:?}, tag {}", header.trace_id, header.encoding, header.tag);
+                return Some((header, args));
+            }
+            // Args of another release may not decode at all
+            Err(_) if !header.release.is_empty() && header.release != RELEASE => (Frame::Error(message!(ReleaseMismatch, header.release, RELEASE)), header.main_server, header.encoding),
+            Err(e) => (e.frame(), header.main_server, header.encoding),
+        },
+    };
+    debug!("answered a request without reading on: {:?}", reply);
+    let _ = write_frame(reader.get_mut(), &encoding, &reply);
+    let _ = write_frame(reader.get_mut(), &encoding, &Frame::EndOfResults { last: main_server });
+    None
+}
+
+// Whether a request only reads the index: a query for a term, the files,
+// a symbol, an outline or tags, or the status. The only requests tenant
+// users may send, anything else could stop, rebuild or steer the server of
+// the tenant.
+fn reads_only(args: &Args) -> bool {
+    let reads = args.term.is_some() || args.files || args.symbol.is_some() || args.outline.is_some() || args.tags || args.status;
+    let changes = args.stop
+        || args.reindex
+        || args.compact
+        || !args.focus.is_empty()
+        || args.clear_focus
+        || args.suspend_watch.is_some()
+        || args.resume_watch.is_some()
+        || args.publish.is_some()
+        || args.subscribe.is_some()
+        || args.events
+        || args.watch
+        || args.job_start.is_some()
+        || args.job_status.is_some()
+        || args.job_results.is_some();
+    reads && !changes
+}
+
+fn convert_path(path: &Path) -> PathBuf {
+    let mut hasher = DefaultHasher::new();
+    let new_path = PathBuf::from(path.display().to_string().replace("\\", "/"));
+    hasher.write(new_path.display().to_string().as_bytes());
+
+    PathBuf::from(hasher.finish().to_string())
+}
+
+fn should_index(filters: &[Filter], path: &Path, root: &Path, shard: Option<Shard>, hidden: bool, tracked: Option<&Tracked>) -> bool {
+    filter_file(filters, path, root, tracked) && in_shard(shard, path, root) && (hidden || !in_hidden_dir(path, root))
+}
+
+// With tracked_only the files git tracks are indexed unless a filter leaves
+// them out, and no other file is.
+fn filter_file(filters: &[Filter], path: &Path, root: &Path, tracked: Option<&Tracked>) -> bool {
+    match tracked {
+        Some(tracked) => tracked.contains(path) && deciding_filter(filters, path, root).is_none_or(|filter| filter.should_include),
+        None => filter_path(filters, path, root, false),
+    }
+}
+
+// The files git tracks below `root`. None when git can't list them, the
+// filters then decide alone.
+fn read_tracked(root: &Path) -> Option<Arc<Tracked>> {
+    match Tracked::read(root) {
+        Ok(tracked) => Some(Arc::new(tracked)),
+        Err(e) => {
+            warn!("{}", message!(NotTracked, root.display(), e));
+            None
+        }
+    }
+}
+
+fn in_shard(shard: Option<Shard>, path: &Path, root: &Path) -> bool {
+    shard.is_none_or(|shard| shard.contains(path.strip_prefix(root).unwrap_or(path)))
+}
+
+fn filter_path(filters: &[Filter], path: &Path, root: &Path, is_dir: bool) -> bool {
+    // Ignore files by default, but not dir
+    deciding_filter(filters, path, root).map_or(is_dir, |filter| filter.should_include)
+}
+
+// The last filter that matches `path`, which decides whether it is indexed.
+fn deciding_filter<'a>(filters: &'a [Filter], path: &Path, root: &Path) -> Option<&'a Filter> {
+    matching_filters(filters, path, root).last()
+}
+
+// The filters that match `path`, in the order they are applied.
+fn matching_filters<'a>(filters: &'a [Filter], path: &Path, root: &Path) -> impl Iterator<Item = &'a Filter> {
+    let root_rel_path = path.strip_prefix(root).ok().map(Path::to_path_buf);
+    filters.iter().filter(move |filter| {
+        let Some(rel_path) = root_rel_path.as_ref().and_then(|root_rel_path| root_rel_path.strip_prefix(&filter.base).ok()) else {
+            return false;
+        };
+        let rel_path_str = rel_path.display().to_string();
+        let pattern = filter.pattern.as_str();
+        // if filter.only_dir && !is_dir {
+        //     continue;
+        // }
+        if filter.should_start_with && filter.should_end_with {
+            pattern == rel_path_str
+        } else if filter.should_start_with || filter.should_end_with {
+            (filter.should_start_with && rel_path_str.starts_with(pattern)) || (filter.should_end_with && rel_path_str.ends_with(pattern))
+        } else {
+            rel_path_str.contains(pattern)
+        }
+    })
+}
+
+#[derive(Hash, PartialEq, Eq)]
+enum DirId {
+    Inode(u64, u64),
+    // Used where the platform has no inode numbers
+    Path(PathBuf),
+}
+
+struct WalkState {
+    follow_symlinks: bool,
+    hidden: bool,
+    // With tracked_only, directories without a tracked file are skipped
+    tracked: Option<Arc<Tracked>>,
+    // Directories already walked, so symlink cycles end
+    visited: HashSet<DirId>,
+}
+
+impl WalkState {
+    fn new(follow_symlinks: bool, hidden: bool) -> WalkState {
+        WalkState {
+            follow_symlinks,
+            hidden,
+            tracked: None,
+            visited: HashSet::new(),
+        }
+    }
+
+    fn tracking(mut self, tracked: Option<Arc<Tracked>>) -> WalkState {
+        self.tracked = tracked;
+        self
+    }
+
+    // Returns false if the directory was walked before.
+    fn enter(&mut self, vfs: &dyn Vfs, dir: &Path) -> bool {
+        let id = match vfs.metadata(dir).ok().and_then(|metadata| metadata.file_id) {
+            Some((device, inode)) => DirId::Inode(device, inode),
+            None => DirId::Path(vfs.canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf())),
+        };
+        self.visited.insert(id)
+    }
+}
+
+// Dotfiles and dot directories such as .git or .cache
+fn is_hidden(path: &Path) -> bool {
+    path.file_name().is_some_and(|name| name.to_string_lossy().starts_with('.'))
+}
+
+fn in_hidden_dir(path: &Path, root: &Path) -> bool {
+    path.strip_prefix(root).is_ok_and(|relative_path| relative_path.ancestors().any(is_hidden))
+}
+
+// The [filters] of a .hanoi below the root apply to its directory only and
+// win over the ones of its parents, like nested .gitignore files. Filters
+// stay sorted by depth so deeper ones are applied last.
+fn read_nested_filters(vfs: &dyn Vfs, dir: &Path, root: &Path, filters: &mut Vec<Filter>) {
+    let Ok(base) = dir.strip_prefix(root) else {
+        return;
+    };
+    if base.as_os_str().is_empty() {
+        return;
+    }
+    filters.retain(|filter| filter.base != base);
+    if let Ok(config_str) = vfs.read_to_string(&dir.join(".hanoi")) {
+        let mut section = "";
+        let start = filters.len();
+        for line in config_str.lines().map(str::trim) {
+            if line.starts_with("[") && line.ends_with("]") {
+                section = &line[1..line.len() - 1];
+            } else if section == "filters" && !line.is_empty() && !line.starts_with("#") {
+                parse_filter(line, filters);
+            }
+        }
+        for filter in &mut filters[start..] {
+            filter.base = base.to_path_buf();
+        }
+        filters.sort_by_key(|filter| filter.base.components().count());
+    }
+}
+
+// `cb` gets every file with the filters that apply at that point of the walk.
+fn visit_dirs(vfs: &dyn Vfs, dir: &Path, cb: &mut impl FnMut(&Path, &[Filter]), root: &Path, filters: &mut Vec<Filter>, walk: &mut WalkState) -> io::Result<()> {
+    if vfs.metadata(dir)?.is_dir && walk.enter(vfs, dir) {
+        read_nested_filters(vfs, dir, root, filters);
+        for path in vfs.read_dir(dir)? {
+            if !walk.hidden && is_hidden(&path) {
+                continue;
+            }
+            let is_symlink = vfs.symlink_metadata(&path).is_ok_and(|metadata| metadata.is_symlink);
+            if is_symlink && !walk.follow_symlinks {
+                continue;
+            }
+            if vfs.metadata(&path).is_ok_and(|metadata| metadata.is_dir) {
+                if walk.tracked.as_ref().is_some_and(|tracked| !tracked.has_below(&path)) {
+                    continue;
+                }
+                if filter_path(filters, path.as_path(), root, true) {
+                    visit_dirs(vfs, &path, cb, root, filters, walk)?;
+                }
+            } else {
+                cb(&path, filters);
+            }
+        }
+    }
+    Ok(())
+}
+
+// Formats as "YYYY-MM-DD HH:MM:SS" in UTC.
+fn format_system_time(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_secs());
+    let (days, day_secs) = (secs / 86400, secs % 86400);
+    // Civil-from-days, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02}", year, month, day, day_secs / 3600, day_secs / 60 % 60, day_secs % 60)
+}
+
+// Reads a file for the index. Archives yield one entry per text file they
+// contain when `archives` is set.
+fn load_file(vfs: &dyn Vfs, path: &Path, max_file_size: Option<u64>, archives: bool) -> std::result::Result<Vec<(PathBuf, String, VfsMetadata)>, ReadFailure> {
+    if !(archives && archive::is_archive(path)) {
+        let (text, metadata) = read_file(vfs, path, max_file_size)?;
+        return Ok(vec![(path.to_path_buf(), text, metadata)]);
+    }
+    let archive_metadata = vfs.metadata(path).map_err(|_| ReadFailure::Io)?;
+    if max_file_size.is_some_and(|max_file_size| archive_metadata.len > max_file_size) {
+        return Err(ReadFailure::TooLarge);
+    }
+    let entries = archive::read_archive(vfs, path).map_err(|_| ReadFailure::Io)?;
+    Ok(entries
+        .into_iter()
+        .map(|(entry_path, text)| {
+            let metadata = VfsMetadata {
+                is_dir: false,
+                is_file: true,
+                is_symlink: false,
+                len: text.len() as u64,
+                modified: archive_metadata.modified,
+                file_id: None,
+            };
+            (entry_path, text, metadata)
+        })
+        .collect())
+}
+
+struct ScopeTime {
+    start: Instant,
+}
+
+impl Default for ScopeTime {
+    fn default() -> ScopeTime {
+        ScopeTime {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Drop for ScopeTime {
+    fn drop(&mut self) {
+        let end_time = Instant::now();
+        let elapsed_time = end_time.duration_since(self.start);
+        let elapsed_ms = elapsed_time.as_secs() * 1000 + elapsed_time.subsec_millis() as u64;
+        info!("{} ms", elapsed_ms);
+    }
+}
+
+struct Indexer2 {
+    root: PathBuf,
+    // Keys are shared with the symbol index
+    files: HashMap<Arc<Path>, IndexedFile>,
+    contents: ContentStore,
+    compactions: CompactionStats,
+    compression: Compression,
+    max_file_size: Option<u64>,
+    max_memory: Option<u64>,
+    // Evicted contents that matched a query since the last restore_matched
+    rematched: Mutex<Vec<(ContentId, String)>>,
+    read_failures: ReadFailures,
+    symbols: SymbolIndex,
+    filters: Vec<Filter>,
+    follow_symlinks: bool,
+    hidden: bool,
+    archives: bool,
+    lazy: bool,
+    shard: Option<Shard>,
+    tracked: Option<Arc<Tracked>>,
+    // Files and directories open in the user's editor. Their results are
+    // returned first and their watcher events handled first.
+    focus: Vec<PathBuf>,
+    suspension: Option<Suspension>,
+    // Paths the watcher reported while an index is built in the background,
+    // rescanned in it once it replaces this one
+    rebuilding: Option<HashSet<PathBuf>>,
+    vfs: Arc<dyn Vfs>,
+    last_change: Option<SystemTime>,
+}
+
+// Watcher events are only recorded while a bulk operation runs, see
+// --suspend-watch.
+struct Suspension {
+    until: Instant,
+    touched: HashSet<PathBuf>,
+}
+
+impl Suspension {
+    // Beyond this many touched paths the whole root is rescanned instead
+    const MAX_TOUCHED: usize = 4096;
+}
+
+impl Default for Indexer2 {
+    fn default() -> Indexer2 {
+        Indexer2 {
+            root: PathBuf::new(),
+            files: HashMap::new(),
+            contents: ContentStore::default(),
+            compactions: CompactionStats::default(),
+            compression: Compression::default(),
+            max_file_size: None,
+            max_memory: None,
+            rematched: Mutex::new(Vec::new()),
+            read_failures: ReadFailures::default(),
+            symbols: SymbolIndex::default(),
+            filters: Vec::new(),
+            follow_symlinks: false,
+            hidden: false,
+            archives: false,
+            lazy: false,
+            shard: None,
+            tracked: None,
+            focus: Vec::new(),
+            suspension: None,
+            rebuilding: None,
+            vfs: Arc::new(OsVfs),
+            last_change: None,
+        }
+    }
+}
+
+// A line of a file with matches, and where they are in it. Lines count
+// from 1.
+struct FoundLine {
+    line: usize,
+    text: String,
+    // The byte offset of the line in the file
+    offset: usize,
+    // The start and length of every match in `text`
+    positions: Vec<(usize, usize)>,
+}
+
+// What find got out of one file.
+enum Searched {
+    // Its content isn't loaded or can't be read
+    Skipped,
+    // The lines with matches, only the first one for -l
+    Found(Vec<FoundLine>),
+}
+
+// Receives the matches of Indexer2::find a file at a time, in the order
+// they are sent.
+trait MatchSink {
+    fn found(&mut self, path: &Path, lines: Vec<FoundLine>);
+}
+
+// Writes matches as the lines servers send: "path:line: text", one JSON
+// object with the offsets of every match for the clients that print
+// columns, see output.rs, or the path alone for -l.
+struct MatchLines<'a, W> {
+    args: &'a Args,
+    writer: &'a mut W,
+}
+
+impl<W: Write> MatchSink for MatchLines<'_, W> {
+    fn found(&mut self, path: &Path, lines: Vec<FoundLine>) {
+        let args = self.args;
+        // The client prints the column of the first match for these
+        let offsets = args.json || args.fzf || args.format == OutputFormat::Vimgrep;
+        let mut out = Vec::new();
+        for found in &lines {
+            if args.files_with_matches {
+                // A JSON string, so a newline in the path doesn't split it,
+                // see Printer::files_with_matches
+                let _ = writeln!(out, "{}", json!(path.display().to_string()));
+            } else if offsets {
+                let submatches: Vec<_> = found
+                    .positions
+                    .iter()
+                    .map(|(start, len)| json!({ "start": start, "end": start + len, "absolute_start": found.offset + start, "absolute_end": found.offset + start + len }))
+                    .collect();
+                let _ = writeln!(out, "{}", json!({ "path": path.display().to_string(), "line": found.line, "text": found.text, "absolute_offset": found.offset, "submatches": submatches }));
+            } else {
+                let _ = writeln!(out, "{}:{}: {}", path.display(), found.line, found.text);
+            }
+        }
+        let _ = self.writer.write_all(&out);
+    }
+}
+
+#[derive(Clone, Copy)]
+struct LoadOptions {
+    compression: Compression,
+    max_file_size: Option<u64>,
+    archives: bool,
+    lazy: bool,
+}
+
+// What a worker thread read, merged into the index once it is done.
+#[derive(Default)]
+struct Loaded {
+    files: HashMap<Arc<Path>, IndexedFile>,
+    contents: ContentStore,
+    read_failures: ReadFailures,
+    symbols: SymbolIndex,
+}
+
+impl Loaded {
+    // Returns the number of bytes read.
+    fn load(&mut self, vfs: &dyn Vfs, path: &Path, options: LoadOptions) -> u64 {
+        if options.lazy {
+            match stat_file(vfs, path, options.max_file_size) {
+                Ok(metadata) => {
+                    self.files.insert(Arc::from(path), IndexedFile::unread(&metadata));
+                }
+                Err(failure) => self.read_failures.record(path, failure),
+            }
+            return 0;
+        }
+        match load_file(vfs, path, options.max_file_size, options.archives) {
+            Ok(entries) => {
+                let mut bytes = 0;
+                for (entry_path, file_str, metadata) in entries {
+                    bytes += metadata.len;
+                    let entry_path: Arc<Path> = Arc::from(entry_path);
+                    self.symbols.set(Arc::clone(&entry_path), extract_symbols(&entry_path, &file_str));
+                    let content = self.contents.insert(file_str, options.compression);
+                    self.files.insert(entry_path, IndexedFile::new(content, &metadata));
+                }
+                bytes
+            }
+            Err(failure) => {
+                self.read_failures.record(path, failure);
+                0
+            }
+        }
+    }
+}
+
+impl Indexer2 {
+    fn load_options(&self) -> LoadOptions {
+        LoadOptions {
+            compression: self.compression,
+            max_file_size: self.max_file_size,
+            archives: self.archives,
+            lazy: self.lazy,
+        }
+    }
+
+    fn merge(&mut self, loaded: Loaded) {
+        let ids = self.contents.merge(loaded.contents);
+        self.files.extend(loaded.files.into_iter().map(|(path, mut file)| {
+            file.content = file.content.map(|content| ids[&content]);
+            (path, file)
+        }));
+        self.read_failures.extend(loaded.read_failures);
+        self.symbols.extend(loaded.symbols);
+    }
+
+    // Reads the files a lazy build skipped. The first query pays for it.
+    fn has_pending(&self) -> bool {
+        self.files.values().any(|file| file.content.is_none())
+    }
+
+    fn load_pending(&mut self) {
+        let pending: Vec<Arc<Path>> = self.files.iter().filter(|(_, file)| file.content.is_none()).map(|(path, _)| Arc::clone(path)).collect();
+        if pending.is_empty() {
+            return;
+        }
+        let _scope_time = ScopeTime::default();
+        events::emit("contents_loading", json!({ "root": self.root, "files": pending.len() }));
+        let options = LoadOptions { lazy: false, ..self.load_options() };
+        let thread_count = 4;
+        let vfs = self.vfs.as_ref();
+        let loaded: Vec<Loaded> = thread::scope(|scope| {
+            let handles: Vec<_> = pending
+                .chunks(pending.len().div_ceil(thread_count))
+                .map(|chunk| scope.spawn(move || {
+                    let mut loaded = Loaded::default();
+                    for path in chunk {
+                        loaded.load(vfs, path, options);
+                    }
+                    loaded
+                }))
+                .collect();
+            handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+        });
+        // Archives come back as their entries
+        for path in &pending {
+            self.files.remove(path);
+        }
+        for loaded in loaded {
+            self.merge(loaded);
+        }
+        self.enforce_memory_budget();
+    }
+
+    fn build(&mut self, path: &Path, progress: Arc<Progress>) {
+        self.root = PathBuf::from(path);
+        let started = Instant::now();
+        events::emit("index_started", json!({ "root": self.root, "shard": self.shard.map(|shard| shard.to_string()) }));
+        let mut filters = mem::take(&mut self.filters);
+        let shard = self.shard;
+        let tracked = self.tracked.clone();
+        let options = self.load_options();
+
+        let mut handles = vec![];
+        let thread_count = 4;
+        let files_per_thread = 1024;
+        let work_queue = WorkQueue {
+            paths: Vec::with_capacity(thread_count * files_per_thread),
+            has_stopped: false,
+        };
+        let pair = Arc::new((Mutex::new(work_queue), Condvar::new()));
+        for _ in 0..thread_count {
+            let pair2 = Arc::clone(&pair);
+            let vfs = Arc::clone(&self.vfs);
+            let progress = Arc::clone(&progress);
+            let handle = thread::spawn(move || {
+                let mut loaded = Loaded::default();
+                let mut paths: Vec<PathBuf> = Vec::with_capacity(files_per_thread);
+                let (lock, cvar) = &*pair2;
+                loop {
+                    let mut work_queue = lock.lock().unwrap();
+                    while !work_queue.has_stopped && work_queue.paths.is_empty() {
+                        work_queue = cvar.wait(work_queue).unwrap();
+                    }
+                    let path_in_queue_count = work_queue.paths.len();
+                    if path_in_queue_count > 0 {
+                        let file_count = cmp::min(path_in_queue_count, files_per_thread);
+                        for _i in 0..file_count {
+                            paths.push(work_queue.paths.pop().unwrap());
+                        }
+                    }
+                    let should_stopped = work_queue.has_stopped && work_queue.paths.is_empty();
+                    drop(work_queue);
+                    for path in paths.drain(..) {
+                        progress.process(loaded.load(vfs.as_ref(), &path, options));
+                    }
+                    if should_stopped {
+                        break;
+                    }
+                }
+                loaded
+            });
+            handles.push(handle);
+        }
+
+        let mut paths = Vec::<PathBuf>::with_capacity(thread_count * files_per_thread);
+        let mut load_files = |file_path: &Path, filters: &[Filter]| {
+            if !filter_file(filters, file_path, path, tracked.as_deref()) || !in_shard(shard, file_path, path) {
+                return;
+            }
+
+            paths.push(file_path.to_path_buf());
+            progress.discover(1);
+            if paths.len() > files_per_thread {
+                let (lock, cvar) = &*pair;
+                let mut work_queue = lock.lock().unwrap();
+                for _i in 0..files_per_thread {
+                    work_queue.paths.push(paths.pop().unwrap());
+                }
+                cvar.notify_all();
+            }
+        };
+
+        let _ = visit_dirs(self.vfs.as_ref(), path, &mut load_files, self.root.as_path(), &mut filters, &mut WalkState::new(self.follow_symlinks, self.hidden).tracking(self.tracked.clone()));
+        self.filters = filters;
+        progress.finish_walk();
+
+        {
+            let (lock, cvar) = &*pair;
+            let mut work_queue = lock.lock().unwrap();
+            if !paths.is_empty() {
+                work_queue.paths.append(&mut paths);
+            }
+            work_queue.has_stopped = true;
+            // We notify the condvar that the value has changed.
+            cvar.notify_all();
+        }
+        for handle in handles {
+            self.merge(handle.join().unwrap());
+        }
+        self.enforce_memory_budget();
+        progress.finish();
+        info!("Indexer2: Done building ({} files, {} unique, {} stored)", self.files.len(), self.contents.len(), ByteSize(self.contents.stored_len() as u64));
+        events::emit("index_completed", json!({
+            "root": self.root,
+            "shard": self.shard.map(|shard| shard.to_string()),
+            "files": self.files.len(),
+            "unreadable": self.read_failures.total().total(),
+            "seconds": started.elapsed().as_secs_f64(),
+        }));
+    }
+
+    // The text to search in `path` for --verify-fresh: None if it is gone,
+    // the disk contents if it changed or is small enough to read again.
+    fn fresh_text<'a>(&self, path: &Path, file: &IndexedFile, indexed: Cow<'a, str>) -> Option<Cow<'a, str>> {
+        const REREAD_MAX: u64 = 64 << 10;
+        let archive = archive::archive_of(path);
+        let disk_path = archive.as_deref().unwrap_or(path);
+        let metadata = self.vfs.metadata(disk_path).ok()?;
+        // Entry sizes are the sizes of the entries, not of the archive
+        let unchanged = metadata.modified == file.modified && (archive.is_some() || metadata.len == file.size);
+        if unchanged && metadata.len > REREAD_MAX {
+            return Some(indexed);
+        }
+        let entries = load_file(self.vfs.as_ref(), disk_path, None, archive.is_some()).ok()?;
+        entries.into_iter().find(|(entry_path, _, _)| entry_path == path).map(|(_, text, _)| Cow::Owned(text))
+    }
+
+    // The indexed text of `path`. Contents evicted to stay under
+    // --max-memory are read from disk again.
+    fn text(&self, path: &Path, content: ContentId) -> Option<Cow<'_, str>> {
+        if let Some(text) = self.contents.text(content) {
+            return Some(text);
+        }
+        let archive = archive::archive_of(path);
+        let disk_path = archive.as_deref().unwrap_or(path);
+        let entries = load_file(self.vfs.as_ref(), disk_path, self.max_file_size, archive.is_some()).ok()?;
+        entries.into_iter().find(|(entry_path, _, _)| entry_path == path).map(|(_, text, _)| Cow::Owned(text))
+    }
+
+    // Matched contents are the last to be evicted, and evicted ones that
+    // matched are kept again by restore_matched.
+    fn matched(&self, content: ContentId, text: &str) {
+        self.contents.touch(content);
+        if self.contents.is_evicted(content) {
+            self.rematched.lock().unwrap().push((content, text.to_string()));
+        }
+    }
+
+    fn has_rematched(&self) -> bool {
+        !self.rematched.lock().unwrap().is_empty()
+    }
+
+    fn restore_matched(&mut self) {
+        let rematched = mem::take(self.rematched.get_mut().unwrap());
+        for (content, text) in rematched {
+            self.contents.restore(content, text, self.compression);
+        }
+        self.enforce_memory_budget();
+    }
+
+    // Evicts the least recently matched contents once they take more than
+    // --max-memory. The files stay indexed, and the contents of focused
+    // files are never evicted.
+    fn enforce_memory_budget(&mut self) {
+        let Some(max_memory) = self.max_memory else {
+            return;
+        };
+        let keep: HashSet<ContentId> = self.files.iter().filter(|(path, _)| self.is_focused(path)).filter_map(|(_, file)| file.content).collect();
+        let evicted = self.contents.evict(max_memory as usize, &keep);
+        if evicted > 0 {
+            info!("evicted {} contents of {} to stay under {}", evicted, self.root.display(), ByteSize(max_memory));
+            events::emit("evicted", json!({ "root": self.root, "contents": evicted }));
+        }
+    }
+
+    fn find(&self, args: &Args, reader: &mut impl Write) -> QueryCounts {
+        self.find_matches(args, &mut MatchLines { args, writer: reader })
+    }
+
+    fn find_matches(&self, args: &Args, sink: &mut impl MatchSink) -> QueryCounts {
+        let mut counts = QueryCounts::default();
+        let Some(term) = args.term.as_deref() else {
+            return counts;
+        };
+        let terms: Vec<&str> = iter::once(term).chain(args.terms.iter().map(String::as_str)).collect();
+        let Some(matcher) = Matcher::new(&terms) else {
+            warn!("too many terms to search for at once: {}", terms.len());
+            return counts;
+        };
+        let (terms, matcher) = (terms.as_slice(), &matcher);
+        let keys = self.sorted_keys(args);
+        tracing::Span::current().record("files", keys.len());
+        // The files are searched a batch at a time on every core, and the
+        // matches of a batch sent in the order of its files before the next
+        // one is searched. With --sort matches all of them are held back
+        // until every file is.
+        let thread_count = thread::available_parallelism().map_or(1, |count| count.get());
+        let mut by_matches = Vec::new();
+        for batch in keys.chunks(thread_count * FIND_FILES_PER_THREAD) {
+            let searched: Vec<Searched> = if batch.len() <= FIND_FILES_PER_THREAD {
+                batch.iter().map(|key| self.search_file(args, terms, matcher, key)).collect()
+            } else {
+                thread::scope(|scope| {
+                    let handles: Vec<_> = batch
+                        .chunks(batch.len().div_ceil(thread_count))
+                        .map(|chunk| scope.spawn(move || chunk.iter().map(|key| self.search_file(args, terms, matcher, key)).collect::<Vec<_>>()))
+                        .collect();
+                    handles.into_iter().flat_map(|handle| handle.join().unwrap()).collect()
+                })
+            };
+            for (key, searched) in batch.iter().zip(searched) {
+                let found = match searched {
+                    Searched::Skipped => {
+                        counts.skipped += 1;
+                        continue;
+                    }
+                    Searched::Found(found) => found,
+                };
+                counts.scanned += 1;
+                counts.matches += found.len() as u64;
+                if found.is_empty() {
+                    continue;
+                }
+                if args.sort == Some(SortKey::Matches) {
+                    by_matches.push((key, found));
+                } else {
+                    sink.found(key, found);
+                }
+            }
+        }
+        by_matches.sort_by_key(|(_, found)| found.len());
+        if args.reverse {
+            by_matches.reverse();
+        }
+        for (key, found) in by_matches {
+            sink.found(key, found);
+        }
+        counts
+    }
+
+    // The lines of one file with matches, run on the threads of find. Only
+    // the lines `matcher` finds one of the terms in are looked at.
+    fn search_file(&self, args: &Args, terms: &[&str], matcher: &Matcher, key: &Path) -> Searched {
+        let file = &self.files[key];
+        let Some(content) = file.content else {
+            return Searched::Skipped;
+        };
+        let Some(mut value) = self.text(key, content) else {
+            return Searched::Skipped;
+        };
+        let mut found = Vec::new();
+        if !matcher.is_match(value.as_bytes()) {
+            return Searched::Found(found);
+        }
+        self.matched(content, &value);
+        if args.verify_fresh {
+            match self.fresh_text(key, file, value) {
+                Some(text) => value = text,
+                None => return Searched::Found(found),
+            }
+        }
+        let word_chars = WordChars::for_path(key);
+        let definitions = self.symbols.of(key);
+        let bytes = value.as_bytes();
+        // Lines are counted up to the line of each match, with the line
+        // breaks of str::lines
+        let (mut line_index, mut counted_to, mut next_line) = (0, 0, 0);
+        for (start, _) in matcher.find_iter(bytes) {
+            if start < next_line {
+                continue;
+            }
+            let line_start = memrchr(b'\n', &bytes[..start]).map_or(0, |newline| newline + 1);
+            // An empty term matches after the last line break too
+            if line_start == bytes.len() {
+                break;
+            }
+            let line_end = memchr(b'\n', &bytes[start..]).map_or(bytes.len(), |newline| start + newline);
+            line_index += memchr_iter(b'\n', &bytes[counted_to..line_start]).count();
+            counted_to = line_start;
+            next_line = line_end + 1;
+            let line = &value[line_start..line_end];
+            let line = line.strip_suffix('\r').unwrap_or(line);
+            if args.definitions_only && !definitions.iter().any(|symbol| symbol.line == line_index + 1 && terms.iter().any(|term| symbol.name.contains(term))) {
+                continue;
+            }
+            let positions: Vec<(usize, usize)> = matcher.find_iter(line.as_bytes()).filter(|&(pos, len)| !args.word || word_chars.is_whole_word(line, pos, len)).collect();
+            if positions.is_empty() {
+                continue;
+            }
+            found.push(FoundLine { line: line_index + 1, text: line.to_string(), offset: line_start, positions });
+            // One line tells the file has a match
+            if args.files_with_matches {
+                break;
+            }
+        }
+        Searched::Found(found)
+    }
+
+    // The indexed paths in the order their results are sent: by path, or by
+    // the --sort key, with the ones of --focus first unless --sort is given.
+    fn sorted_keys(&self, args: &Args) -> Vec<&Arc<Path>> {
+        let mut keys: Vec<&Arc<Path>> = self.files.keys().collect();
+        keys.sort();
+        match args.sort {
+            Some(SortKey::Mtime) => keys.sort_by_key(|key| self.files[*key].modified),
+            Some(SortKey::Size) => keys.sort_by_key(|key| self.files[*key].size),
+            Some(SortKey::Path | SortKey::Matches) => {}
+            None => keys.sort_by_key(|key| !self.is_focused(key)),
+        }
+        // Files with as many matches are reversed with them in find
+        if args.reverse && args.sort.is_some_and(|sort| sort != SortKey::Matches) {
+            keys.reverse();
+        }
+        keys
+    }
+
+    fn list_symbols(&self, reader: &mut ReplyStream) {
+        for (path, symbol) in self.symbols.all() {
+            let _ = writeln!(reader, "{}:{}: {} {}", path.display(), symbol.line, symbol.kind, symbol.name);
+        }
+    }
+
+    fn outline(&self, path: &Path, reader: &mut ReplyStream) {
+        for symbol in self.symbols.of(path) {
+            let _ = writeln!(reader, "{}:{}: {}{} {}", path.display(), symbol.line, "  ".repeat(symbol.depth), symbol.kind, symbol.name);
+        }
+    }
+
+    fn find_symbol(&self, name: &str, reader: &mut ReplyStream) {
+        for (path, symbol) in self.symbols.find(name) {
+            let _ = writeln!(reader, "{}:{}: {} {}", path.display(), symbol.line, symbol.kind, symbol.name);
+        }
+    }
+
+    fn list_files(&self, args: &Args, reader: &mut ReplyStream) {
+        for key in self.sorted_keys(args) {
+            let file = &self.files[key];
+            let line = if args.long && args.json {
+                let lines = file.content.and_then(|content| self.text(key, content)).map(|text| text.lines().count());
+                let mtime = file.modified.map(|time| time.duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_secs()));
+                json!({ "path": key.display().to_string(), "size": file.size, "mtime": mtime, "extension": IndexedFile::extension(key), "lines": lines }).to_string()
+            } else if args.long {
+                let modified = file.modified.map_or_else(|| String::from("-"), format_system_time);
+                format!("{:>10} {} {:<6} {}", file.size, modified, IndexedFile::extension(key), key.display())
+            } else {
+                key.display().to_string()
+            };
+            // Sent as one line even if the path has newlines in it
+            if args.null {
+                let _ = reader.send(Frame::ResultLine(line));
+                continue;
+            }
+            let _ = reader.write_all(line.as_bytes());
+            let _ = reader.write(b"\n");
+        }
+    }
+
+    fn unreadable_percent(&self) -> f64 {
+        let failed = self.read_failures.total().total();
+        let attempted = self.files.len() + failed;
+        if attempted == 0 {
+            return 0.0;
+        }
+        failed as f64 * 100.0 / attempted as f64
+    }
+
+    // The stats only the index knows about.
+    fn stats(&self) -> ServerStats {
+        ServerStats {
+            root: self.root.display().to_string(),
+            pid: process::id(),
+            uptime_secs: 0,
+            files: self.files.len() as u64,
+            total_bytes: self.files.values().map(|file| file.size).sum(),
+            index_bytes: (self.used() + self.contents.stored_len()) as u64,
+            resident_bytes: resident_memory(),
+            last_change: self.last_change.map(|time| time.duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_secs())),
+            children: Vec::new(),
+        }
+    }
+
+    fn status(&self, reader: &mut ReplyStream) {
+        let failures = self.read_failures.total();
+        let _ = writeln!(reader, "contents: {} unique, {} stored, {} evicted", self.contents.len(), ByteSize(self.contents.stored_len() as u64), self.contents.evicted_len());
+        let _ = writeln!(reader, "unreadable files: {} ({:.1}%; {})", failures.total(), self.unreadable_percent(), failures);
+        let mut dirs: Vec<_> = self.read_failures.dirs.iter().collect();
+        dirs.sort_by_key(|(_, counts)| std::cmp::Reverse(counts.total()));
+        for (dir, counts) in dirs {
+            let _ = writeln!(reader, "  {}: {} ({})", dir.display(), counts.total(), counts);
+        }
+        let last = self.compactions.last.map_or_else(|| String::from("never"), format_system_time);
+        let _ = writeln!(reader, "compaction: {} runs, {} reclaimed, last {}, {} reclaimable", self.compactions.runs, ByteSize(self.compactions.reclaimed), last, ByteSize(self.slack() as u64));
+    }
+
+    fn slack(&self) -> usize {
+        compaction::slack(&self.files) + self.contents.slack() + self.symbols.slack() + compaction::slack(&self.read_failures.dirs)
+    }
+
+    fn used(&self) -> usize {
+        compaction::used(&self.files) + self.contents.used() + self.symbols.used() + compaction::used(&self.read_failures.dirs)
+    }
+
+    fn compact(&mut self) -> usize {
+        let reclaimed = compaction::shrink(&mut self.files) + self.contents.shrink() + self.symbols.shrink() + compaction::shrink(&mut self.read_failures.dirs);
+        self.compactions.record(reclaimed);
+        events::emit("compacted", json!({ "root": self.root, "reclaimed_bytes": reclaimed }));
+        reclaimed
+    }
+
+    fn handle_compact_request(&mut self, reader: &mut ReplyStream) {
+        let reclaimed = self.compact();
+        let _ = writeln!(reader, "compacted {}: {} reclaimed", self.root.display(), ByteSize(reclaimed as u64));
+    }
+
+    fn indexes(&self, path: &Path) -> bool {
+        should_index(&self.filters, path, &self.root, self.shard, self.hidden, self.tracked.as_deref())
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        self.vfs.metadata(path).is_ok_and(|metadata| metadata.is_file)
+    }
+
+    fn reindex(&mut self, reader: &mut ReplyStream) {
+        self.files.clear();
+        self.contents = ContentStore::default();
+        self.read_failures = ReadFailures::default();
+        self.symbols = SymbolIndex::default();
+        let root = self.root.clone();
+        self.build(&root, Arc::default());
+        let _ = writeln!(reader, "reindexed {}: {} files", self.root.display(), self.files.len());
+    }
+
+    // An empty index with the settings of this one. The filters of nested
+    // .hanoi files are read again when it is built.
+    fn fresh(&self) -> Indexer2 {
+        Indexer2 {
+            root: self.root.clone(),
+            compression: self.compression,
+            max_file_size: self.max_file_size,
+            max_memory: self.max_memory,
+            filters: self.filters.iter().filter(|filter| filter.base.as_os_str().is_empty()).cloned().collect(),
+            follow_symlinks: self.follow_symlinks,
+            hidden: self.hidden,
+            archives: self.archives,
+            lazy: self.lazy,
+            shard: self.shard,
+            tracked: self.tracked.clone(),
+            vfs: Arc::clone(&self.vfs),
+            ..Indexer2::default()
+        }
+    }
+
+    // Takes over the index built from fresh() and returns the old one, to be
+    // dropped once the lock is released. The focus, a suspension and the
+    // root filters, which may have been reloaded meanwhile, carry over.
+    fn replace(&mut self, mut fresh: Indexer2) -> Indexer2 {
+        let touched = self.rebuilding.take().unwrap_or_default();
+        fresh.filters.retain(|filter| !filter.base.as_os_str().is_empty());
+        fresh.filters.splice(0..0, self.filters.iter().filter(|filter| filter.base.as_os_str().is_empty()).cloned());
+        fresh.focus = mem::take(&mut self.focus);
+        fresh.suspension = self.suspension.take();
+        fresh.compactions = mem::take(&mut self.compactions);
+        fresh.last_change = self.last_change;
+        let old = mem::replace(self, fresh);
+        if touched.len() > Suspension::MAX_TOUCHED || touched.contains(&self.root) {
+            let root = self.root.clone();
+            self.rescan(&root);
+        } else {
+            for path in &touched {
+                self.rescan(path);
+            }
+        }
+        self.enforce_memory_budget();
+        old
+    }
+
+    fn is_focused(&self, path: &Path) -> bool {
+        self.focus.iter().any(|focus| path.starts_with(focus))
+    }
+
+    fn set_focus(&mut self, args: &Args, reader: &mut ReplyStream) {
+        self.focus = args.focus
+            .iter()
+            .map(PathBuf::from)
+            .filter(|path| path.starts_with(&self.root))
+            .collect();
+        let _ = writeln!(reader, "focused {} paths in {}", self.focus.len(), self.root.display());
+    }
+
+    fn suspend_watch(&mut self, duration: Duration, reader: &mut ReplyStream) {
+        let until = Instant::now() + duration;
+        match self.suspension.as_mut() {
+            Some(suspension) => suspension.until = until,
+            None => self.suspension = Some(Suspension { until, touched: HashSet::new() }),
+        }
+        let _ = writeln!(reader, "suspended watching {} for {}", self.root.display(), HumanDuration(duration));
+    }
+
+    // Rescans the paths the caller touched and the ones the watcher reported
+    // while suspended. Returns how many paths were rescanned.
+    fn resume_watch(&mut self, paths: &[String]) -> usize {
+        let mut touched = self.suspension.take().map(|suspension| suspension.touched).unwrap_or_default();
+        touched.extend(paths.iter().map(PathBuf::from).filter(|path| path.starts_with(&self.root)));
+        if touched.len() > Suspension::MAX_TOUCHED || touched.contains(&self.root) {
+            touched = HashSet::from([self.root.clone()]);
+        }
+        for path in &touched {
+            self.rescan(path);
+        }
+        touched.len()
+    }
+
+    fn handle_resume_request(&mut self, paths: &[String], reader: &mut ReplyStream) {
+        let rescanned = self.resume_watch(paths);
+        let _ = writeln!(reader, "resumed watching {}: rescanned {} paths", self.root.display(), rescanned);
+    }
+
+    fn suspension_expired(&self) -> bool {
+        self.suspension.as_ref().is_some_and(|suspension| Instant::now() >= suspension.until)
+    }
+
+    fn resume_if_expired(&mut self) {
+        if self.suspension_expired() {
+            info!("watch suspension of {} expired", self.root.display());
+            self.resume_watch(&[]);
+        }
+    }
+
+    fn handle_events(&mut self, mut events: Vec<Event>) {
+        self.last_change = Some(SystemTime::now());
+        self.resume_if_expired();
+        if let Some(touched) = self.rebuilding.as_mut() {
+            for event in &events {
+                if event.paths.is_empty() {
+                    touched.insert(self.root.clone());
+                }
+                touched.extend(event.paths.iter().cloned());
+            }
+        }
+        if let Some(suspension) = self.suspension.as_mut() {
+            for event in &events {
+                if event.paths.is_empty() {
+                    suspension.touched.insert(self.root.clone());
+                }
+                suspension.touched.extend(event.paths.iter().cloned());
+            }
+            return;
+        }
+        events.sort_by_key(|event| !event.paths.iter().any(|path| self.is_focused(path)));
+        for event in &events {
+            self.handle_event(event);
+        }
+        self.enforce_memory_budget();
+        if compaction::needs_compaction(self.slack(), self.used()) {
+            let reclaimed = self.compact();
+            info!("compacted {}: {} reclaimed", self.root.display(), ByteSize(reclaimed as u64));
+        }
+    }
+
+    // Applies the [filters] of a changed root .hanoi. Files they now leave
+    // out are dropped and the ones they now let in are read.
+    fn reload_filters(&mut self, filters: Vec<Filter>) {
+        self.filters.retain(|filter| !filter.base.as_os_str().is_empty());
+        self.filters.splice(0..0, filters);
+        let root = self.root.clone();
+        self.rescan(&root);
+    }
+
+    fn update_file(&mut self, path: &Path) {
+        match load_file(self.vfs.as_ref(), path, self.max_file_size, self.archives) {
+            Ok(entries) => {
+                // Entries deleted from an archive must not linger
+                self.remove_file(path);
+                for (entry_path, file_str, metadata) in entries {
+                    let entry_path: Arc<Path> = Arc::from(entry_path);
+                    self.symbols.update(Arc::clone(&entry_path), &file_str);
+                    let content = self.contents.insert(file_str, self.compression);
+                    self.files.insert(entry_path, IndexedFile::new(content, &metadata));
+                }
+            }
+            Err(failure) => self.read_failures.record(path, failure),
+        }
+    }
+
+    fn remove_file(&mut self, path: &Path) {
+        let mut removed: Vec<Arc<Path>> = vec![Arc::from(path)];
+        if self.archives && archive::is_archive(path) {
+            removed.extend(self.files.keys().filter(|key| archive::is_entry_of(key, path)).cloned());
+        }
+        for removed_path in removed {
+            if let Some(content) = self.files.remove(&removed_path).and_then(|file| file.content) {
+                self.contents.release(content);
+            }
+            self.symbols.remove(&removed_path);
+        }
+    }
+
+    // Brings everything under `path` back in sync with the disk after the
+    // watcher lost events. Unchanged files (same size and mtime) are kept.
+    fn rescan(&mut self, path: &Path) {
+        info!("rescan: {}", path.display());
+        events::emit("rescan", json!({ "root": self.root, "path": path }));
+        let mut on_disk: Vec<PathBuf> = Vec::new();
+        if self.is_file(path) {
+            if self.indexes(path) {
+                on_disk.push(path.to_path_buf());
+            }
+        } else {
+            let mut filters = mem::take(&mut self.filters);
+            let mut collect = |file_path: &Path, filters: &[Filter]| {
+                if should_index(filters, file_path, &self.root, self.shard, self.hidden, self.tracked.as_deref()) {
+                    on_disk.push(file_path.to_path_buf());
+                }
+            };
+            let _ = visit_dirs(self.vfs.as_ref(), path, &mut collect, &self.root, &mut filters, &mut WalkState::new(self.follow_symlinks, self.hidden).tracking(self.tracked.clone()));
+            self.filters = filters;
+        }
+
+        let on_disk_set: HashSet<&Path> = on_disk.iter().map(PathBuf::as_path).collect();
+        let stale: Vec<Arc<Path>> = self.files
+            .keys()
+            .filter(|key| key.starts_with(path) && !on_disk_set.contains(&***key))
+            .cloned()
+            .collect();
+        for stale_path in stale {
+            self.remove_file(&stale_path);
+        }
+        for file_path in &on_disk {
+            let changed = match (self.files.get(file_path.as_path()), self.vfs.metadata(file_path)) {
+                (Some(file), Ok(metadata)) => file.size != metadata.len || file.modified != metadata.modified,
+                _ => true,
+            };
+            if changed {
+                self.update_file(file_path);
+            }
+        }
+    }
+
+    fn refresh_tracked(&mut self) {
+        let Ok(tracked) = Tracked::read(&self.root) else {
+            return;
+        };
+        if self.tracked.as_deref() != Some(&tracked) {
+            self.tracked = Some(Arc::new(tracked));
+            let root = self.root.clone();
+            self.rescan(&root);
+        }
+    }
+
+    fn handle_event(&mut self, event: &Event) {
+        if event.need_rescan() {
+            if event.paths.is_empty() {
+                let root = self.root.clone();
+                self.rescan(&root);
+            }
+            for path in &event.paths {
+                self.rescan(path);
+            }
+            return;
+        }
+        // git add, rm or a checkout change what tracked_only indexes
+        if self.tracked.as_ref().is_some_and(|tracked| event.paths.contains(&tracked.index)) {
+            self.refresh_tracked();
+        }
+        // A nested .hanoi changes what is indexed below it
+        for path in &event.paths {
+            if path.file_name().is_some_and(|name| name == ".hanoi") {
+                if let Some(dir) = path.parent().filter(|dir| *dir != self.root) {
+                    self.rescan(dir);
+                }
+            }
+        }
+        match event.kind {
+            // The path a file was renamed from comes alone, or first of the
+            // two paths of the rename, and is gone like a removed one
+            EventKind::Modify(ModifyKind::Name(mode)) => {
+                let gone = match (mode, event.paths.len()) {
+                    (RenameMode::From, paths) => paths,
+                    (RenameMode::Both, 2) => 1,
+                    _ => 0,
+                };
+                let (from, to) = event.paths.split_at(gone);
+                for path in from {
+                    if self.indexes(path) {
+                        debug!("handle rename from event: {}", path.display());
+                        self.remove_file(path);
+                    }
+                }
+                for path in to {
+                    if self.indexes(path) && self.is_file(path) {
+                        debug!("handle rename to event: {}", path.display());
+                        self.update_file(path);
+                    }
+                }
+            },
+            EventKind::Create(_) | EventKind::Modify(_) => {
+                for path in &event.paths {
+                    if self.indexes(path) && self.is_file(path) {
+                        debug!("handle create/modify event: {}", path.display());
+                        self.update_file(path);
+                    }
+                }
+            },
+            EventKind::Remove(_) => {
+                for path in &event.paths {
+                    // The file is gone, so it can't be checked to be one
+                    if self.indexes(path) {
+                        debug!("handle remove event: {}", path.display());
+                        self.remove_file(path);
+                    }
+                }
+            },
+            _ => {}
+        }
+    }
+}
+
+fn parse_filter(l: &str, filters: &mut Vec<Filter>) {
+    let mut line = l;
+    let mut filter = Filter {
+        base : PathBuf::new(),
+        should_include : true,
+        should_start_with : true,
+        should_end_with : true,
+        only_dir : false,
+        pattern : String::new(),
+    };
+    if line.starts_with("!") {
+        filter.should_include = false;
+        line = &line[1..]
+    }
+    if line.starts_with("*") {
+        filter.should_start_with = false;
+        line = &line[1..]
+    }
+    if line.ends_with("*") {
+        filter.should_end_with = false;
+        line = &line[0..line.len() - 1]
+    }
+    if line.ends_with("/") {
+        filter.only_dir = true;
+        line = &line[0..line.len() - 1]
+    }
+    let mut pattern = String::from(line);
+    if cfg!(target_os = "windows") {
+        pattern = pattern.replace("/", "\\");
+    } else {
+        pattern = pattern.replace("\\", "/");
+    }
+    filter.pattern = pattern;
+    filters.push(filter);
+}
+
+// The index for queries, reading lazily indexed contents first. Only that
+// takes the write lock, so queries on a loaded index run side by side.
+fn read_loaded(indexer: &RwLock<Indexer2>) -> Locked<RwLockReadGuard<'_, Indexer2>> {
+    if watchdog::read("indexer", indexer).has_pending() {
+        watchdog::write("indexer", indexer).load_pending();
+    }
+    watchdog::read("indexer", indexer)
+}
+
+fn handle_job_request(args: &Args, saved_searches: &HashMap<String, Vec<String>>, jobs_dir: &Path, indexer: &Arc<RwLock<Indexer2>>, reader: &mut ReplyStream) {
+    let vfs = Arc::clone(&watchdog::read("indexer", indexer).vfs);
+    if let Some(name) = args.job_start.as_ref() {
+        match saved_searches.get(name) {
+            Some(terms) => match jobs::start_job(vfs, jobs_dir, name, terms, indexer) {
+                Ok(id) => {
+                    let _ = writeln!(reader, "started job {}", id);
+                }
+                Err(e) => {
+                    let _ = reader.send(Error::Write(jobs_dir.to_path_buf(), e).frame());
+                }
+            },
+            None => {
+                let _ = writeln!(reader, "no saved search named \"{}\"", name);
+            }
+        }
+    } else if let Some(id) = args.job_status.as_ref() {
+        jobs::job_status(vfs.as_ref(), jobs_dir, id, reader);
+    } else if let Some(id) = args.job_results.as_ref() {
+        jobs::job_results(vfs.as_ref(), jobs_dir, id, reader);
+    }
+}
+
+// Parses a `key = value` line of the [options] section. Options given on
+// the command line take precedence over the config file.
+fn parse_server_option(line: &str, args: &mut Args) -> std::result::Result<(), String> {
+    let (key, value) = line.split_once('=').ok_or_else(|| format!("expected \"key = value\", found \"{}\"", line))?;
+    let (key, value) = (key.trim(), value.trim());
+    match key {
+        "max_file_size" => {
+            let max_file_size = parse_option(key, value, ByteSize::from_str)?;
+            args.max_file_size.get_or_insert(max_file_size);
+        }
+        "max_memory" => {
+            let max_memory = parse_option(key, value, ByteSize::from_str)?;
+            args.max_memory.get_or_insert(max_memory);
+        }
+        "max_unreadable_percent" => {
+            let max_unreadable_percent = parse_option(key, value, parse_percent)?;
+            args.max_unreadable_percent.get_or_insert(max_unreadable_percent);
+        }
+        "locale" => {
+            let locale = Locale::from_name(value).ok_or_else(|| format!("invalid value for {}: unknown locale \"{}\"", key, value))?;
+            messages::set_locale(locale);
+        }
+        "follow_symlinks" => {
+            args.follow_symlinks |= parse_option(key, value, parse_bool)?;
+        }
+        "shards" => {
+            let shards = parse_option(key, value, |value| value.parse::<usize>().map_err(|e| e.to_string()))?;
+            args.shards.get_or_insert(shards);
+        }
+        "lazy" => {
+            args.lazy |= parse_option(key, value, parse_bool)?;
+        }
+        "hidden" => {
+            args.hidden |= parse_option(key, value, parse_bool)?;
+        }
+        "tracked_only" => {
+            args.tracked_only |= parse_option(key, value, parse_bool)?;
+        }
+        "archives" => {
+            args.archives |= parse_option(key, value, parse_bool)?;
+        }
+        "hang_timeout" => {
+            let hang_timeout = parse_option(key, value, HumanDuration::from_str)?;
+            args.hang_timeout.get_or_insert(hang_timeout);
+        }
+        "watch_debounce" => {
+            let watch_debounce = parse_option(key, value, HumanDuration::from_str)?;
+            args.watch_debounce.get_or_insert(watch_debounce);
+        }
+        "listen" => {
+            args.listen.get_or_insert_with(|| String::from(value));
+        }
+        "max_remote_queries" => {
+            let max_remote_queries = parse_option(key, value, |value| value.parse::<usize>().map_err(|e| e.to_string()))?;
+            args.max_remote_queries.get_or_insert(max_remote_queries);
+        }
+        "max_client_queries" => {
+            let max_client_queries = parse_option(key, value, |value| value.parse::<usize>().map_err(|e| e.to_string()))?;
+            args.max_client_queries.get_or_insert(max_client_queries);
+        }
+        "http" => {
+            args.http.get_or_insert_with(|| String::from(value));
+        }
+        "pipe_timeout" => {
+            let pipe_timeout = parse_option(key, value, HumanDuration::from_str)?;
+            args.pipe_timeout.get_or_insert(pipe_timeout);
+        }
+        _ => return Err(format!("unknown option \"{}\"", key)),
+    }
+    Ok(())
+}
+
+// Clients are only served once the index is built. Until then they are told
+// how far along the build is instead of waiting without a word.
+fn answer_while_indexing(named_pipe: &LocalSocketListener, progress: &Progress, verbose: bool, token: &str) {
+    if named_pipe.set_nonblocking(true).is_err() {
+        return;
+    }
+    let mut last_report = Instant::now();
+    while !progress.is_done() {
+        match named_pipe.accept() {
+            Ok(stream) => {
+                let _ = stream.set_nonblocking(false);
+                let _ = stream.set_timeout(transport::pipe_timeout());
+                let mut incoming_reader = BufReader::new(stream);
+                let Some((header, client_args)) = read_request(&mut incoming_reader, token) else {
+                    continue;
+                };
+                let mut client_reader = ReplyStream::new(incoming_reader.into_inner(), client_args.codec).tagged(client_args.session.then_some(header.tag));
+                let _ = client_reader.send(Frame::Progress(message!(Indexing, progress.percent(), progress)));
+                if client_args.main_server {
+                    client_reader.end_all();
+                }
+            }
+            Err(_) => thread::sleep(Duration::from_millis(50)),
+        }
+        if verbose && last_report.elapsed() >= Duration::from_secs(1) {
+            info!("indexing: {}", progress);
+            last_report = Instant::now();
+        }
+    }
+    let _ = named_pipe.set_nonblocking(false);
+}
+
+#[derive(Default)]
+struct RootConfig {
+    filters: Vec<Filter>,
+    additional_dirs: Vec<PathBuf>,
+    saved_searches: HashMap<String, Vec<String>>,
+    tenants: Tenants,
+    child_commands: HashMap<PathBuf, ChildCommand>,
+}
+
+// How the child server of an additional directory is started, from the
+// [child_servers] section: "dir = binary extra args...". By default the
+// running binary is started again with the forwarded options.
+#[derive(Clone)]
+struct ChildCommand {
+    binary: PathBuf,
+    args: Vec<String>,
+}
+
+fn parse_child_command(line: &str) -> std::result::Result<(PathBuf, ChildCommand), String> {
+    let Some((dir, command)) = line.split_once('=') else {
+        return Err(format!("expected \"dir = binary args...\", found \"{}\"", line));
+    };
+    let mut words = command.split_whitespace();
+    let Some(binary) = words.next() else {
+        return Err(format!("no binary given for \"{}\"", dir.trim()));
+    };
+    Ok((PathBuf::from(dir.trim()), ChildCommand {
+        binary: PathBuf::from(binary),
+        args: words.map(String::from).collect(),
+    }))
+}
+
+// Reads the .hanoi of `root`. Its options apply to `args` unless they were
+// given on the command line. Returns None if an option is invalid.
+fn read_root_config(vfs: &dyn Vfs, root: &Path, args: &mut Args) -> Option<RootConfig> {
+    let mut root_config = RootConfig::default();
+    let config_path = root.join(".hanoi");
+    if let Ok(config_str) = vfs.read_to_string(&config_path) {
+        let mut section = "";
+        for line in config_str.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with("#") {
+                // Ignore comment
+                continue;
+            }
+
+            if line.starts_with("[") && line.ends_with("]") {
+                section = &line[1..line.len() - 1];
+                continue;
+            }
+            match section {
+                "filters" => parse_filter(line, &mut root_config.filters),
+                "additional_dirs" => root_config.additional_dirs.push(PathBuf::from(line)),
+                // name = term, repeated to search for several terms at once
+                "saved_searches" => match line.split_once('=') {
+                    Some((name, term)) => root_config.saved_searches.entry(String::from(name.trim())).or_default().push(String::from(term.trim())),
+                    None => warn!("{}", message!(ConfigError, config_path.display(), format!("expected \"name = term\", found \"{}\"", line))),
+                },
+                "child_servers" => match parse_child_command(line) {
+                    Ok((dir, command)) => {
+                        root_config.child_commands.insert(dir, command);
+                    }
+                    Err(e) => warn!("{}", message!(ConfigError, config_path.display(), e)),
+                },
+                "tenants" | "tenant_readers" | "tenant_tokens" => {
+                    let result = match section {
+                        "tenants" => root_config.tenants.parse_tenant(line),
+                        "tenant_readers" => root_config.tenants.parse_readers(line),
+                        _ => root_config.tenants.parse_token(line),
+                    };
+                    if let Err(e) = result {
+                        warn!("{}", message!(ConfigError, config_path.display(), e));
+                    }
+                }
+                "options" => {
+                    if let Err(e) = parse_server_option(line, args) {
+                        error!("{}", message!(ConfigError, config_path.display(), e));
+                        return None;
+                    }
+                }
+                &_ => warn!("{}", message!(UnknownSection, line, section)),
+            }
+        }
+    }
+    Some(root_config)
+}
+
+fn spawn_child_server(args: &Args, root: &Path, shard: Option<Shard>, command: Option<&ChildCommand>) -> std::result::Result<Child, Error> {
+    let binary = match command {
+        Some(command) => command.binary.clone(),
+        None => std::env::current_exe().unwrap_or_else(|_| PathBuf::from("Hanoi")),
+    };
+    let mut child = Command::new(binary);
+    // Children get their settings as flags. The HANOI_ variables are meant
+    // for this server, with its --name or --listen, except the language.
+    for (name, _) in std::env::vars_os() {
+        if name.to_string_lossy().starts_with("HANOI_") && name != "HANOI_LANG" {
+            child.env_remove(name);
+        }
+    }
+    child
+        // Binaries from [child_servers] may predate the subcommands
+        .arg("--mode=server")
+        .arg(std::format!("--root={}", root.display()))
+        .arg(std::format!("--compression={}", args.compression.to_possible_value().unwrap().get_name()))
+        .args(shard.map(|shard| std::format!("--shard={}", shard)))
+        .args(args.follow_symlinks.then_some("--follow-symlinks"))
+        .args(args.hidden.then_some("--hidden"))
+        .args(args.tracked_only.then_some("--vcs"))
+        .args(args.archives.then_some("--archives"))
+        .args(args.lazy.then_some("--lazy"))
+        .args(args.max_file_size.map(|size| std::format!("--max-file-size={}", size.0)))
+        .args(args.max_memory.map(|size| std::format!("--max-memory={}", size.0)))
+        .args(args.max_unreadable_percent.map(|percent| std::format!("--max-unreadable-percent={}", percent)))
+        .arg(std::format!("--log-level={}", args.log_level.to_possible_value().unwrap().get_name()))
+        .args(args.log_file.as_ref().map(|log_file| std::format!("--log-file={}", log_file)))
+        .args(command.map_or(&[][..], |command| &command.args))
+        .spawn()
+        .map_err(|e| Error::Spawn(root.to_path_buf(), e))
+}
+
+// Starts the server for --detach in the background, with the arguments this
+// process got.
+fn detach_server(args: &Args) -> ExitCode {
+    let Some(root_str) = args.root.first() else {
+        eprintln!("{}", message!(MissingRoot));
+        return ExitCode::from(EXIT_ERROR);
+    };
+    let root = runtime::address(Path::new(root_str.as_str()), args.name.as_deref());
+    let server_args: Vec<String> = std::env::args().skip(1).filter(|arg| arg != "--detach").collect();
+    match runtime::spawn_detached(&root, &server_args) {
+        Ok(pid) => {
+            eprintln!("{}", message!(Detached, pid, runtime::log_path(&root).display()));
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::from(EXIT_ERROR)
+        }
+    }
+}
+
+// What every root served by this process does when it is stopped by a
+// signal.
+type ShutDowns = Arc<Mutex<Vec<Box<dyn FnOnce() + Send>>>>;
+
+fn server_main(args: &Args) -> ExitCode {
+    if let Err(e) = logging::init(args.log_level, args.log_file.as_deref().map(Path::new)) {
+        eprintln!("{}", e);
+        return ExitCode::from(EXIT_ERROR);
+    }
+    let Some((root_str, extra_roots)) = args.root.split_first() else {
+        error!("{}", message!(MissingRoot));
+        return ExitCode::from(EXIT_ERROR);
+    };
+    // Ctrl-C and SIGTERM stop the child servers and remove the sockets and
+    // registry entries of every root, so the next server isn't kept out by
+    // them. Clients still being answered are cut off.
+    let shut_downs = ShutDowns::default();
+    let shut_down = {
+        let shut_downs = Arc::clone(&shut_downs);
+        move || {
+            for shut_down in mem::take(&mut *watchdog::lock("shut downs", &shut_downs)) {
+                shut_down();
+            }
+            process::exit(0);
+        }
+    };
+    if let Err(e) = runtime::on_termination(shut_down) {
+        warn!("{}", message!(SignalError, e));
+    }
+    watchdog::start(args.hang_timeout.map_or(Duration::from_secs(30), |timeout| timeout.0));
+    // Senders are dropped as the extra roots stop, the first root waits for
+    // all of them before the process exits
+    let (stopped, all_stopped) = mpsc::channel::<()>();
+    for root in extra_roots {
+        let mut root_args = args.clone();
+        root_args.root = vec![root.clone()];
+        root_args.listen = None;
+        root_args.http = None;
+        let shut_downs = Arc::clone(&shut_downs);
+        let stopped = stopped.clone();
+        // Only the first root decides the exit code, the process stays up
+        // for it when another one fails
+        thread::spawn(move || serve_root(&root_args, &shut_downs, &[], move || drop(stopped)));
+    }
+    drop(stopped);
+    let mut root_args = args.clone();
+    root_args.root = vec![root_str.clone()];
+    let extra_roots: Vec<PathBuf> = extra_roots.iter().map(PathBuf::from).collect();
+    serve_root(&root_args, &shut_downs, &extra_roots, || {
+        let _ = all_stopped.recv();
+        process::exit(0);
+    })
+}
+
+// Indexes and serves one root. The server of `args.root` also answers
+// for `extra_roots`. Calls `finish` once it stopped, instead of waiting for
+// the clients still attached to it. Fails when the root can't be served.
+fn serve_root(args: &Args, shut_downs: &ShutDowns, extra_roots: &[PathBuf], finish: impl FnOnce()) -> ExitCode {
+    let started = Instant::now();
+    let path = PathBuf::from(args.root[0].as_str());
+    // Shards run under the server that started them, and named servers next
+    // to any other
+    if args.shard.is_none() && args.name.is_none() {
+        if let Some((existing_root, _)) = runtime::find_server(&path) {
+            error!("{}", message!(AlreadyIndexed, existing_root.display()));
+            return ExitCode::from(EXIT_ERROR);
+        }
+    }
+
+    info!("{}", message!(StartIndexing, path.display()));
+    let address = args.shard.map_or_else(|| runtime::address(&path, args.name.as_deref()), |shard| shard.address(&path));
+    let (named_pipe, registration) = match runtime::bind(&address, args.name.as_deref()) {
+        Ok(bound) => bound,
+        Err(e) if e.kind() == io::ErrorKind::AddrInUse => {
+            let pid = runtime::server_pid(&address).map_or_else(|| String::from("?"), |pid| pid.to_string());
+            error!("{}", message!(ServerRunning, address.display(), pid));
+            return ExitCode::from(EXIT_ERROR);
+        }
+        Err(e) => {
+            error!("{}", message!(SocketError, runtime::socket_name(&address).display(), e));
+            return ExitCode::from(EXIT_ERROR);
+        }
+    };
+    let (token, token_path) = match auth::create_token(&address) {
+        Ok(created) => created,
+        Err(e) => {
+            error!("{}", message!(TokenError, e));
+            return ExitCode::from(EXIT_ERROR);
+        }
+    };
+
+    let mut args = args.clone();
+    let vfs: Arc<dyn Vfs> = Arc::new(OsVfs);
+    let Some(RootConfig { filters, mut additional_dirs, saved_searches, mut tenants, child_commands }) = read_root_config(vfs.as_ref(), &path, &mut args) else {
+        return ExitCode::from(EXIT_ERROR);
+    };
+    let child_servers = Arc::new(Mutex::new(ChildServers::new(&args)));
+    let shut_down = {
+        let child_servers = Arc::clone(&child_servers);
+        let address = address.clone();
+        move || {
+            info!("{}", message!(Stopping, address.display()));
+            let mut child_servers = watchdog::lock("child servers", &child_servers);
+            child_servers.request_stop_all();
+            child_servers.stop();
+            runtime::clean_up(&address, None);
+        }
+    };
+    watchdog::lock("shut downs", shut_downs).push(Box::new(shut_down));
+    if args.shard.is_some() {
+        // Only the server that started the shards serves these
+        additional_dirs.clear();
+        tenants = Tenants::default();
+        args.shards = None;
+    }
+    let tenants = Arc::new(tenants);
+    if let Some(pipe_timeout) = args.pipe_timeout {
+        transport::set_pipe_timeout(pipe_timeout.0);
+    }
+    // Shared by --listen and --http, remote clients take turns however they
+    // connect
+    let scheduler = Arc::new(Scheduler::new(args.max_remote_queries.unwrap_or(8), args.max_client_queries.unwrap_or(2)));
+    if let Some(listen_address) = args.listen.as_ref().filter(|_| args.shard.is_none()) {
+        match transport::listen(listen_address, runtime::socket_name(&address), token.clone(), Arc::clone(&tenants), Arc::clone(&scheduler)) {
+            Ok(()) => info!("{}", message!(Listening, listen_address, token_path.display())),
+            Err(e) => error!("{}", message!(ListenError, listen_address, e)),
+        }
+    }
+    if let Some(http_address) = args.http.as_ref().filter(|_| args.shard.is_none()) {
+        #[cfg(feature = "http")]
+        match http::serve(http_address, runtime::socket_name(&address), token.clone(), Arc::clone(&tenants), Arc::clone(&scheduler)) {
+            Ok(()) => info!("{}", message!(Listening, http_address, token_path.display())),
+            Err(e) => error!("{}", message!(ListenError, http_address, e)),
+        }
+        #[cfg(not(feature = "http"))]
+        warn!("{}", message!(HttpUnavailable, http_address));
+    }
+    let shards: Vec<Shard> = Shard::all(args.shards.filter(|count| *count > 1).unwrap_or(0)).collect();
+
+    let mut indexer2 = Indexer2 {
+        compression: args.compression,
+        max_file_size: args.max_file_size.map(|size| size.0),
+        max_memory: args.max_memory.map(|size| size.0),
+        filters,
+        follow_symlinks: args.follow_symlinks,
+        hidden: args.hidden,
+        archives: args.archives,
+        lazy: args.lazy,
+        shard: args.shard,
+        tracked: args.tracked_only.then(|| read_tracked(&path)).flatten(),
+        vfs: Arc::clone(&vfs),
+        ..Default::default()
+    };
+    // With shards the files are indexed by the shard servers alone
+    if shards.is_empty() {
+        let progress = Arc::new(Progress::default());
+        thread::scope(|scope| {
+            scope.spawn(|| answer_while_indexing(&named_pipe, &progress, args.verbose, &token));
+            // A detached server has no terminal to draw on
+            if io::stderr().is_terminal() {
+                scope.spawn(|| progress::show_bar(&progress));
+            }
+            let _scope_time = ScopeTime::default();
+            indexer2.build(&path, Arc::clone(&progress));
+        });
+    }
+    let failures = indexer2.read_failures.total();
+    if failures.total() > 0 {
+        warn!("{}", message!(UnreadableFiles, failures.total(), failures));
+    }
+    if let Some(max_unreadable_percent) = args.max_unreadable_percent {
+        let unreadable_percent = indexer2.unreadable_percent();
+        if unreadable_percent > max_unreadable_percent {
+            error!("{}", message!(TooManyUnreadableFiles, format!("{:.1}", unreadable_percent), max_unreadable_percent));
+            return ExitCode::from(EXIT_ERROR);
+        }
+    }
+    let indexer2 = Arc::new(RwLock::new(indexer2));
+    let publications = Arc::new(Mutex::new(Publications::default()));
+    let jobs_dir = jobs::jobs_dir(&convert_path(&address));
+    jobs::resume_jobs(Arc::clone(&vfs), &jobs_dir, &indexer2);
+    let mut children = watchdog::lock("child servers", &child_servers);
+    for shard in &shards {
+        children.spawn(shard.address(&path), &path, Some(*shard), None);
+    }
+    for dir in additional_dirs.iter().chain(tenants.roots()) {
+        children.spawn(dir.clone(), dir, None, child_commands.get(dir));
+    }
+    // Shards are addressed like additional directories
+    children.forward_dirs = shards.iter().map(|shard| shard.address(&path)).chain(additional_dirs.iter().cloned()).collect();
+    drop(children);
+    supervise(Arc::clone(&child_servers));
+    let mut _watcher = None;
+    if shards.is_empty() {
+        let indexer2 = indexer2.clone();
+        let publications = publications.clone();
+        let child_servers = child_servers.clone();
+        let (config_args, config_root, config_vfs) = (args.clone(), path.clone(), Arc::clone(&vfs));
+        let config_path = path.join(".hanoi");
+        let mut config_str = vfs.read_to_string(&config_path).ok();
+        let debounce = args.watch_debounce.map_or(Duration::from_millis(200), |debounce| debounce.0);
+        let watched = vfs.watch(&path, debounce, Box::new(move |res: Result<Vec<Event>>| {
+            match res {
+               Ok(events) => {
+                   let _activity = watchdog::track(format!("handling {} watcher events", events.len()));
+                   let mut indexer2 = watchdog::write("indexer", &indexer2);
+                   // Saving the root .hanoi often comes as several events.
+                   // One that can't be read keeps the configuration the
+                   // server has.
+                   let mut config_changed = false;
+                   if events.iter().any(|event| event.paths.contains(&config_path)) {
+                       let new_config_str = config_vfs.read_to_string(&config_path).ok();
+                       config_changed = new_config_str != config_str;
+                       config_str = new_config_str;
+                   }
+                   if let Some(config) = config_changed.then(|| read_root_config(config_vfs.as_ref(), &config_root, &mut config_args.clone())).flatten() {
+                       info!("{}", message!(ConfigReloaded, config_path.display()));
+                       events::emit("config_reloaded", json!({ "root": config_root, "additional_dirs": config.additional_dirs }));
+                       indexer2.reload_filters(config.filters);
+                       watchdog::lock("child servers", &child_servers).update_additional_dirs(&config.additional_dirs, &config.child_commands);
+                   }
+                   indexer2.handle_events(events);
+                   watchdog::lock("publications", &publications).notify(&indexer2);
+               }
+               Err(e) => {
+                   error!("{}", message!(WatchError, format!("{:?}", e)));
+                   events::emit("watch_error", json!({ "error": format!("{:?}", e) }));
+               }
+            }
+        }));
+        // Without a watcher the index still answers, it just goes stale
+        match watched {
+            Ok(watcher) => _watcher = Some(watcher),
+            Err(e) => warn!("{}", Error::Watch(e.to_string())),
+        }
+    }
+
+    let trace_name = args.shard.map_or_else(|| path.display().to_string(), |shard| shard.address(&path).display().to_string());
+    let stopping = AtomicBool::new(false);
+    let answer = |header: Header, mut client_args: Args, caller: &Caller, mut client_reader: ReplyStream| {
+        if client_args.ping {
+            if client_args.main_server {
+                client_reader.end_all();
+            }
+            return;
+        }
+        // Args of another release decoded, but may not mean what the client
+        // meant
+        if client_args.main_server && !header.release.is_empty() && header.release != RELEASE {
+            let _ = client_reader.send(Frame::Warning(message!(ReleaseDiffers, header.release, RELEASE)));
+        }
+        let attached = transport::stays_attached(&client_args);
+        let mut watched = Vec::new();
+        let mut trace = (client_args.stats && client_args.verbose).then(|| Trace::new(header.trace_id, trace_name.clone()));
+        if let Some(trace) = trace.as_mut() {
+            trace.record_queue_wait(header.sent_at);
+        }
+        let scan_start = Instant::now();
+        let _activity = watchdog::track(format!("request {:016x}", header.trace_id));
+        let span = info_span!("request", trace_id = %format_args!("{:016x}", header.trace_id), term = client_args.term.as_deref().unwrap_or_default(), files = tracing::field::Empty);
+        let _span = span.enter();
+        if watchdog::read("indexer", &indexer2).suspension_expired() {
+            watchdog::write("indexer", &indexer2).resume_if_expired();
+        }
+        let tenant = client_args.tenant.as_ref().map(|name| tenants.resolve(name, caller));
+        if let Some(tenant) = tenant.as_ref() {
+            // Tenant queries are answered by the tenant's own server only
+            if let Err(e) = tenant {
+                let _ = client_reader.send(Frame::Error(e.clone()));
+            }
+        } else if client_args.stop {
+            watchdog::lock("child servers", &child_servers).stop_supervising();
+            let _ = writeln!(client_reader, "{}", message!(Stopping, trace_name));
+        } else if client_args.events {
+            // Registered below, once the child servers replied
+        } else if let Some(name) = client_args.subscribe.as_ref() {
+            let published_args = watchdog::lock("publications", &publications).published(name);
+            match published_args {
+                Some(published_args) => {
+                    send_results(name, &published_args, &read_loaded(&indexer2), &mut client_reader);
+                }
+                None if client_args.main_server => {
+                    let _ = client_reader.send(Frame::Error(message!(NotPublished, name)));
+                }
+                None => {}
+            }
+        } else if client_args.job_start.is_some() || client_args.job_status.is_some() || client_args.job_results.is_some() {
+            // Jobs only run on the server the client talks to
+            if client_args.main_server {
+                handle_job_request(&client_args, &saved_searches, &jobs_dir, &indexer2, &mut client_reader);
+            }
+        } else if client_args.compact {
+            watchdog::write("indexer", &indexer2).handle_compact_request(&mut client_reader);
+        } else if client_args.reindex && client_args.background {
+            reindex_in_background(&indexer2, &mut client_reader);
+        } else if client_args.reindex {
+            watchdog::write("indexer", &indexer2).reindex(&mut client_reader);
+        } else if let Some(duration) = client_args.suspend_watch {
+            watchdog::write("indexer", &indexer2).suspend_watch(duration.0, &mut client_reader);
+        } else if let Some(paths) = client_args.resume_watch.as_ref() {
+            watchdog::write("indexer", &indexer2).handle_resume_request(paths, &mut client_reader);
+        } else if !client_args.focus.is_empty() || client_args.clear_focus {
+            watchdog::write("indexer", &indexer2).set_focus(&client_args, &mut client_reader);
+        } else if client_args.status {
+            let indexer = watchdog::read("indexer", &indexer2);
+            let children = watchdog::lock("child servers", &child_servers).forward_dirs.iter().chain(extra_roots).map(|dir| dir.display().to_string()).collect();
+            let _ = client_reader.send(Frame::Stats(ServerStats { root: trace_name.clone(), uptime_secs: started.elapsed().as_secs(), children, ..indexer.stats() }));
+            indexer.status(&mut client_reader);
+        } else if client_args.files {
+            watchdog::read("indexer", &indexer2).list_files(&client_args, &mut client_reader);
+        } else if let Some(symbol) = client_args.symbol.as_ref() {
+            read_loaded(&indexer2).find_symbol(symbol, &mut client_reader);
+        } else if let Some(path) = client_args.outline.as_ref() {
+            read_loaded(&indexer2).outline(Path::new(path), &mut client_reader);
+        } else if client_args.tags {
+            read_loaded(&indexer2).list_symbols(&mut client_reader);
+        } else if client_args.term.is_some() && client_args.watch {
+            // Every match is new to a client that just started watching
+            watched = publish::matches(&client_args, &read_loaded(&indexer2));
+            let _ = publish::send_changes(&[], &watched, &mut client_reader);
+        } else if client_args.term.is_some() {
+            if let Some(name) = client_args.publish.as_ref() {
+                watchdog::lock("publications", &publications).publish(name, &client_args);
+            }
+            let counts = read_loaded(&indexer2).find(&client_args, &mut client_reader);
+            if client_args.stats {
+                counts.send(header.trace_id, &trace_name, scan_start, &mut client_reader);
+            }
+            if watchdog::read("indexer", &indexer2).has_rematched() {
+                watchdog::write("indexer", &indexer2).restore_matched();
+            }
+        }
+        if let Some(trace) = trace.as_mut() {
+            trace.record("scan", scan_start);
+        }
+        // Send the arguments to child servers
+        let is_main_server = client_args.main_server;
+        if is_main_server {
+            client_args.main_server = false;
+        }
+        let forward_to: Vec<PathBuf> = match &tenant {
+            Some(Ok(root)) => vec![root.to_path_buf()],
+            Some(Err(_)) => Vec::new(),
+            // The other roots of this process are searched like additional
+            // directories
+            None => watchdog::lock("child servers", &child_servers).forward_dirs.iter().chain(extra_roots).cloned().collect(),
+        };
+        client_args.tenant = None;
+        // Only the replies to the client are compressed, tagged or in its
+        // codec, those of the child servers are read back here
+        client_args.compress = false;
+        client_args.session = false;
+        client_args.codec = Encoding::Bincode;
+        let mut attached_children = Vec::new();
+        for dir in &forward_to {
+            let forward_start = Instant::now();
+            let mut answered = false;
+            if let Ok(additional_pipe) = LocalSocketStream::connect(runtime::socket_name(dir)) {
+                let mut additional_buffer = BufReader::new(additional_pipe);
+                if let Err(e) = write_request(&mut additional_buffer, &client_args, header.trace_id, auth::read_token(dir)) {
+                    let _ = client_reader.send(e.frame());
+                    continue;
+                }
+                // The replies of child servers reach the client with the ones
+                // of this server. Also stop waiting for a child server that
+                // died.
+                while let Some(frame) = read_frame(&mut additional_buffer, &client_args.codec) {
+                    if let Frame::EndOfResults { .. } = frame {
+                        answered = true;
+                        break;
+                    }
+                    let _ = client_reader.send(frame);
+                }
+                if attached {
+                    attached_children.push(additional_buffer);
+                }
+            }
+            if !answered {
+                let warning = match watchdog::lock("child servers", &child_servers).restarting_in(dir) {
+                    Some(wait) => message!(ChildRestarting, dir.display(), HumanDuration(Duration::from_secs(wait.as_secs().max(1)))),
+                    None => message!(ChildNotAnswering, dir.display()),
+                };
+                let _ = client_reader.send(Frame::Warning(warning));
+            }
+            if let Some(trace) = trace.as_mut() {
+                trace.record_fan_out(&dir.display().to_string(), forward_start);
+            }
+        }
+        if let Some(trace) = trace.as_ref() {
+            trace.send(&mut client_reader);
+        }
+        info!(elapsed_micros = scan_start.elapsed().as_micros() as u64, "answered");
+        if is_main_server {
+            client_reader.end_all();
+        }
+        if client_args.stop {
+            // The client hears back before the server goes away
+            drop(client_reader);
+            stopping.store(true, Ordering::Relaxed);
+            // Wakes up the accept loop below
+            let _ = LocalSocketStream::connect(runtime::socket_name(&address));
+            return;
+        }
+        if !attached {
+            return;
+        }
+        // Later replies go out as they come, each set ending with
+        // EndOfResults, until nothing holds on to the client anymore
+        let _ = client_reader.end_batch();
+        let client_reader = Arc::new(Mutex::new(client_reader));
+        if client_args.events {
+            events::listen(Arc::clone(&client_reader));
+        } else if let Some(name) = client_args.subscribe.as_ref() {
+            watchdog::lock("publications", &publications).subscribe(name, Arc::clone(&client_reader));
+        } else if client_args.watch {
+            watchdog::lock("publications", &publications).watch(&client_args, watched, Arc::clone(&client_reader));
+        }
+        for mut child_reader in attached_children {
+            let client_reader = Arc::clone(&client_reader);
+            let codec = client_args.codec;
+            thread::spawn(move || {
+                while let Some(frame) = read_frame(&mut child_reader, &codec) {
+                    let mut client_reader = client_reader.lock().unwrap_or_else(|e| e.into_inner());
+                    if client_reader.send(frame).and_then(|_| client_reader.flush()).is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+    };
+    // Every client is served on its own thread, so searches only wait for
+    // each other while the index is being changed
+    let handle_client = |stream: LocalSocketStream| {
+        let _ = stream.set_timeout(transport::pipe_timeout());
+        let mut incoming_reader = BufReader::new(stream);
+        // Only the owner of the server can read its token, see auth.rs
+        let Some((header, client_args)) = read_request(&mut incoming_reader, &token) else {
+            return;
+        };
+        if !client_args.session {
+            let client_reader = ReplyStream::new(incoming_reader.into_inner(), client_args.codec).compressed(client_args.compress);
+            return answer(header, client_args, &Caller::Owner, client_reader);
+        }
+        // The requests of a session are read here and each answered on its
+        // own thread, until the client hangs up or sends one that can't be
+        // read
+        let Ok(writer) = incoming_reader.get_ref().duplicate() else {
+            return;
+        };
+        let writer = SharedWriter::new(writer);
+        // A session waits idle between requests
+        let _ = incoming_reader.get_ref().set_timeout(Duration::ZERO);
+        thread::scope(|scope| {
+            let mut request = Some((header, client_args));
+            while let Some((header, client_args)) = request.take().or_else(|| read_request(&mut incoming_reader, &token)) {
+                let client_reader = ReplyStream::new(writer.clone(), client_args.codec).compressed(client_args.compress).tagged(Some(header.tag));
+                let answer = &answer;
+                scope.spawn(move || answer(header, client_args, &Caller::Owner, client_reader));
+            }
+        });
+    };
+    thread::scope(|scope| {
+        for stream in named_pipe.incoming().flatten() {
+            if stopping.load(Ordering::Relaxed) {
+                break;
+            }
+            let handle_client = &handle_client;
+            scope.spawn(move || handle_client(stream));
+        }
+        // Clients still attached to events or publications go with the
+        // process instead of keeping it alive
+        watchdog::lock("child servers", &child_servers).stop();
+        drop(registration);
+        finish();
+    });
+    ExitCode::SUCCESS
+}
+
+// Starts building a new index on a thread of its own, without holding the
+// lock so queries keep being answered from the old one, and swaps them once
+// it is done. Only one such build runs at a time.
+fn reindex_in_background(indexer2: &Arc<RwLock<Indexer2>>, reader: &mut ReplyStream) {
+    let mut fresh = {
+        let mut indexer = watchdog::write("indexer", indexer2);
+        if indexer.rebuilding.is_some() {
+            let _ = reader.send(Frame::Error(message!(AlreadyRebuilding, indexer.root.display())));
+            return;
+        }
+        indexer.rebuilding = Some(HashSet::new());
+        indexer.fresh()
+    };
+    let _ = writeln!(reader, "reindexing {} in the background", fresh.root.display());
+    let indexer2 = Arc::clone(indexer2);
+    thread::spawn(move || {
+        let root = fresh.root.clone();
+        fresh.build(&root, Arc::default());
+        let old = watchdog::write("indexer", &indexer2).replace(fresh);
+        info!("reindexed {} in the background", root.display());
+        drop(old);
+    });
+}
+
+fn client_main(args: &mut Args) -> ExitCode {
+    let start = Instant::now();
+    let trace_id: u64 = rand::thread_rng().gen();
+    let connect;
+    let root_dir = match std::env::current_dir() {
+        Ok(root_dir) => root_dir,
+        Err(e) => {
+            eprintln!("{}", Error::Read(PathBuf::from("."), e));
+            return ExitCode::from(EXIT_ERROR);
+        }
+    };
+    // The servers don't know the client's working directory
+    args.focus = args.focus
+        .iter()
+        .map(|focus| root_dir.join(focus).display().to_string())
+        .collect();
+    if let Some(paths) = args.resume_watch.as_mut() {
+        *paths = paths.iter().map(|path| root_dir.join(path).display().to_string()).collect();
+    }
+    if let Some(path) = args.outline.as_mut() {
+        *path = root_dir.join(&path).display().to_string();
+    }
+    if args.query_from_stdin {
+        let mut term = String::new();
+        if let Err(e) = io::stdin().read_to_string(&mut term) {
+            eprintln!("{}", Error::Read(PathBuf::from("-"), e));
+            return ExitCode::from(EXIT_ERROR);
+        }
+        let term = term.trim_end_matches(['\n', '\r']);
+        // fzf reloads with an empty query before anything is typed
+        if term.is_empty() {
+            return ExitCode::SUCCESS;
+        }
+        args.term = Some(term.to_string());
+    }
+    args.main_server = true;
+    let kind = if args.status || args.stop || args.ping || args.events || args.watch || args.tags || args.suspend_watch.is_some() || args.resume_watch.is_some() || args.compact || args.reindex || !args.focus.is_empty() || args.clear_focus || args.job_start.is_some() || args.job_status.is_some() {
+        ResultKind::Other
+    } else if args.files || args.files_with_matches {
+        ResultKind::Files
+    } else {
+        ResultKind::Matches
+    };
+    let mut printer = Printer::new(kind, args.verbose_labels || args.accessible, args.ascii || args.accessible, args.preview.map(Previewer::new)).json(args.json).format(args.format, &root_dir).null(args.null).files_with_matches(args.files_with_matches && !args.files).heading(args.heading).sort(args.sort, args.reverse).blame(args.blame).quiet(args.quiet).fzf(args.fzf, &root_dir);
+    if args.blame && !cfg!(feature = "blame") {
+        eprintln!("{}", message!(BlameUnavailable));
+    }
+    let mut trace_report = TraceReport::new(trace_id);
+    if args.servers {
+        let servers = runtime::list_servers();
+        if servers.is_empty() {
+            eprintln!("{}", message!(NoServers));
+        }
+        for server in servers {
+            let pid = server.pid.map_or_else(|| String::from("?"), |pid| pid.to_string());
+            println!("{}", message!(ServerListed, server.root.display(), pid));
+        }
+        return ExitCode::SUCCESS;
+    }
+    // Thousands of results would scroll past on a terminal
+    #[cfg(unix)]
+    let _pager = if kind != ResultKind::Other && !args.tui && !args.quiet && !args.no_pager && !args.fzf && io::stdout().is_terminal() {
+        pager::start()
+    } else {
+        None
+    };
+    if args.no_daemon {
+        logging::init_one_shot();
+        if kind == ResultKind::Other {
+            eprintln!("{}", message!(NeedsServer));
+            return ExitCode::from(EXIT_ERROR);
+        }
+        let root = args.root.first().map_or_else(|| root_dir.clone(), PathBuf::from);
+        let mut replies_reader = match oneshot::search(args, root.clone()) {
+            Ok(replies_reader) => BufReader::new(replies_reader),
+            Err(e) => {
+                eprintln!("{}", message!(OneShotError, e));
+                return ExitCode::from(EXIT_ERROR);
+            }
+        };
+        connect = start.elapsed();
+        printer.begin(&root.display().to_string(), args.term.as_deref());
+        while read_replies(&mut replies_reader, Protocol::Bincode, args.codec, &mut printer, &mut trace_report) == Replies::More {}
+    } else if let Some(address) = args.connect.clone() {
+        let stream = match TcpStream::connect(&address) {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("{}", message!(ConnectError, address, e));
+                return ExitCode::from(EXIT_ERROR);
+            }
+        };
+        let mut server_reader = BufReader::new(stream);
+        let token = args.token.clone().unwrap_or_default();
+        let sent = match args.protocol {
+            Protocol::Bincode => write_request(&mut server_reader, args, trace_id, token),
+            Protocol::Json => {
+                // The arguments of this client are passed on as they are
+                let request = JsonRequest { version: PROTOCOL_VERSION, token, trace_id, args: std::env::args().skip(1).collect() };
+                server_reader.get_mut().write_all(request.to_line().as_bytes()).map_err(Error::Pipe)
+            }
+        };
+        if let Err(e) = sent {
+            eprintln!("{}", message!(ConnectError, address, e));
+            return ExitCode::from(EXIT_ERROR);
+        }
+        connect = start.elapsed();
+        printer.begin(&address, args.term.as_deref());
+        // The remote server relays every reply over this connection
+        while read_replies(&mut server_reader, args.protocol, args.codec, &mut printer, &mut trace_report) == Replies::More {}
+    } else {
+        let server_dir = args.daemon.as_ref().map_or_else(|| root_dir.clone(), PathBuf::from);
+        let found = match args.server.as_ref() {
+            Some(name) => runtime::find_named_server(name),
+            None => runtime::find_server(server_dir.as_path()).or_else(|| if args.auto_start { auto_start(&server_dir) } else { None }),
+        };
+        let Some((existing_pipe_name, named_pipe)) = found else {
+            match args.server.as_ref() {
+                Some(name) => eprintln!("{}", message!(NoNamedServer, name)),
+                None if !args.auto_start => eprintln!("{}", message!(NoServer)),
+                None => {}
+            }
+            return ExitCode::from(EXIT_ERROR);
+        };
+        let ping_start = Instant::now();
+        if let Err(e) = runtime::ping(&existing_pipe_name) {
+            recover_dead_server(&existing_pipe_name, e);
+            return ExitCode::from(EXIT_ERROR);
+        }
+        if args.ping {
+            println!("{}", message!(ServerAlive, existing_pipe_name.display(), format!("{:?}", ping_start.elapsed())));
+            return ExitCode::SUCCESS;
+        }
+        if args.server_version || args.strict_version {
+            let (release, protocol) = match runtime::server_version(&existing_pipe_name) {
+                Ok(version) => version,
+                Err(e) => {
+                    eprintln!("{}", message!(VersionUnknown, existing_pipe_name.display(), e));
+                    return ExitCode::from(EXIT_ERROR);
+                }
+            };
+            if args.server_version {
+                println!("{}", message!(ServerVersion, release, protocol, RELEASE, PROTOCOL_VERSION));
+            }
+            if release != RELEASE || protocol != PROTOCOL_VERSION {
+                eprintln!("{}", message!(ReleaseDiffers, RELEASE, release));
+                if args.strict_version {
+                    return ExitCode::from(EXIT_ERROR);
+                }
+            }
+            if args.server_version {
+                return ExitCode::SUCCESS;
+            }
+        }
+        if args.tags {
+            return tags::tags_main(args, &existing_pipe_name);
+        }
+        if args.tui {
+            #[cfg(unix)]
+            return tui::run(args, &existing_pipe_name);
+            #[cfg(not(unix))]
+            {
+                eprintln!("{}", message!(TuiUnavailable));
+                return ExitCode::from(EXIT_ERROR);
+            }
+        }
+        let mut main_server_reader = BufReader::new(named_pipe);
+        if let Err(e) = write_request(&mut main_server_reader, args, trace_id, auth::read_token(&existing_pipe_name)) {
+            eprintln!("{}", message!(ConnectError, existing_pipe_name.display(), e));
+            return ExitCode::from(EXIT_ERROR);
+        }
+        connect = start.elapsed();
+        printer.begin(&existing_pipe_name.display().to_string(), args.term.as_deref());
+        while read_replies(&mut main_server_reader, Protocol::Bincode, args.codec, &mut printer, &mut trace_report) == Replies::More {}
+    }
+    printer.finish();
+    // The summary record has the time already
+    if args.stats && !args.json && !args.quiet && !args.fzf {
+        trace_report.print(connect, start.elapsed(), args.verbose);
+    }
+    printer.exit_code()
+}
+
+// The closest directory around `dir` with a .hanoi, or else with a .git.
+fn project_root(dir: &Path) -> PathBuf {
+    [".hanoi", ".git"]
+        .iter()
+        .find_map(|marker| dir.ancestors().find(|ancestor| ancestor.join(marker).exists()))
+        .unwrap_or(dir)
+        .to_path_buf()
+}
+
+// Starts a server for the project of `dir` for --auto-start and connects to
+// it once it is done indexing.
+fn auto_start(dir: &Path) -> Option<(PathBuf, LocalSocketStream)> {
+    let root = project_root(dir);
+    if let Err(e) = runtime::start_server(&root) {
+        eprintln!("{}", e);
+        return None;
+    }
+    eprintln!("{}", message!(AutoStarted, root.display()));
+    let started = Instant::now();
+    loop {
+        thread::sleep(AUTO_START_POLL);
+        match runtime::ping(&root) {
+            Ok(true) => return runtime::find_server(&root),
+            Ok(false) => {}
+            // Not listening yet
+            Err(_) if started.elapsed() < AUTO_START_TIMEOUT => {}
+            Err(e) => {
+                let pid = runtime::server_pid(&root).map_or_else(|| String::from("?"), |pid| pid.to_string());
+                eprintln!("{}", message!(ServerNotResponding, root.display(), pid, e));
+                eprintln!("{}", message!(SeeLog, runtime::log_path(&root).display()));
+                return None;
+            }
+        }
+    }
+}
+
+// Tells the user that the server for `root` stopped answering and offers to
+// replace it with a new one.
+fn recover_dead_server(root: &Path, error: Error) {
+    let pid = runtime::server_pid(root);
+    let pid_str = pid.map_or_else(|| String::from("?"), |pid| pid.to_string());
+    eprintln!("{}", message!(ServerNotResponding, root.display(), pid_str, error));
+    if !io::stdin().is_terminal() {
+        eprintln!("{}", message!(RestartHint, pid_str, root.display()));
+        return;
+    }
+    print!("{} ", message!(RestartPrompt));
+    let _ = io::stdout().flush();
+    let mut answer = String::new();
+    if io::stdin().read_line(&mut answer).is_err() || !answer.trim().eq_ignore_ascii_case("y") {
+        return;
+    }
+    runtime::clean_up(root, pid);
+    match runtime::start_server(root) {
+        Ok(()) => eprintln!("{}", message!(ServerRestarted, root.display())),
+        Err(e) => eprintln!("{}", e),
+    }
+}
+
+#[derive(PartialEq)]
+enum Replies {
+    // The server finished one batch, more may follow
+    More,
+    Done,
+}
+
+// Prints the frames read from one stream as they arrive, until a server
+// marks the end of its replies. A closed stream counts as the end of
+// everything.
+fn read_replies(reader: &mut impl BufRead, protocol: Protocol, encoding: Encoding, printer: &mut Printer, trace_report: &mut TraceReport) -> Replies {
+    loop {
+        let frames = match protocol.read_frame(reader, &encoding) {
+            None => {
+                printer.end(false);
+                return Replies::Done;
+            }
+            Some(Frame::Compressed(data)) => match decompress_frames(&data, &encoding) {
+                Ok(frames) => frames,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return Replies::Done;
+                }
+            },
+            Some(frame) => vec![frame],
+        };
+        for frame in frames {
+            match frame {
+                Frame::EndOfResults { last: true } => {
+                    printer.end(true);
+                    return Replies::Done;
+                }
+                Frame::EndOfResults { last: false } => return Replies::More,
+                Frame::ResultLine(line) => {
+                    printer.line(&line);
+                    // --quiet has its answer with the first result
+                    if printer.answered() {
+                        return Replies::Done;
+                    }
+                }
+                Frame::Error(message) => printer.notice("error", &message),
+                Frame::Warning(message) => printer.notice("warning", &message),
+                Frame::Progress(message) => printer.notice("progress", &message),
+                Frame::Trace { id, server, stage, micros } => trace_report.add(id, server, stage, micros),
+                Frame::QueryStats { id, server, scanned, skipped, matches, micros } => trace_report.add_query(id, server, QueryCounts { scanned, skipped, matches }, micros),
+                Frame::Stats(stats) => printer.stats(&stats),
+                Frame::Version { release, protocol } => printer.notice("version", &message!(ServerVersion, release, protocol, RELEASE, PROTOCOL_VERSION)),
+                // Not nested, and this client opens no sessions
+                Frame::Compressed(_) | Frame::Tagged { .. } => {}
+            }
+        }
+    }
+}
+
+// The hanoi command line, main.rs does nothing but call it.
+pub fn run() -> ExitCode {
+    messages::init_locale_from_env();
+    let mut args = Cli::parse().into_args();
+    match args.mode {
+        OperatingMode::Server if args.detach => detach_server(&args),
+        OperatingMode::Server => server_main(&args),
+        OperatingMode::Estimate => estimate::estimate_main(&args),
+        OperatingMode::Filters => filters::filters_main(&args),
+        OperatingMode::Explain => filters::explain_main(&args),
+        OperatingMode::Lsp => lsp::lsp_main(&args),
+        OperatingMode::Client if args.stdio => {
+            stdio::stdio_main(&args);
+            ExitCode::SUCCESS
+        }
+        OperatingMode::Client => {
+            config::apply(&OsVfs, &mut args);
+            client_main(&mut args)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // An index of `text` alone at /src/<name>, as a worker loads it.
+    fn index_of(name: &str, text: &str) -> (Indexer2, Arc<Path>) {
+        let key: Arc<Path> = Arc::from(Path::new("/src").join(name));
+        let metadata = VfsMetadata { is_dir: false, is_file: true, is_symlink: false, len: text.len() as u64, modified: None, file_id: None };
+        let mut indexer = Indexer2::default();
+        indexer.symbols.set(Arc::clone(&key), extract_symbols(&key, text));
+        let content = indexer.contents.insert(text.to_string(), Compression::None);
+        indexer.files.insert(Arc::clone(&key), IndexedFile::new(content, &metadata));
+        (indexer, key)
+    }
+
+    fn search(name: &str, text: &str, term: &str, set: impl FnOnce(&mut Args)) -> Vec<String> {
+        let (indexer, key) = index_of(name, text);
+        let mut args = Cli::try_parse_remote(Vec::new()).unwrap();
+        args.term = Some(term.to_string());
+        set(&mut args);
+        let Searched::Found(found) = indexer.search_file(&args, &[term], &Matcher::new(&[term]).unwrap(), &key) else {
+            panic!("{} was skipped", key.display());
+        };
+        let mut lines = Vec::new();
+        MatchLines { args: &args, writer: &mut lines }.found(&key, found);
+        String::from_utf8(lines).unwrap().lines().map(String::from).collect()
+    }
+
+    #[test]
+    fn strips_carriage_returns() {
+        let lines = search("a.rs", "fn a() {}\r\nlet needle = 1;\r\n", "needle", |_| {});
+        assert_eq!(lines, ["/src/a.rs:2: let needle = 1;"]);
+    }
+
+    #[test]
+    fn finds_the_last_line_without_a_line_break() {
+        let lines = search("a.rs", "one\ntwo needle", "needle", |_| {});
+        assert_eq!(lines, ["/src/a.rs:2: two needle"]);
+    }
+
+    #[test]
+    fn checks_every_hit_of_a_line_for_whole_words() {
+        let lines = search("a.rs", "needles and needle\nneedles\n", "needle", |args| args.word = true);
+        assert_eq!(lines, ["/src/a.rs:1: needles and needle"]);
+        let lines = search("a.rs", "needles and needle\n", "needle", |args| {
+            args.word = true;
+            args.json = true;
+        });
+        let found: serde_json::Value = serde_json::from_str(&lines[0]).unwrap();
+        assert_eq!(found["submatches"], json!([{ "start": 12, "end": 18, "absolute_start": 12, "absolute_end": 18 }]));
+    }
+
+    #[test]
+    fn keeps_definitions_only() {
+        let text = "fn needle() {}\n\nfn main() {\n    needle();\n}\n";
+        let lines = search("a.rs", text, "needle", |args| args.definitions_only = true);
+        assert_eq!(lines, ["/src/a.rs:1: fn needle() {}"]);
+        assert_eq!(search("a.rs", text, "needle", |_| {}).len(), 2);
+    }
+    #[test]
+    fn drops_the_path_a_file_was_renamed_from() {
+        for (mode, paths) in [(RenameMode::From, vec!["/src/a.rs"]), (RenameMode::Both, vec!["/src/a.rs", "/src/b.rs"])] {
+            let (mut indexer, key) = index_of("a.rs", "fn a() {}\n");
+            indexer.root = PathBuf::from("/src");
+            parse_filter("*.rs", &mut indexer.filters);
+            let mut event = Event::new(EventKind::Modify(ModifyKind::Name(mode)));
+            event.paths = paths.into_iter().map(PathBuf::from).collect();
+            indexer.handle_event(&event);
+            assert!(!indexer.files.contains_key(&key), "{:?}", mode);
+        }
+    }
+}
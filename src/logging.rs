@@ -0,0 +1,61 @@
+use crate::error::Error;
+
+use bincode::{Decode, Encode};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use tracing::level_filters::LevelFilter;
+
+use std::{
+    fs::{self, OpenOptions},
+    io,
+    path::Path,
+    sync::Mutex,
+};
+
+// What servers log, from --log-level. Every line has a timestamp and a level,
+// and lines logged while a request is answered carry its span:
+//   2024-05-01T10:00:00.000000Z  INFO request{trace_id=... term="main" files=18}: answered elapsed_micros=412
+// Watcher events and protocol details are logged at debug.
+#[derive(Encode, Decode, Serialize, Deserialize, ValueEnum, Clone, Copy, PartialEq, Debug, Default)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    #[default]
+    Info,
+    Debug,
+    Trace,
+}
+
+impl From<LogLevel> for LevelFilter {
+    fn from(level: LogLevel) -> LevelFilter {
+        match level {
+            LogLevel::Error => LevelFilter::ERROR,
+            LogLevel::Warn => LevelFilter::WARN,
+            LogLevel::Info => LevelFilter::INFO,
+            LogLevel::Debug => LevelFilter::DEBUG,
+            LogLevel::Trace => LevelFilter::TRACE,
+        }
+    }
+}
+
+// Logs to stdout, or appends to `log_file` without colors. A server started
+// with --detach has its stdout in its log already.
+pub fn init(level: LogLevel, log_file: Option<&Path>) -> Result<(), Error> {
+    let builder = tracing_subscriber::fmt().with_max_level(level);
+    let Some(log_file) = log_file else {
+        builder.init();
+        return Ok(());
+    };
+    if let Some(dir) = log_file.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).map_err(|e| Error::Write(dir.to_path_buf(), e))?;
+    }
+    let file = OpenOptions::new().create(true).append(true).open(log_file).map_err(|e| Error::Write(log_file.to_path_buf(), e))?;
+    builder.with_ansi(false).with_writer(Mutex::new(file)).init();
+    Ok(())
+}
+
+// --no-daemon builds its index in the client, whose output is the results
+// alone. Only what went wrong is logged, to stderr.
+pub fn init_one_shot() {
+    tracing_subscriber::fmt().with_max_level(LevelFilter::WARN).with_writer(io::stderr).init();
+}
@@ -0,0 +1,331 @@
+use crate::{
+    auth,
+    messages::message,
+    output::{split_match, OutputFormat},
+    protocol::{Frame, RELEASE},
+    runtime, transport,
+    vfs::{OsVfs, Vfs},
+    words::WordChars,
+    Args, EXIT_ERROR,
+};
+
+use rand::Rng;
+use serde_json::{json, Value};
+
+use std::{
+    collections::HashMap,
+    env,
+    io::{self, BufRead, Write},
+    path::PathBuf,
+    process::ExitCode,
+    sync::Arc,
+};
+
+// JSON-RPC error codes of the protocol.
+const METHOD_NOT_FOUND: i64 = -32601;
+const REQUEST_FAILED: i64 = -32803;
+
+type Reply = Result<Value, (i64, String)>;
+
+// Speaks the Language Server Protocol over stdin and stdout, so any editor
+// can use the index of the server for its workspace:
+//   workspace/symbol         the symbols named exactly like the query
+//   textDocument/references  every whole-word match of the word at the
+//                            cursor, declarations and comments included
+//   hanoi/search             {"query": "term", "word": false} is answered
+//                            with a Location and the "text" of every match
+// The word at the cursor is read from the text the editor has open, which
+// is sent whole on every change, or from disk for files it doesn't.
+// The server for the workspace has to be running, requests fail with the
+// message of the client otherwise. As the protocol asks, it exits with an
+// error unless told to shut down before the exit or the end of stdin.
+pub fn lsp_main(args: &Args) -> ExitCode {
+    let mut session = Session { args: args.clone(), root: env::current_dir().unwrap_or_default(), vfs: Arc::new(OsVfs), documents: HashMap::new() };
+    let mut input = io::stdin().lock();
+    let mut output = io::stdout().lock();
+    let mut shut_down = false;
+    while let Some(message) = read_message(&mut input) {
+        let id = message.get("id").cloned();
+        let params = &message["params"];
+        let reply = match message["method"].as_str().unwrap_or_default() {
+            "initialize" => Ok(session.initialize(params)),
+            "shutdown" => {
+                shut_down = true;
+                Ok(Value::Null)
+            }
+            "exit" => break,
+            "textDocument/didOpen" | "textDocument/didChange" => {
+                session.edit(params);
+                Ok(Value::Null)
+            }
+            "textDocument/didClose" => {
+                session.close(params);
+                Ok(Value::Null)
+            }
+            "workspace/symbol" => session.workspace_symbol(params),
+            "textDocument/references" => session.references(params),
+            "hanoi/search" => session.search(params),
+            method => Err((METHOD_NOT_FOUND, format!("unknown method {}", method))),
+        };
+        // Notifications such as initialized and didOpen get no reply
+        let Some(id) = id else {
+            continue;
+        };
+        let reply = match reply {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err((code, message)) => json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } }),
+        };
+        if write_message(&mut output, &reply).is_err() {
+            return ExitCode::from(EXIT_ERROR);
+        }
+    }
+    if shut_down {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::from(EXIT_ERROR)
+    }
+}
+
+struct Session {
+    args: Args,
+    // The workspace, whose server answers
+    root: PathBuf,
+    // Where the documents of the editor are read from
+    vfs: Arc<dyn Vfs>,
+    // The text of the documents the editor has open, by path
+    documents: HashMap<PathBuf, String>,
+}
+
+impl Session {
+    fn initialize(&mut self, params: &Value) -> Value {
+        let root = params["rootUri"]
+            .as_str()
+            .and_then(path_from_uri)
+            .or_else(|| params["rootPath"].as_str().map(PathBuf::from))
+            .or_else(|| params["workspaceFolders"][0]["uri"].as_str().and_then(path_from_uri));
+        if let Some(root) = root {
+            self.root = root;
+        }
+        json!({
+            // Full, the whole text of a document with every change
+            "capabilities": { "textDocumentSync": 1, "workspaceSymbolProvider": true, "referencesProvider": true },
+            "serverInfo": { "name": "hanoi", "version": RELEASE },
+        })
+    }
+
+    fn edit(&mut self, params: &Value) {
+        let text = params["textDocument"]["text"].as_str().or_else(|| params["contentChanges"].as_array()?.last()?["text"].as_str());
+        if let (Some(path), Some(text)) = (params["textDocument"]["uri"].as_str().and_then(path_from_uri), text) {
+            self.documents.insert(path, text.to_string());
+        }
+    }
+
+    fn close(&mut self, params: &Value) {
+        if let Some(path) = params["textDocument"]["uri"].as_str().and_then(path_from_uri) {
+            self.documents.remove(&path);
+        }
+    }
+
+    fn workspace_symbol(&self, params: &Value) -> Reply {
+        let name = params["query"].as_str().unwrap_or_default();
+        if name.is_empty() {
+            return Ok(json!([]));
+        }
+        let mut args = self.args.clone();
+        args.symbol = Some(name.to_string());
+        let lines = self.query(args, false)?;
+        let symbols = lines.iter().filter_map(|line| {
+            let (path, line_num, symbol) = split_match(line)?;
+            let (kind, name) = symbol.split_once(' ')?;
+            let start = json!({ "line": line_num.parse::<u64>().ok()?.saturating_sub(1), "character": 0 });
+            Some(json!({
+                "name": name,
+                "kind": symbol_kind(kind),
+                "location": { "uri": uri_from_path(path), "range": { "start": start, "end": start } },
+            }))
+        });
+        Ok(Value::Array(symbols.collect()))
+    }
+
+    fn references(&self, params: &Value) -> Reply {
+        let path = params["textDocument"]["uri"].as_str().and_then(path_from_uri).ok_or((REQUEST_FAILED, String::from("no file:// textDocument.uri")))?;
+        let read;
+        let text = match self.documents.get(&path) {
+            Some(text) => text,
+            None => {
+                read = self.vfs.read_to_string(&path).map_err(|e| (REQUEST_FAILED, format!("{}: {}", path.display(), e)))?;
+                &read
+            }
+        };
+        let line_num = params["position"]["line"].as_u64().unwrap_or_default() as usize;
+        let character = params["position"]["character"].as_u64().unwrap_or_default() as usize;
+        let Some(word) = text.lines().nth(line_num).and_then(|line| word_at(line, character, WordChars::for_path(&path))) else {
+            return Ok(json!([]));
+        };
+        let mut args = self.args.clone();
+        args.term = Some(word.to_string());
+        args.word = true;
+        let locations = self.matches(args)?.into_iter().map(|(location, _)| location);
+        Ok(Value::Array(locations.collect()))
+    }
+
+    fn search(&self, params: &Value) -> Reply {
+        let term = params["query"].as_str().unwrap_or_default();
+        if term.is_empty() {
+            return Ok(json!([]));
+        }
+        let mut args = self.args.clone();
+        args.term = Some(term.to_string());
+        args.word = params["word"].as_bool().unwrap_or_default();
+        let matches = self.matches(args)?.into_iter().map(|(mut location, text)| {
+            location["text"] = json!(text);
+            location
+        });
+        Ok(Value::Array(matches.collect()))
+    }
+
+    // The Location of every match of `args` where the server found it, and
+    // the text of its line.
+    fn matches(&self, args: Args) -> Result<Vec<(Value, String)>, (i64, String)> {
+        let records = self.query(args, true)?;
+        let matches = records.iter().filter_map(|record| serde_json::from_str::<Value>(record).ok()).flat_map(|found| {
+            let (Some(path), Some(line), Some(text)) = (found["path"].as_str(), found["line"].as_u64(), found["text"].as_str()) else {
+                return Vec::new();
+            };
+            let submatches = found["submatches"].as_array().map(Vec::as_slice).unwrap_or_default();
+            submatches
+                .iter()
+                .filter_map(|submatch| {
+                    let (start, end) = (submatch["start"].as_u64()? as usize, submatch["end"].as_u64()? as usize);
+                    Some((location(path, line, text, start, end.checked_sub(start)?), text.to_string()))
+                })
+                .collect()
+        });
+        Ok(matches.collect())
+    }
+
+    // The result lines of `args` from the server for the workspace. Matches
+    // are "path:line: text", or JSON records with the offsets of every match
+    // in the line with `offsets`.
+    fn query(&self, mut args: Args, offsets: bool) -> Result<Vec<String>, (i64, String)> {
+        args.json = offsets;
+        args.fzf = false;
+        args.format = OutputFormat::Plain;
+        let Some((address, _)) = runtime::find_server(&self.root) else {
+            return Err((REQUEST_FAILED, message!(NoServer)));
+        };
+        let mut lines = Vec::new();
+        let mut error = None;
+        transport::forward(args, rand::thread_rng().gen(), &runtime::socket_name(&address), auth::read_token(&address), |frame| {
+            match frame {
+                Frame::ResultLine(line) if !line.trim().is_empty() => lines.push(line.trim_end().to_string()),
+                Frame::Error(message) => error = Some(message.clone()),
+                _ => {}
+            }
+            true
+        });
+        error.map_or(Ok(lines), |message| Err((REQUEST_FAILED, message)))
+    }
+}
+
+// A message is a Content-Length header, an empty line and that many bytes of
+// JSON. None once the editor closed stdin.
+fn read_message(input: &mut impl BufRead) -> Option<Value> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header).ok()? == 0 {
+            return None;
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+    let mut body = vec![0; length?];
+    input.read_exact(&mut body).ok()?;
+    // Without a method it is left unanswered
+    Some(serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+fn write_message(output: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+// The range of `len` bytes at `offset` of the match on line `line_num` of
+// `path`. LSP counts characters in UTF-16 code units, from line 0.
+fn location(path: &str, line_num: u64, text: &str, offset: usize, len: usize) -> Value {
+    let line = line_num.saturating_sub(1);
+    let start = text.get(..offset).unwrap_or_default().encode_utf16().count();
+    let end = start + text.get(offset..offset + len).unwrap_or_default().encode_utf16().count();
+    json!({
+        "uri": uri_from_path(path),
+        "range": { "start": { "line": line, "character": start }, "end": { "line": line, "character": end } },
+    })
+}
+
+// The identifier around UTF-16 position `character` of `line`.
+fn word_at(line: &str, character: usize, word_chars: WordChars) -> Option<&str> {
+    let mut units = 0;
+    let cursor = line.char_indices().find(|(_, c)| {
+        units += c.len_utf16();
+        units > character
+    })?.0;
+    let start = line[..cursor].rfind(|c| !word_chars.contains(c)).map_or(0, |before| before + line[before..].chars().next().map_or(1, char::len_utf8));
+    let end = line[cursor..].find(|c| !word_chars.contains(c)).map_or(line.len(), |after| cursor + after);
+    Some(&line[start..end]).filter(|word| !word.is_empty())
+}
+
+// The kinds of symbols.rs as LSP SymbolKind numbers.
+fn symbol_kind(kind: &str) -> u8 {
+    match kind {
+        "class" => 5,
+        "enum" => 10,
+        "interface" | "trait" => 11,
+        "function" | "macro" => 12,
+        "struct" => 23,
+        "type" => 26,
+        "impl" => 19,
+        _ => 13,
+    }
+}
+
+fn path_from_uri(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?;
+    // file:///C:/src on Windows
+    let path = if cfg!(windows) { path.trim_start_matches('/') } else { path };
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut rest = path.as_bytes();
+    while let Some((&byte, after)) = rest.split_first() {
+        let escaped = (byte == b'%').then(|| after.get(..2)).flatten().and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match escaped {
+            Some(decoded) => {
+                bytes.push(decoded);
+                rest = &after[2..];
+            }
+            None => {
+                bytes.push(byte);
+                rest = after;
+            }
+        }
+    }
+    String::from_utf8(bytes).ok().map(PathBuf::from)
+}
+
+fn uri_from_path(path: &str) -> String {
+    let path = path.replace('\\', "/");
+    let mut uri = String::from(if path.starts_with('/') { "file://" } else { "file:///" });
+    for byte in path.bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-._~:".contains(&byte) {
+            uri.push(byte as char);
+        } else {
+            uri.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    uri
+}
@@ -0,0 +1,57 @@
+use aho_corasick::{AhoCorasick, MatchKind};
+use memchr::memmem::{self, Finder};
+
+// Finds the terms of a query in the text of files for Indexer2::find. One
+// term is found with memmem, the term and those of -e with one Aho-Corasick
+// automaton, so a file is scanned once for all of them rather than once per
+// term.
+pub enum Matcher {
+    One(Box<Finder<'static>>),
+    Any(AhoCorasick),
+}
+
+// The start and length of every match in a text, left to right and not
+// overlapping. Made for every line with a match, where boxing the larger
+// one would allocate.
+#[allow(clippy::large_enum_variant)]
+pub enum Matches<'a> {
+    One(memmem::FindIter<'a, 'a>, usize),
+    Any(aho_corasick::FindIter<'a, 'a>),
+}
+
+impl Matcher {
+    // None when the automaton of the terms would be too large to build.
+    pub fn new(terms: &[&str]) -> Option<Matcher> {
+        match terms {
+            [term] => Some(Matcher::One(Box::new(Finder::new(term).into_owned()))),
+            // Where terms start at the same place the longer one is reported,
+            // for -w with terms such as foo and foo_bar
+            _ => AhoCorasick::builder().match_kind(MatchKind::LeftmostLongest).build(terms).ok().map(Matcher::Any),
+        }
+    }
+
+    pub fn is_match(&self, text: &[u8]) -> bool {
+        match self {
+            Matcher::One(finder) => finder.find(text).is_some(),
+            Matcher::Any(automaton) => automaton.is_match(text),
+        }
+    }
+
+    pub fn find_iter<'a>(&'a self, text: &'a [u8]) -> Matches<'a> {
+        match self {
+            Matcher::One(finder) => Matches::One(finder.find_iter(text), finder.needle().len()),
+            Matcher::Any(automaton) => Matches::Any(automaton.find_iter(text)),
+        }
+    }
+}
+
+impl Iterator for Matches<'_> {
+    type Item = (usize, usize);
+
+    fn next(&mut self) -> Option<(usize, usize)> {
+        match self {
+            Matches::One(positions, len) => positions.next().map(|start| (start, *len)),
+            Matches::Any(matches) => matches.next().map(|found| (found.start(), found.len())),
+        }
+    }
+}
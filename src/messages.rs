@@ -0,0 +1,291 @@
+use std::{
+    env,
+    fmt::Display,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+// User-facing messages. Search results, file listings and other output
+// meant for other programs never go through the catalog.
+#[derive(Clone, Copy)]
+pub enum Message {
+    AlreadyIndexed,
+    StartIndexing,
+    ConfigError,
+    UnknownSection,
+    UnreadableFiles,
+    TooManyUnreadableFiles,
+    WatchError,
+    NoServer,
+    NotPublished,
+    Hang,
+    Indexing,
+    UnknownTenant,
+    TenantAccessDenied,
+    ProtocolMismatch,
+    ListenError,
+    ConnectError,
+    AccessDenied,
+    TokenError,
+    Listening,
+    InvalidRequest,
+    // Only shown by builds without the http feature
+    #[cfg_attr(feature = "http", allow(dead_code))]
+    HttpUnavailable,
+    NeedsServer,
+    OneShotError,
+    SocketError,
+    MissingRoot,
+    ServerFailed,
+    ErrorRead,
+    ErrorWrite,
+    ErrorPipe,
+    ErrorDecode,
+    ErrorEncode,
+    ErrorTooLarge,
+    ErrorSpawn,
+    ErrorWatch,
+    ErrorConfig,
+    ServerAlive,
+    ServerNotResponding,
+    RestartHint,
+    RestartPrompt,
+    ServerRestarted,
+    Detached,
+    Stopping,
+    ServerListed,
+    NoServers,
+    AutoStarted,
+    SeeLog,
+    ConfigReloaded,
+    ChildDown,
+    ChildRestarted,
+    ChildUnresponsive,
+    ChildRestarting,
+    ChildNotAnswering,
+    SignalError,
+    ServerRunning,
+    AlreadyRebuilding,
+    NoNamedServer,
+    TooManyRequests,
+    ReleaseMismatch,
+    ReleaseDiffers,
+    ServerVersion,
+    VersionUnknown,
+    TuiUnavailable,
+    TuiSearching,
+    TuiResults,
+    TagsWritten,
+    TagsError,
+    NotTracked,
+    BlameUnavailable,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Locale {
+    English,
+    Vietnamese,
+}
+
+static LOCALE: AtomicU8 = AtomicU8::new(Locale::English as u8);
+
+impl Locale {
+    // Accepts POSIX style names such as "vi", "vi_VN" or "vi_VN.UTF-8".
+    pub fn from_name(name: &str) -> Option<Locale> {
+        let language = name.split(['_', '-', '.']).next().unwrap_or("").to_ascii_lowercase();
+        match language.as_str() {
+            "en" | "c" | "posix" => Some(Locale::English),
+            "vi" => Some(Locale::Vietnamese),
+            _ => None,
+        }
+    }
+}
+
+pub fn set_locale(locale: Locale) {
+    LOCALE.store(locale as u8, Ordering::Relaxed);
+}
+
+// HANOI_LANG wins over the usual LC_ALL > LC_MESSAGES > LANG chain.
+pub fn init_locale_from_env() {
+    for var in ["HANOI_LANG", "LC_ALL", "LC_MESSAGES", "LANG"] {
+        if let Ok(value) = env::var(var) {
+            if value.is_empty() {
+                continue;
+            }
+            if let Some(locale) = Locale::from_name(&value) {
+                set_locale(locale);
+            }
+            return;
+        }
+    }
+}
+
+fn template(message: Message, locale: Locale) -> &'static str {
+    match locale {
+        Locale::English => match message {
+            Message::AlreadyIndexed => "This directory or its parent directory has been indexed: {}",
+            Message::StartIndexing => "Start indexing: {}",
+            Message::ConfigError => "{}: {}",
+            Message::UnknownSection => "Line \"{}\" in an unknown section \"{}\"",
+            Message::UnreadableFiles => "Could not read {} files ({})",
+            Message::TooManyUnreadableFiles => "{}% of the files could not be read, which exceeds --max-unreadable-percent={}",
+            Message::WatchError => "watch error: {}",
+            Message::NoServer => "Please start the server for the current or parent directory",
+            Message::NotPublished => "No query has been published as \"{}\"",
+            Message::Hang => "Possible hang: {} has been running for {}s",
+            Message::Indexing => "Indexing {}% complete ({}), try again shortly",
+            Message::UnknownTenant => "No tenant \"{}\" is hosted here",
+            Message::TenantAccessDenied => "User \"{}\" may not query tenant \"{}\"",
+            Message::ProtocolMismatch => "The client speaks protocol version {} but the server speaks {}, restart the server with the same release",
+            Message::ListenError => "Could not listen on {}: {}",
+            Message::ConnectError => "Could not connect to {}: {}",
+            Message::AccessDenied => "The server refused the request: its token is missing or wrong",
+            Message::TokenError => "Could not write the server token: {}",
+            Message::Listening => "Listening on {}, clients need the token in {}",
+            Message::InvalidRequest => "Invalid request: {}",
+            Message::HttpUnavailable => "Not serving HTTP on {}: this build was made without the http feature",
+            Message::NeedsServer => "This request needs a running server and can't be answered with --no-daemon",
+            Message::OneShotError => "Could not search without a server: {}",
+            Message::SocketError => "Could not create the socket {}: {}",
+            Message::MissingRoot => "The server needs --root",
+            Message::ServerFailed => "Server failed: {}",
+            Message::ErrorRead => "could not read {}: {}",
+            Message::ErrorWrite => "could not write {}: {}",
+            Message::ErrorPipe => "the connection broke: {}",
+            Message::ErrorDecode => "could not decode the request: {}",
+            Message::ErrorEncode => "could not encode the request: {}",
+            Message::ErrorTooLarge => "a message of {} bytes is too large",
+            Message::ErrorSpawn => "could not start the server for {}: {}",
+            Message::ErrorWatch => "could not watch for changes: {}",
+            Message::ErrorConfig => "invalid [options] in {}",
+            Message::ServerAlive => "The server for {} answered in {}",
+            Message::ServerNotResponding => "The server for {} (process {}) is not responding: {}",
+            Message::RestartHint => "Stop process {} and start the server for {} again",
+            Message::RestartPrompt => "Stop it and start a new server? [y/N]",
+            Message::ServerRestarted => "Started a new server for {}",
+            Message::Detached => "The server runs in the background as process {}, its output goes to {}",
+            Message::Stopping => "Stopping the server for {}",
+            Message::ServerListed => "{} (process {})",
+            Message::NoServers => "No servers are running",
+            Message::AutoStarted => "Started a server for {}, waiting for it to index",
+            Message::SeeLog => "Its output is in {}",
+            Message::ConfigReloaded => "Reloaded {}",
+            Message::ChildDown => "The child server for {} exited ({}), restarting it in {}",
+            Message::ChildRestarted => "Restarted the child server for {}",
+            Message::ChildUnresponsive => "The child server for {} does not answer, killing it",
+            Message::ChildRestarting => "Results from {} are missing: its server is down and restarts in {}",
+            Message::ChildNotAnswering => "Results from {} may be missing: its server did not answer",
+            Message::SignalError => "Could not handle Ctrl-C and SIGTERM, the server will not clean up when stopped by them: {}",
+            Message::ServerRunning => "A server for {} is already running (process {})",
+            Message::AlreadyRebuilding => "The index of {} is already being rebuilt",
+            Message::NoNamedServer => "No server named {} is running",
+            Message::TooManyRequests => "The server refused the request: too many of this client's requests are waiting",
+            Message::ReleaseMismatch => "The server could not read the request of a client from release {}, it is from release {}; restart the server with the same release",
+            Message::ReleaseDiffers => "The client is from release {} but the server from release {}, restart the server with the same release",
+            Message::ServerVersion => "server: release {}, protocol {}\nclient: release {}, protocol {}",
+            Message::VersionUnknown => "The server for {} did not tell its version, it may be from an older release: {}",
+            Message::TuiUnavailable => "The tui needs a terminal",
+            Message::TuiSearching => "Searching...",
+            Message::TuiResults => "{}{} results, Enter opens, Esc quits",
+            Message::TagsWritten => "Wrote {} tags to {}",
+            Message::TagsError => "Could not write the tags to {}: {}",
+            Message::NotTracked => "Could not list the files git tracks in {}, indexing by the filters alone: {}",
+            Message::BlameUnavailable => "Not showing --blame: this build was made without the blame feature",
+        },
+        Locale::Vietnamese => match message {
+            Message::AlreadyIndexed => "Thư mục này hoặc thư mục cha của nó đã được lập chỉ mục: {}",
+            Message::StartIndexing => "Bắt đầu lập chỉ mục: {}",
+            Message::ConfigError => "{}: {}",
+            Message::UnknownSection => "Dòng \"{}\" nằm trong mục không xác định \"{}\"",
+            Message::UnreadableFiles => "Không thể đọc {} tệp ({})",
+            Message::TooManyUnreadableFiles => "Không thể đọc {}% số tệp, vượt quá --max-unreadable-percent={}",
+            Message::WatchError => "lỗi theo dõi: {}",
+            Message::NoServer => "Vui lòng khởi động server cho thư mục hiện tại hoặc thư mục cha",
+            Message::NotPublished => "Không có truy vấn nào được công bố với tên \"{}\"",
+            Message::Hang => "Có thể bị treo: {} đã chạy {} giây",
+            Message::Indexing => "Đã lập chỉ mục {}% ({}), vui lòng thử lại sau",
+            Message::UnknownTenant => "Không có tenant \"{}\" nào ở đây",
+            Message::TenantAccessDenied => "Người dùng \"{}\" không được truy vấn tenant \"{}\"",
+            Message::ProtocolMismatch => "Máy khách dùng giao thức phiên bản {} nhưng máy chủ dùng {}, hãy khởi động lại máy chủ cùng phiên bản",
+            Message::ListenError => "Không thể lắng nghe trên {}: {}",
+            Message::ConnectError => "Không thể kết nối tới {}: {}",
+            Message::AccessDenied => "Máy chủ từ chối yêu cầu: thiếu mã xác thực hoặc mã không đúng",
+            Message::TokenError => "Không thể ghi mã xác thực của máy chủ: {}",
+            Message::Listening => "Đang lắng nghe trên {}, máy khách cần mã xác thực trong {}",
+            Message::InvalidRequest => "Yêu cầu không hợp lệ: {}",
+            Message::HttpUnavailable => "Không phục vụ HTTP trên {}: bản dựng này không có tính năng http",
+            Message::NeedsServer => "Yêu cầu này cần máy chủ đang chạy, không thể trả lời với --no-daemon",
+            Message::OneShotError => "Không thể tìm kiếm khi không có máy chủ: {}",
+            Message::SocketError => "Không thể tạo socket {}: {}",
+            Message::MissingRoot => "Máy chủ cần tham số --root",
+            Message::ServerFailed => "Máy chủ gặp lỗi: {}",
+            Message::ErrorRead => "không thể đọc {}: {}",
+            Message::ErrorWrite => "không thể ghi {}: {}",
+            Message::ErrorPipe => "kết nối bị ngắt: {}",
+            Message::ErrorDecode => "không thể giải mã yêu cầu: {}",
+            Message::ErrorEncode => "không thể mã hoá yêu cầu: {}",
+            Message::ErrorTooLarge => "thông điệp {} byte quá lớn",
+            Message::ErrorSpawn => "không thể khởi động máy chủ cho {}: {}",
+            Message::ErrorWatch => "không thể theo dõi thay đổi: {}",
+            Message::ErrorConfig => "[options] không hợp lệ trong {}",
+            Message::ServerAlive => "Máy chủ cho {} đã trả lời sau {}",
+            Message::ServerNotResponding => "Máy chủ cho {} (tiến trình {}) không phản hồi: {}",
+            Message::RestartHint => "Hãy dừng tiến trình {} rồi khởi động lại máy chủ cho {}",
+            Message::RestartPrompt => "Dừng nó và khởi động máy chủ mới? [y/N]",
+            Message::ServerRestarted => "Đã khởi động máy chủ mới cho {}",
+            Message::Detached => "Máy chủ đang chạy nền với tiến trình {}, đầu ra được ghi vào {}",
+            Message::Stopping => "Đang dừng máy chủ cho {}",
+            Message::ServerListed => "{} (tiến trình {})",
+            Message::NoServers => "Không có máy chủ nào đang chạy",
+            Message::AutoStarted => "Đã khởi động máy chủ cho {}, đang chờ lập chỉ mục",
+            Message::SeeLog => "Đầu ra của nó nằm trong {}",
+            Message::ConfigReloaded => "Đã tải lại {}",
+            Message::ChildDown => "Máy chủ con cho {} đã thoát ({}), sẽ khởi động lại sau {}",
+            Message::ChildRestarted => "Đã khởi động lại máy chủ con cho {}",
+            Message::ChildUnresponsive => "Máy chủ con cho {} không phản hồi, đang dừng nó",
+            Message::ChildRestarting => "Thiếu kết quả từ {}: máy chủ của nó đang dừng và sẽ khởi động lại sau {}",
+            Message::ChildNotAnswering => "Có thể thiếu kết quả từ {}: máy chủ của nó không trả lời",
+            Message::SignalError => "Không thể xử lý Ctrl-C và SIGTERM, máy chủ sẽ không dọn dẹp khi bị chúng dừng: {}",
+            Message::ServerRunning => "Máy chủ cho {} đang chạy (tiến trình {})",
+            Message::AlreadyRebuilding => "Chỉ mục của {} đang được xây dựng lại",
+            Message::NoNamedServer => "Không có máy chủ nào tên {} đang chạy",
+            Message::TooManyRequests => "Máy chủ từ chối yêu cầu: có quá nhiều yêu cầu của máy khách này đang chờ",
+            Message::ReleaseMismatch => "Máy chủ không đọc được yêu cầu của máy khách phiên bản {}, máy chủ thuộc phiên bản {}; hãy khởi động lại máy chủ cùng phiên bản",
+            Message::ReleaseDiffers => "Máy khách thuộc phiên bản {} nhưng máy chủ thuộc phiên bản {}, hãy khởi động lại máy chủ cùng phiên bản",
+            Message::ServerVersion => "máy chủ: phiên bản {}, giao thức {}\nmáy khách: phiên bản {}, giao thức {}",
+            Message::VersionUnknown => "Máy chủ cho {} không cho biết phiên bản, có thể nó thuộc phiên bản cũ hơn: {}",
+            Message::TuiUnavailable => "Chế độ tui cần một terminal",
+            Message::TuiSearching => "Đang tìm...",
+            Message::TuiResults => "{}{} kết quả, Enter để mở, Esc để thoát",
+            Message::TagsWritten => "Đã ghi {} tag vào {}",
+            Message::TagsError => "Không ghi được tag vào {}: {}",
+            Message::NotTracked => "Không liệt kê được các tệp git theo dõi trong {}, chỉ lập chỉ mục theo bộ lọc: {}",
+            Message::BlameUnavailable => "Không hiển thị --blame: bản dựng này không có tính năng blame",
+        },
+    }
+}
+
+pub fn format(message: Message, args: &[&dyn Display]) -> String {
+    let locale = if LOCALE.load(Ordering::Relaxed) == Locale::Vietnamese as u8 {
+        Locale::Vietnamese
+    } else {
+        Locale::English
+    };
+    let mut parts = template(message, locale).split("{}");
+    let mut result = String::from(parts.next().unwrap_or(""));
+    for (index, part) in parts.enumerate() {
+        if let Some(arg) = args.get(index) {
+            result.push_str(&arg.to_string());
+        }
+        result.push_str(part);
+    }
+    result
+}
+
+// message!(StartIndexing, path.display()) formats a catalog entry in the
+// current locale.
+macro_rules! message {
+    ($message:ident $(, $arg:expr)* $(,)?) => {
+        $crate::messages::format($crate::messages::Message::$message, &[$(&$arg as &dyn std::fmt::Display),*])
+    };
+}
+pub(crate) use message;
@@ -0,0 +1,36 @@
+use crate::{index::local_index, messages::message, protocol::Frame, replies::ReplyStream, vfs::OsVfs, Args};
+
+use std::{
+    io::{self, PipeReader},
+    path::{Path, PathBuf},
+    sync::Arc,
+    thread,
+};
+
+// Answers a query with --no-daemon: indexes `root` for this query alone and
+// sends the replies the way a server would, so the client reads them like
+// any others. Additional directories, shards and tenants of the root are
+// left out, as their servers are never started.
+pub fn search(args: &Args, root: PathBuf) -> io::Result<PipeReader> {
+    let (reader, writer) = io::pipe()?;
+    let mut args = args.clone();
+    thread::spawn(move || {
+        let mut replies = ReplyStream::new(writer, args.codec);
+        replies.end_all();
+        let Some(indexer2) = local_index(Arc::new(OsVfs), &mut args, &root) else {
+            return;
+        };
+        if let Some(symbol) = args.symbol.as_ref() {
+            indexer2.find_symbol(symbol, &mut replies);
+        } else if let Some(path) = args.outline.as_ref() {
+            indexer2.outline(Path::new(path), &mut replies);
+        } else if args.files {
+            indexer2.list_files(&args, &mut replies);
+        } else if args.term.is_some() {
+            indexer2.find(&args, &mut replies);
+        } else {
+            let _ = replies.send(Frame::Error(message!(NeedsServer)));
+        }
+    });
+    Ok(reader)
+}
@@ -0,0 +1,109 @@
+use bincode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+
+use std::{fmt, str::FromStr, time::Duration};
+
+// Byte counts accepted as "512", "64K", "10MB", "2G", ... (powers of 1024).
+#[derive(Encode, Decode, Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct ByteSize(pub u64);
+
+// Durations accepted as "500ms", "30s", "5m", "1h"; a bare number is seconds.
+#[derive(Encode, Decode, Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct HumanDuration(pub Duration);
+
+fn split_number(value: &str) -> Result<(f64, String), String> {
+    let value = value.trim();
+    let unit_start = value.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(value.len());
+    let (number, unit) = value.split_at(unit_start);
+    if number.is_empty() {
+        return Err(format!("\"{}\" does not start with a number", value));
+    }
+    let number = number.parse::<f64>().map_err(|_| format!("\"{}\" is not a valid number", number))?;
+    Ok((number, unit.trim().to_ascii_lowercase()))
+}
+
+impl FromStr for ByteSize {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<ByteSize, String> {
+        let (number, unit) = split_number(value)?;
+        let multiplier: u64 = match unit.as_str() {
+            "" | "b" => 1,
+            "k" | "kb" | "kib" => 1 << 10,
+            "m" | "mb" | "mib" => 1 << 20,
+            "g" | "gb" | "gib" => 1 << 30,
+            "t" | "tb" | "tib" => 1 << 40,
+            _ => return Err(format!("unknown size unit \"{}\" (expected B, K, M, G or T)", unit)),
+        };
+        Ok(ByteSize((number * multiplier as f64) as u64))
+    }
+}
+
+impl fmt::Display for ByteSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const UNITS: [&str; 5] = ["B", "K", "M", "G", "T"];
+        let mut value = self.0 as f64;
+        let mut unit = 0;
+        while value >= 1024.0 && unit < UNITS.len() - 1 {
+            value /= 1024.0;
+            unit += 1;
+        }
+        if unit == 0 {
+            write!(f, "{}B", self.0)
+        } else {
+            write!(f, "{:.1}{}", value, UNITS[unit])
+        }
+    }
+}
+
+impl FromStr for HumanDuration {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<HumanDuration, String> {
+        let (number, unit) = split_number(value)?;
+        let seconds = match unit.as_str() {
+            "ms" => number / 1000.0,
+            "" | "s" => number,
+            "m" | "min" => number * 60.0,
+            "h" => number * 3600.0,
+            "d" => number * 86400.0,
+            _ => return Err(format!("unknown duration unit \"{}\" (expected ms, s, m, h or d)", unit)),
+        };
+        Ok(HumanDuration(Duration::from_secs_f64(seconds)))
+    }
+}
+
+impl fmt::Display for HumanDuration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // The largest unit the duration is a whole number of
+        const UNITS: [(u128, &str); 5] = [(86_400_000, "d"), (3_600_000, "h"), (60_000, "m"), (1000, "s"), (1, "ms")];
+        let millis = self.0.as_millis();
+        let (size, unit) = UNITS.into_iter().find(|(size, _)| millis >= *size && millis.is_multiple_of(*size)).unwrap_or((1, "ms"));
+        write!(f, "{}{}", millis / size, unit)
+    }
+}
+
+pub fn parse_percent(value: &str) -> Result<f64, String> {
+    let number = value.trim().trim_end_matches('%').trim();
+    match number.parse::<f64>() {
+        Ok(percent) if (0.0..=100.0).contains(&percent) => Ok(percent),
+        _ => Err(format!("\"{}\" is not a percentage between 0 and 100", value)),
+    }
+}
+
+pub fn parse_bool(value: &str) -> Result<bool, String> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "true" | "yes" | "on" | "1" => Ok(true),
+        "false" | "no" | "off" | "0" => Ok(false),
+        _ => Err(format!("\"{}\" is not true or false", value)),
+    }
+}
+
+// Parses `value` for the option `key`, naming the key in the error so users
+// can find the offending flag or config line.
+pub fn parse_option<T, F>(key: &str, value: &str, parse: F) -> Result<T, String>
+where
+    F: FnOnce(&str) -> Result<T, String>,
+{
+    parse(value).map_err(|e| format!("invalid value for {}: {}", key, e))
+}
@@ -0,0 +1,652 @@
+use crate::{
+    blame::Blamer,
+    preview::Previewer,
+    stats::ServerStats,
+    vfs::{OsVfs, Vfs},
+    EXIT_ERROR, EXIT_NO_RESULTS,
+};
+
+use bincode::{Decode, Encode};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::{Display, Write as _},
+    io::{self, Write},
+    mem,
+    path::{Component, Path, PathBuf},
+    process::ExitCode,
+    sync::Arc,
+    time::{Instant, UNIX_EPOCH},
+};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ResultKind {
+    // "path:line: text" lines from searches and symbol lookups
+    Matches,
+    // One path per line from --files
+    Files,
+    // Anything else is printed as is
+    Other,
+}
+
+// How matches are printed, from --format.
+#[derive(Encode, Decode, Serialize, Deserialize, ValueEnum, Clone, Copy, PartialEq, Debug, Default)]
+pub enum OutputFormat {
+    // "path:line: text" with the paths as the servers have them
+    #[default]
+    Plain,
+    // "path:line:col:text" with paths relative to the working directory, as
+    // Vim's :grep expects with grepformat=%f:%l:%c:%m
+    Vimgrep,
+}
+
+// The order of results, from --sort. Paths sort by name, the other keys
+// keep the matches of a file together.
+#[derive(Encode, Decode, Serialize, Deserialize, ValueEnum, Clone, Copy, PartialEq, Debug)]
+pub enum SortKey {
+    Path,
+    Mtime,
+    Size,
+    // The number of matches in the file
+    Matches,
+}
+
+// Prints what the servers send back to the client. With verbose labels the
+// results are buffered so each one can be announced as "match 3 of 40".
+//
+// With --json every line is a JSON object with a "type", so tools don't have
+// to split on ':', which Windows drive letters contain too:
+//   {"server":"/src/project","term":"main","type":"begin"}
+//   {"col":4,"line":12,"path":"src/main.rs","server":"/src/project","text":"fn main() {","type":"match"}
+//   {"complete":true,"server":"/src/project","type":"end"}
+//   {"elapsed_micros":812,"files":1,"matches":1,"type":"summary"}
+// Match records also have the byte offsets of every match of the term in
+// "text", and in the file with "absolute_offset" the one of the line:
+//   "absolute_offset":310,"submatches":[{"absolute_end":317,"absolute_start":313,"end":7,"start":3}]
+// Files are "file" records, errors and warnings "error" and "warning" ones.
+// With --files --long the file records also have the metadata the index has:
+//   {"extension":"rs","lines":120,"mtime":1700000000,"path":"/src/project/src/main.rs","server":"/src/project","size":3071,"type":"file"}
+// "mtime" is in seconds since the Unix epoch, "lines" null for files a lazy
+// server hasn't read yet.
+// "col" is the 1-based byte offset of the first match, null for results sent
+// without offsets as symbols are. "server" is the server the client asked.
+pub struct Printer {
+    kind: ResultKind,
+    verbose_labels: bool,
+    ascii: bool,
+    preview: Option<Previewer>,
+    blame: Option<Blamer>,
+    buffered: Vec<String>,
+    json: Option<JsonRecords>,
+    // The working directory paths are made relative to for vimgrep
+    vimgrep: Option<PathBuf>,
+    null: bool,
+    // With -l, the paths printed so far
+    files_with_matches: Option<HashSet<String>>,
+    // With --heading, the path of the matches printed last
+    heading: Option<Option<String>>,
+    quiet: bool,
+    // With --fzf, only candidates go to stdout
+    fzf: bool,
+    // With --sort, the key and the results held back until all servers
+    // answered
+    sort: Option<(SortKey, bool)>,
+    sorted: Vec<String>,
+    results: usize,
+    failed: bool,
+    vfs: Arc<dyn Vfs>,
+    // Where results go, stdout but in tests
+    out: Box<dyn Write>,
+}
+
+struct JsonRecords {
+    server: String,
+    started: Instant,
+    matches: usize,
+    files: HashSet<String>,
+}
+
+impl Printer {
+    pub fn new(kind: ResultKind, verbose_labels: bool, ascii: bool, preview: Option<Previewer>) -> Printer {
+        Printer {
+            kind,
+            verbose_labels,
+            ascii,
+            preview,
+            blame: None,
+            buffered: Vec::new(),
+            json: None,
+            vimgrep: None,
+            null: false,
+            files_with_matches: None,
+            heading: None,
+            quiet: false,
+            fzf: false,
+            sort: None,
+            sorted: Vec::new(),
+            results: 0,
+            failed: false,
+            vfs: Arc::new(OsVfs),
+            out: Box::new(io::stdout()),
+        }
+    }
+
+    pub fn format(mut self, format: OutputFormat, cwd: &Path) -> Printer {
+        if format == OutputFormat::Vimgrep {
+            self.vimgrep = Some(cwd.to_path_buf());
+        }
+        self
+    }
+
+    // Ends the paths of --files with NUL, for -0.
+    pub fn null(mut self, null: bool) -> Printer {
+        self.null = null;
+        self
+    }
+
+    // Takes the results for the paths of -l, sent by the servers as JSON
+    // strings, and prints each path once.
+    pub fn files_with_matches(mut self, files_with_matches: bool) -> Printer {
+        self.files_with_matches = files_with_matches.then(HashSet::new);
+        self
+    }
+
+    // Prints the path once above the matches in it, for --heading.
+    pub fn heading(mut self, heading: bool) -> Printer {
+        self.heading = heading.then_some(None);
+        self
+    }
+
+    // Prints nothing but errors, to stderr, for --quiet.
+    pub fn quiet(mut self, quiet: bool) -> Printer {
+        self.quiet = quiet;
+        self
+    }
+
+    // Prints candidates for fzf, for --fzf: the lines of vimgrep with none
+    // of the decorations that would end up in the candidates, and notices on
+    // stderr.
+    pub fn fzf(mut self, fzf: bool, cwd: &Path) -> Printer {
+        if fzf {
+            self.fzf = true;
+            self.vimgrep = Some(cwd.to_path_buf());
+            self.json = None;
+            self.heading = None;
+            self.verbose_labels = false;
+            self.preview = None;
+            self.blame = None;
+        }
+        self
+    }
+
+    // Merges the results of all servers in the order of --sort, reversed
+    // for --reverse.
+    pub fn sort(mut self, sort: Option<SortKey>, reverse: bool) -> Printer {
+        self.sort = sort.map(|sort| (sort, reverse));
+        self
+    }
+
+    // Shows who last changed the line of each match, for --blame.
+    pub fn blame(mut self, blame: bool) -> Printer {
+        self.blame = blame.then(Blamer::default);
+        self
+    }
+
+    // Prints JSON records rather than lines, see Printer.
+    pub fn json(mut self, json: bool) -> Printer {
+        if json {
+            self.json = Some(JsonRecords { server: String::new(), started: Instant::now(), matches: 0, files: HashSet::new() });
+        }
+        self
+    }
+
+    // The request for `term` went out to `server`.
+    pub fn begin(&mut self, server: &str, term: Option<&str>) {
+        if let Some(records) = self.json.as_mut().filter(|_| !self.quiet) {
+            records.server = server.to_string();
+            let begin = json!({ "type": "begin", "server": server, "term": term });
+            self.write_line(begin);
+        }
+    }
+
+    // The server is done replying, or went away before it was when not
+    // `complete`.
+    pub fn end(&mut self, complete: bool) {
+        self.failed |= !complete;
+        if let Some(records) = self.json.as_ref().filter(|_| !self.quiet) {
+            let end = json!({ "type": "end", "server": records.server, "complete": complete });
+            self.write_line(end);
+        }
+    }
+
+    // Errors, warnings and progress reports of the servers, on stderr so
+    // only results reach pipes. JSON readers get them in the stream, unless
+    // it goes to xargs -0. The version of a server is what was asked for.
+    pub fn notice(&mut self, kind: &str, message: &str) {
+        self.failed |= kind == "error";
+        if self.quiet {
+            if kind == "error" {
+                eprintln!("{}", message);
+            }
+            return;
+        }
+        match self.json.as_ref() {
+            Some(_) if !self.null => self.write_line(json!({ "type": kind, "message": message })),
+            None if kind == "version" && !self.fzf && !self.null => self.write_line(message),
+            _ => eprintln!("{}", message),
+        }
+    }
+
+    pub fn stats(&mut self, stats: &ServerStats) {
+        if self.quiet {
+            return;
+        }
+        match self.json.as_ref() {
+            Some(_) => self.write_line(json!({ "type": "stats", "stats": stats.to_json() })),
+            None => self.write_line(stats),
+        }
+    }
+
+    pub fn line(&mut self, line: &str) {
+        let decoded;
+        let line = match self.files_with_matches.as_mut() {
+            Some(printed) => {
+                // Other servers may have had the file too, as --root
+                // directories can overlap
+                let Some(path) = serde_json::from_str::<String>(line).ok().filter(|path| printed.insert(path.clone())) else {
+                    return;
+                };
+                decoded = path;
+                decoded.as_str()
+            }
+            None => line,
+        };
+        let raw_path = self.null && self.kind == ResultKind::Files;
+        // Whitespace around the path is part of it
+        let line = if raw_path { line } else { line.trim() };
+        if line.is_empty() {
+            return;
+        }
+        self.results += 1;
+        if self.quiet {
+            return;
+        }
+        if self.sort.is_some() && self.kind != ResultKind::Other {
+            self.sorted.push(line.to_string());
+            return;
+        }
+        self.print_result(line);
+    }
+
+    fn print_result(&mut self, line: &str) {
+        let raw_path = self.null && self.kind == ResultKind::Files;
+        if raw_path {
+            let path = match self.vimgrep.as_ref() {
+                Some(cwd) => relative_to(Path::new(line), cwd).display().to_string(),
+                None => line.to_string(),
+            };
+            write!(self.out, "{}\0", path).expect("failed printing to stdout");
+            return;
+        }
+        // Editors read the text of vimgrep lines, it is left as it is
+        let annotated = if self.json.is_none() && self.vimgrep.is_none() { self.annotate(line) } else { None };
+        let shown = annotated.as_deref().unwrap_or(line);
+        if let Some(records) = self.json.as_mut() {
+            let mut record = records.record(self.kind, line);
+            if let Some(blame) = self.blame.as_mut().and_then(|blamer| blamer.line(record["path"].as_str()?, record["line"].as_u64()? as usize)) {
+                record["blame"] = json!(blame);
+            }
+            self.write_line(record);
+        } else if let Some(cwd) = self.vimgrep.as_ref() {
+            let vimgrep = match (self.kind, found_match(line)) {
+                (ResultKind::Matches, Some((path, line_num, col, text))) => format!("{}:{}:{}:{}", relative_to(Path::new(&path), cwd).display(), line_num, col.unwrap_or(1), text),
+                (ResultKind::Files, _) => relative_to(Path::new(line), cwd).display().to_string(),
+                _ => line.to_string(),
+            };
+            self.print(&vimgrep);
+        } else if self.verbose_labels && self.kind != ResultKind::Other {
+            self.buffered.push(shown.to_string());
+        } else if let (Some(last_path), ResultKind::Matches, Some((path, line_num, text))) = (self.heading.as_ref(), self.kind, split_match(shown)) {
+            if last_path.as_deref() != Some(path) {
+                if last_path.is_some() {
+                    self.write_line("");
+                }
+                self.print(path);
+                self.heading = Some(Some(path.to_string()));
+            }
+            self.print(&format!("{}: {}", line_num, text));
+            self.print_preview(shown);
+        } else {
+            self.print(shown);
+            self.print_preview(shown);
+        }
+    }
+
+    // The match with who last changed its line before the text, for --blame:
+    //   /src/main.rs:12: [Ada Lovelace 2024-05-01] fn main() {
+    fn annotate(&mut self, line: &str) -> Option<String> {
+        if self.kind != ResultKind::Matches {
+            return None;
+        }
+        let (path, line_num, text) = split_match(line)?;
+        let blame = self.blame.as_mut()?.line(path, line_num.parse().ok()?)?;
+        Some(format!("{}:{}: [{}] {}", path, line_num, blame, text))
+    }
+
+    // Whether --quiet has seen the result it waits for.
+    pub fn answered(&self) -> bool {
+        self.quiet && self.kind != ResultKind::Other && self.results > 0
+    }
+
+    pub fn exit_code(&self) -> ExitCode {
+        if self.failed {
+            ExitCode::from(EXIT_ERROR)
+        } else if self.results == 0 && self.kind != ResultKind::Other {
+            ExitCode::from(EXIT_NO_RESULTS)
+        } else {
+            ExitCode::SUCCESS
+        }
+    }
+
+    pub fn finish(&mut self) {
+        if self.quiet {
+            return;
+        }
+        for line in self.take_sorted() {
+            self.print_result(&line);
+        }
+        if let Some(records) = self.json.as_ref() {
+            let elapsed_micros = records.started.elapsed().as_micros() as u64;
+            let summary = json!({ "type": "summary", "matches": records.matches, "files": records.files.len(), "elapsed_micros": elapsed_micros });
+            self.write_line(summary);
+            return;
+        }
+        let count = self.buffered.len();
+        for (index, line) in mem::take(&mut self.buffered).iter().enumerate() {
+            self.print(&label(self.kind, index, count, line));
+            self.print_preview(line);
+        }
+        if self.verbose_labels && self.kind != ResultKind::Other {
+            let noun = if self.kind == ResultKind::Files { "files" } else { "matches" };
+            self.print(&format!("{} {} in total", count, noun));
+        }
+    }
+
+    // The results held back for --sort, in its order. The sort is stable, so
+    // the matches of a file stay in the order of their lines. Files whose
+    // metadata can't be read, as on the machine of a --connect server, come
+    // first.
+    fn take_sorted(&mut self) -> Vec<String> {
+        let (Some((sort, reverse)), lines) = (self.sort, mem::take(&mut self.sorted)) else {
+            return Vec::new();
+        };
+        let paths: Vec<String> = lines.iter().map(|line| result_path(self.kind, line)).collect();
+        let mut keys: HashMap<&str, Option<u128>> = HashMap::new();
+        for path in &paths {
+            let key = keys.entry(path).or_insert_with(|| match sort {
+                SortKey::Path => None,
+                SortKey::Mtime => self.vfs.metadata(Path::new(path)).ok().and_then(|metadata| metadata.modified?.duration_since(UNIX_EPOCH).ok()).map(|duration| duration.as_nanos()),
+                SortKey::Size => self.vfs.metadata(Path::new(path)).ok().map(|metadata| metadata.len as u128),
+                SortKey::Matches => Some(0),
+            });
+            if sort == SortKey::Matches {
+                *key = key.map(|matches| matches + 1);
+            }
+        }
+        let mut keyed: Vec<((Option<u128>, &str), String)> = paths.iter().map(|path| (keys[path.as_str()], path.as_str())).zip(lines).collect();
+        keyed.sort_by(|(a, _), (b, _)| if reverse { b.cmp(a) } else { a.cmp(b) });
+        keyed.into_iter().map(|(_, line)| line).collect()
+    }
+
+    fn print(&mut self, line: &str) {
+        if self.ascii {
+            self.write_line(to_ascii(line));
+        } else {
+            self.write_line(line);
+        }
+    }
+
+    // Panics like println! when stdout is gone.
+    fn write_line(&mut self, line: impl Display) {
+        writeln!(self.out, "{}", line).expect("failed printing to stdout");
+    }
+
+    fn print_preview(&mut self, line: &str) {
+        let Some(preview) = &self.preview else {
+            return;
+        };
+        if self.kind != ResultKind::Matches {
+            return;
+        }
+        let snippet = split_match(line).and_then(|(path, line_num, _)| preview.snippet(path, line_num.parse().ok()?));
+        for snippet_line in snippet.unwrap_or_default() {
+            self.print(&snippet_line);
+        }
+    }
+}
+
+impl JsonRecords {
+    fn record(&mut self, kind: ResultKind, line: &str) -> Value {
+        match (kind, split_match(line)) {
+            // Servers send the offsets of matches as one JSON object
+            (ResultKind::Matches, _) if line.starts_with('{') => match serde_json::from_str::<Value>(line) {
+                Ok(Value::Object(mut found)) => {
+                    self.matches += 1;
+                    self.files.insert(found.get("path").and_then(Value::as_str).unwrap_or_default().to_string());
+                    let col = first_column(&found);
+                    found.insert(String::from("type"), json!("match"));
+                    found.insert(String::from("col"), json!(col));
+                    found.insert(String::from("server"), json!(self.server));
+                    Value::Object(found)
+                }
+                _ => json!({ "type": "line", "text": line, "server": self.server }),
+            },
+            (ResultKind::Matches, Some((path, line_num, text))) => {
+                self.matches += 1;
+                self.files.insert(path.to_string());
+                json!({ "type": "match", "path": path, "line": line_num.parse::<u64>().ok(), "col": null, "text": text, "server": self.server })
+            }
+            // Servers send the metadata of --long as one JSON object
+            (ResultKind::Files, _) if line.starts_with('{') => match serde_json::from_str::<Value>(line) {
+                Ok(Value::Object(mut file)) => {
+                    self.files.insert(file.get("path").and_then(Value::as_str).unwrap_or_default().to_string());
+                    file.insert(String::from("type"), json!("file"));
+                    file.insert(String::from("server"), json!(self.server));
+                    Value::Object(file)
+                }
+                _ => json!({ "type": "line", "text": line, "server": self.server }),
+            },
+            (ResultKind::Files, _) => {
+                self.files.insert(line.to_string());
+                json!({ "type": "file", "path": line, "server": self.server })
+            }
+            _ => json!({ "type": "line", "text": line, "server": self.server }),
+        }
+    }
+}
+
+// The 1-based byte column of the first match of a match record, where the
+// server found it.
+fn first_column(found: &Map<String, Value>) -> Option<u64> {
+    found.get("submatches")?.get(0)?.get("start")?.as_u64().map(|start| start + 1)
+}
+
+// The path, line number, column and text of a match, sent as a JSON record
+// with its offsets or as "path:line: text" without them.
+fn found_match(line: &str) -> Option<(String, String, Option<u64>, String)> {
+    if !line.starts_with('{') {
+        let (path, line_num, text) = split_match(line)?;
+        return Some((path.to_string(), line_num.to_string(), None, text.to_string()));
+    }
+    let Ok(Value::Object(found)) = serde_json::from_str::<Value>(line) else {
+        return None;
+    };
+    let field = |name: &str| found.get(name).and_then(Value::as_str).map(String::from);
+    Some((field("path")?, found.get("line")?.as_u64()?.to_string(), first_column(&found), field("text")?))
+}
+
+// `path` as seen from `base`, both absolute. Paths on another Windows drive
+// have no relative form and are kept as they are.
+pub fn relative_to(path: &Path, base: &Path) -> PathBuf {
+    if !path.is_absolute() {
+        return path.to_path_buf();
+    }
+    let mut path_components = path.components().peekable();
+    let mut base_components = base.components().peekable();
+    if path_components.peek() != base_components.peek() {
+        return path.to_path_buf();
+    }
+    while path_components.peek().is_some() && path_components.peek() == base_components.peek() {
+        path_components.next();
+        base_components.next();
+    }
+    let mut relative: PathBuf = base_components.map(|_| Component::ParentDir).collect();
+    relative.extend(path_components);
+    if relative.as_os_str().is_empty() {
+        relative.push(".");
+    }
+    relative
+}
+
+// Splits "path:line: text" at the first ":<digits>: " so Windows drive
+// letters stay part of the path.
+pub fn split_match(line: &str) -> Option<(&str, &str, &str)> {
+    let bytes = line.as_bytes();
+    let mut start = 0;
+    while let Some(offset) = line[start..].find(':') {
+        let colon = start + offset;
+        let digits_end = colon + 1 + bytes[colon + 1..].iter().take_while(|b| b.is_ascii_digit()).count();
+        if digits_end > colon + 1 && line[digits_end..].starts_with(": ") {
+            return Some((&line[..colon], &line[colon + 1..digits_end], &line[digits_end + 2..]));
+        }
+        start = colon + 1;
+    }
+    None
+}
+
+// The file a result is about, for --sort: the path of a match, a listed
+// file or a JSON record.
+fn result_path(kind: ResultKind, line: &str) -> String {
+    match kind {
+        ResultKind::Files | ResultKind::Matches if line.starts_with('{') => serde_json::from_str::<Value>(line)
+            .ok()
+            .and_then(|result| result.get("path").and_then(Value::as_str).map(String::from))
+            .unwrap_or_default(),
+        ResultKind::Matches => split_match(line).map_or(line, |(path, _, _)| path).to_string(),
+        _ => line.to_string(),
+    }
+}
+
+// The result at `index` of `count` as --verbose-labels announces it, e.g.
+// "match 3 of 40, file src/foo.rs, line 12: text".
+pub fn label(kind: ResultKind, index: usize, count: usize, line: &str) -> String {
+    match kind {
+        ResultKind::Matches => match split_match(line) {
+            Some((path, line_num, text)) => format!("match {} of {}, file {}, line {}: {}", index + 1, count, path, line_num, text),
+            None => format!("match {} of {}: {}", index + 1, count, line),
+        },
+        ResultKind::Files => format!("file {} of {}: {}", index + 1, count, line),
+        ResultKind::Other => String::from(line),
+    }
+}
+
+pub fn to_ascii(line: &str) -> String {
+    let mut result = String::with_capacity(line.len());
+    for c in line.chars() {
+        if c.is_ascii() {
+            result.push(c);
+        } else {
+            let _ = write!(result, "\\u{{{:x}}}", c as u32);
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{cell::RefCell, rc::Rc};
+
+    struct Captured(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // What `printer` prints for the replies of one server.
+    fn printed(mut printer: Printer, lines: &[&str]) -> String {
+        let out = Rc::new(RefCell::new(Vec::new()));
+        printer.out = Box::new(Captured(out.clone()));
+        printer.begin("/src", Some("main"));
+        for line in lines {
+            printer.line(line);
+        }
+        printer.notice("version", "hanoi 0.1.0");
+        printer.end(true);
+        printer.finish();
+        String::from_utf8(out.take()).unwrap()
+    }
+
+    const FOUND: &str = r#"{"absolute_offset":310,"line":12,"path":"/src/main.rs","submatches":[{"absolute_end":317,"absolute_start":313,"end":7,"start":3}],"text":"fn main() {"}"#;
+
+    #[test]
+    fn json_prints_a_record_per_line() {
+        let printer = Printer::new(ResultKind::Matches, false, false, None).json(true);
+        let mut records: Vec<Value> = printed(printer, &[FOUND, "/src/lib.rs:3: pub fn main"]).lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        records.last_mut().unwrap().as_object_mut().unwrap().remove("elapsed_micros");
+        assert_eq!(
+            records,
+            [
+                json!({ "type": "begin", "server": "/src", "term": "main" }),
+                json!({
+                    "type": "match",
+                    "path": "/src/main.rs",
+                    "line": 12,
+                    "col": 4,
+                    "text": "fn main() {",
+                    "absolute_offset": 310,
+                    "submatches": [{ "absolute_end": 317, "absolute_start": 313, "end": 7, "start": 3 }],
+                    "server": "/src"
+                }),
+                json!({ "type": "match", "path": "/src/lib.rs", "line": 3, "col": null, "text": "pub fn main", "server": "/src" }),
+                json!({ "type": "version", "message": "hanoi 0.1.0" }),
+                json!({ "type": "end", "server": "/src", "complete": true }),
+                json!({ "type": "summary", "matches": 2, "files": 2 }),
+            ]
+        );
+    }
+    #[test]
+    fn vimgrep_prints_paths_relative_to_the_working_directory() {
+        let printer = Printer::new(ResultKind::Matches, false, false, None).format(OutputFormat::Vimgrep, Path::new("/src"));
+        assert_eq!(printed(printer, &[FOUND, "/src/lib.rs:3: pub fn main", "/other/main.rs:1: main"]), "main.rs:12:4:fn main() {\nlib.rs:3:1:pub fn main\n../other/main.rs:1:1:main\nhanoi 0.1.0\n");
+    }
+    #[test]
+    fn null_ends_paths_with_nul_and_keeps_their_whitespace() {
+        let printer = Printer::new(ResultKind::Files, false, false, None).null(true);
+        assert_eq!(printed(printer, &["/src/main.rs", " /src/a b.rs "]), "/src/main.rs\0 /src/a b.rs \0");
+    }
+    #[test]
+    fn fzf_prints_nothing_but_candidates() {
+        let printer = Printer::new(ResultKind::Matches, true, false, None).json(true).heading(true).fzf(true, Path::new("/src"));
+        assert_eq!(printed(printer, &[FOUND, "/src/lib.rs:3: pub fn main"]), "main.rs:12:4:fn main() {\nlib.rs:3:1:pub fn main\n");
+    }
+    #[test]
+    fn files_with_matches_prints_each_path_once() {
+        let lines = [r#""/src/main.rs""#, r#""/src/lib.rs""#, r#""/src/main.rs""#];
+        let printer = Printer::new(ResultKind::Files, false, false, None).files_with_matches(true);
+        assert_eq!(printed(printer, &lines), "/src/main.rs\n/src/lib.rs\nhanoi 0.1.0\n");
+        let printer = Printer::new(ResultKind::Files, false, false, None).files_with_matches(true).null(true);
+        assert_eq!(printed(printer, &lines), "/src/main.rs\0/src/lib.rs\0");
+    }
+}
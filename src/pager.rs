@@ -0,0 +1,53 @@
+use std::{
+    env,
+    io::{self, Write},
+    os::fd::AsRawFd,
+    process::{Child, Command, Stdio},
+};
+
+// Results printed while a Pager is alive go through $PAGER, less by default.
+// Less is told to quit right away when they fit on one screen, so short
+// results print as they would without it.
+pub struct Pager {
+    child: Child,
+    // The terminal, put back on stdout when the results are done
+    stdout: libc::c_int,
+}
+
+// Points stdout at a new pager. None when no pager could be started, the
+// results are then printed as they are.
+pub fn start() -> Option<Pager> {
+    let command = env::var("PAGER").unwrap_or_else(|_| String::from("less"));
+    if command.trim().is_empty() || command.trim() == "cat" {
+        return None;
+    }
+    let mut pager = Command::new("sh");
+    pager.arg("-c").arg(&command).stdin(Stdio::piped());
+    if env::var_os("LESS").is_none() {
+        pager.env("LESS", "FRX");
+    }
+    let mut child = pager.spawn().ok()?;
+    let stdin = child.stdin.take()?;
+    let _ = io::stdout().flush();
+    let stdout = unsafe { libc::dup(libc::STDOUT_FILENO) };
+    if stdout < 0 || unsafe { libc::dup2(stdin.as_raw_fd(), libc::STDOUT_FILENO) } < 0 {
+        let _ = child.kill();
+        let _ = child.wait();
+        return None;
+    }
+    // Quitting the pager early ends the client quietly, as it does for git
+    unsafe { libc::signal(libc::SIGPIPE, libc::SIG_DFL) };
+    Some(Pager { child, stdout })
+}
+
+impl Drop for Pager {
+    fn drop(&mut self) {
+        let _ = io::stdout().flush();
+        // The pager only sees the end of the results once stdout is closed
+        unsafe {
+            libc::dup2(self.stdout, libc::STDOUT_FILENO);
+            libc::close(self.stdout);
+        }
+        let _ = self.child.wait();
+    }
+}
@@ -0,0 +1,134 @@
+use crate::symbols::Symbol;
+
+use tree_sitter::{Language, Node, Parser};
+
+use std::path::Path;
+
+// The kind of symbol a node defines and the node holding its name.
+type Definition = fn(Node) -> Option<(&'static str, Node)>;
+
+// The symbols of `text` as its tree-sitter grammar parses them, nested in
+// the definitions around them. None for extensions without a grammar, they
+// are left to the regexes of symbols.rs.
+pub fn extract_symbols(path: &Path, text: &str) -> Option<Vec<Symbol>> {
+    let (language, definition): (Language, Definition) = match path.extension()?.to_str()? {
+        "rs" => (tree_sitter_rust::LANGUAGE.into(), rust),
+        "c" | "h" => (tree_sitter_c::LANGUAGE.into(), c),
+        "py" => (tree_sitter_python::LANGUAGE.into(), python),
+        "js" | "jsx" | "mjs" | "cjs" => (tree_sitter_javascript::LANGUAGE.into(), javascript),
+        "ts" => (tree_sitter_typescript::LANGUAGE_TYPESCRIPT.into(), javascript),
+        "tsx" => (tree_sitter_typescript::LANGUAGE_TSX.into(), javascript),
+        "go" => (tree_sitter_go::LANGUAGE.into(), go),
+        "java" => (tree_sitter_java::LANGUAGE.into(), java),
+        _ => return None,
+    };
+    let mut parser = Parser::new();
+    parser.set_language(&language).ok()?;
+    let tree = parser.parse(text, None)?;
+    let mut symbols = Vec::new();
+    // Without recursion, minified files nest deep enough to overflow a stack
+    let mut nodes = vec![(tree.root_node(), 0)];
+    while let Some((node, depth)) = nodes.pop() {
+        let mut child_depth = depth;
+        if let Some((kind, name)) = definition(node) {
+            // The line of the name, past attributes and doc comments
+            if let Ok(name_text) = name.utf8_text(text.as_bytes()) {
+                symbols.push(Symbol { name: name_text.to_string(), kind, line: name.start_position().row + 1, depth });
+                child_depth += 1;
+            }
+        }
+        let mut cursor = node.walk();
+        let children: Vec<Node> = node.named_children(&mut cursor).collect();
+        nodes.extend(children.into_iter().rev().map(|child| (child, child_depth)));
+    }
+    Some(symbols)
+}
+
+fn rust(node: Node) -> Option<(&'static str, Node)> {
+    let kind = match node.kind() {
+        "function_item" | "function_signature_item" => "function",
+        "struct_item" | "union_item" => "struct",
+        "enum_item" => "enum",
+        "trait_item" => "trait",
+        "type_item" => "type",
+        "macro_definition" => "macro",
+        // Named after the type, Foo for impl<T> Display for Foo<T>
+        "impl_item" => {
+            let implemented = node.child_by_field_name("type")?;
+            return Some(("impl", implemented.child_by_field_name("type").unwrap_or(implemented)));
+        }
+        _ => return None,
+    };
+    Some((kind, node.child_by_field_name("name")?))
+}
+
+fn c(node: Node) -> Option<(&'static str, Node)> {
+    match node.kind() {
+        "function_definition" => Some(("function", declared_name(node)?)),
+        "type_definition" => Some(("type", declared_name(node)?)),
+        // Declarations such as struct point p; have no body
+        "struct_specifier" | "union_specifier" if node.child_by_field_name("body").is_some() => Some(("struct", node.child_by_field_name("name")?)),
+        "enum_specifier" if node.child_by_field_name("body").is_some() => Some(("enum", node.child_by_field_name("name")?)),
+        "preproc_def" | "preproc_function_def" => Some(("macro", node.child_by_field_name("name")?)),
+        _ => None,
+    }
+}
+
+// The identifier at the bottom of the declarators of C, *name(int) of a
+// function returning a pointer.
+fn declared_name(node: Node) -> Option<Node> {
+    let mut declarator = node.child_by_field_name("declarator")?;
+    while let Some(inner) = declarator.child_by_field_name("declarator") {
+        declarator = inner;
+    }
+    declarator.kind().ends_with("identifier").then_some(declarator)
+}
+
+fn python(node: Node) -> Option<(&'static str, Node)> {
+    let kind = match node.kind() {
+        "function_definition" => "function",
+        "class_definition" => "class",
+        _ => return None,
+    };
+    Some((kind, node.child_by_field_name("name")?))
+}
+
+// TypeScript is a superset, its grammars have the nodes of JavaScript.
+fn javascript(node: Node) -> Option<(&'static str, Node)> {
+    let kind = match node.kind() {
+        "function_declaration" | "generator_function_declaration" | "function_signature" | "method_definition" => "function",
+        "class_declaration" | "abstract_class_declaration" => "class",
+        "interface_declaration" => "interface",
+        "type_alias_declaration" => "type",
+        "enum_declaration" => "enum",
+        // const f = () => {}
+        "variable_declarator" if node.child_by_field_name("value").is_some_and(|value| matches!(value.kind(), "arrow_function" | "function_expression" | "function")) => "function",
+        _ => return None,
+    };
+    Some((kind, node.child_by_field_name("name")?))
+}
+
+fn go(node: Node) -> Option<(&'static str, Node)> {
+    let kind = match node.kind() {
+        "function_declaration" | "method_declaration" => "function",
+        "type_spec" => match node.child_by_field_name("type")?.kind() {
+            "struct_type" => "struct",
+            "interface_type" => "interface",
+            _ => "type",
+        },
+        "type_alias" => "type",
+        _ => return None,
+    };
+    Some((kind, node.child_by_field_name("name")?))
+}
+
+fn java(node: Node) -> Option<(&'static str, Node)> {
+    let kind = match node.kind() {
+        "class_declaration" | "record_declaration" => "class",
+        "interface_declaration" | "annotation_type_declaration" => "interface",
+        "enum_declaration" => "enum",
+        "method_declaration" | "constructor_declaration" => "function",
+        _ => return None,
+    };
+    Some((kind, node.child_by_field_name("name")?))
+}
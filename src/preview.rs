@@ -0,0 +1,69 @@
+use crate::vfs::{OsVfs, Vfs};
+
+use syntect::{
+    easy::HighlightLines,
+    highlighting::{Theme, ThemeSet},
+    parsing::SyntaxSet,
+    util::{as_24_bit_terminal_escaped, LinesWithEndings},
+};
+
+use std::{env, path::Path, sync::Arc};
+
+// Lines around each match for --preview. Terminals that draw images inline
+// (kitty, iTerm2, WezTerm) all take 24-bit colors, so the snippet is syntax
+// highlighted there and printed as plain text everywhere else.
+pub struct Previewer {
+    context: usize,
+    highlighting: Option<(SyntaxSet, Theme)>,
+    vfs: Arc<dyn Vfs>,
+}
+
+fn supports_images() -> bool {
+    env::var_os("KITTY_WINDOW_ID").is_some()
+        || env::var("TERM").is_ok_and(|term| term.contains("kitty"))
+        || env::var("TERM_PROGRAM").is_ok_and(|program| program == "iTerm.app" || program == "WezTerm")
+}
+
+impl Previewer {
+    pub fn new(context: usize) -> Previewer {
+        let highlighting = supports_images().then(|| {
+            let mut themes = ThemeSet::load_defaults().themes;
+            (SyntaxSet::load_defaults_newlines(), themes.remove("base16-ocean.dark").unwrap_or_default())
+        });
+        Previewer { context, highlighting, vfs: Arc::new(OsVfs) }
+    }
+
+    pub fn plain(context: usize) -> Previewer {
+        Previewer { context, highlighting: None, vfs: Arc::new(OsVfs) }
+    }
+
+    // The snippet around `line_num` (1-based) of `path`, with the matching
+    // line marked. None for files that can't be read from here, such as the
+    // contents of archives.
+    pub fn snippet(&self, path: &str, line_num: usize) -> Option<Vec<String>> {
+        let text = self.vfs.read_to_string(Path::new(path)).ok()?;
+        let first = line_num.checked_sub(1)?.saturating_sub(self.context);
+        let last = line_num + self.context;
+        let lines: Vec<String> = match &self.highlighting {
+            Some((syntaxes, theme)) => {
+                let syntax = syntaxes.find_syntax_for_file(path).ok().flatten().unwrap_or_else(|| syntaxes.find_syntax_plain_text());
+                let mut highlighter = HighlightLines::new(syntax, theme);
+                let mut lines = Vec::new();
+                // Earlier lines are highlighted too so comments and strings
+                // opened above the snippet are colored right
+                for line in LinesWithEndings::from(&text).take(last) {
+                    let ranges = highlighter.highlight_line(line, syntaxes).ok()?;
+                    lines.push(format!("{}\x1b[0m", as_24_bit_terminal_escaped(&ranges, false).trim_end()));
+                }
+                lines
+            }
+            None => text.lines().take(last).map(String::from).collect(),
+        };
+        Some(lines
+            .into_iter()
+            .enumerate()
+            .skip(first)
+            .map(|(index, line)| format!("{} {:>5} | {}", if index + 1 == line_num { '>' } else { ' ' }, index + 1, line))
+            .collect())
+    }
+}
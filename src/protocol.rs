@@ -0,0 +1,362 @@
+use crate::{
+    codec::{Codec, Encoding},
+    error::Error,
+    stats::ServerStats,
+    trace::now_micros,
+};
+
+use bincode::{Decode, Encode};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use std::io::{self, BufRead, Read, Write};
+
+// Bumped whenever Args or the replies change in a way older binaries can't
+// read.
+pub const PROTOCOL_VERSION: u32 = 7;
+
+// The release of this binary. Args are decoded by position, so every field
+// added, removed or moved changes the protocol version too.
+pub const RELEASE: &str = env!("CARGO_PKG_VERSION");
+
+// Requests and frames are far smaller, a longer length prefix is garbage or
+// not one of ours. Lengths are little-endian u32 on every platform.
+pub const MAX_MESSAGE_LEN: usize = 64 << 20;
+
+// Sent before every request so a server can tell a client from another
+// release apart before decoding its Args. The replies come back over the
+// same stream. New fields go at the end only:
+// decoding stops after the fields a server knows about, so older servers
+// still read the version of newer clients.
+#[derive(Encode, Decode, Serialize, Deserialize, Clone, Debug)]
+pub struct Header {
+    pub version: u32,
+    pub main_server: bool,
+    // Shared by every hop of a request, for --stats --verbose
+    pub trace_id: u64,
+    // Microseconds since the epoch when the frame was sent
+    pub sent_at: u64,
+    // The secret of the receiving server, see auth.rs
+    pub token: String,
+    // How the Args and the replies are encoded, see codec.rs
+    pub encoding: Encoding,
+    // Picked by the client to tell the requests of a --session apart
+    pub tag: u64,
+    // Empty from clients of releases before it was added
+    pub release: String,
+    // Asks for a Version reply and sends no Args, so a server of any release
+    // answers it
+    pub version_only: bool,
+}
+
+impl Header {
+    pub fn new(main_server: bool, trace_id: u64, token: String) -> Header {
+        Header {
+            version: PROTOCOL_VERSION,
+            main_server,
+            trace_id,
+            sent_at: now_micros(),
+            token,
+            encoding: Encoding::Bincode,
+            tag: 0,
+            release: String::from(RELEASE),
+            version_only: false,
+        }
+    }
+}
+
+// Everything servers send back, to clients and to each other, as
+//   [u32 little endian length][Frame encoded as the request asked]
+// so no text in the results can be mistaken for the end of them.
+#[derive(Encode, Decode, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum Frame {
+    // One line of results or other output for the user
+    ResultLine(String),
+    Error(String),
+    // Something the client should know that doesn't stop the request, like
+    // a child server being down
+    Warning(String),
+    // How far along a server is that can't answer yet
+    Progress(String),
+    // Where the time of a request went, for --stats --verbose
+    Trace { id: u64, server: String, stage: String, micros: u64 },
+    // Ends the replies of one server. `last` is set once the server the
+    // client talks to is done with every server it asked.
+    EndOfResults { last: bool },
+    // The frames of one chunk of replies, zstd compressed for a client that
+    // asked with --compress. An EndOfResults is always the last frame of its
+    // chunk.
+    Compressed(Vec<u8>),
+    // A reply to the request of a --session with this tag in its header.
+    // Editor plugins keep one connection open and send a request whenever
+    // they need one, without waiting for the replies to the ones before;
+    // each request is answered with Tagged frames, up to a Tagged
+    // EndOfResults, while the others go on. Sessions are for local sockets
+    // only, a --session request over --connect is answered untagged.
+    Tagged { tag: u64, frame: Box<Frame> },
+    // The reply of every server to --status, see stats.rs
+    Stats(ServerStats),
+    // The reply to a request with version_only in its header
+    Version { release: String, protocol: u32 },
+    // What one server searched to answer a request, for --stats
+    QueryStats { id: u64, server: String, scanned: u64, skipped: u64, matches: u64, micros: u64 },
+}
+
+// Frames sent to --compress clients are tiny, the chunks they come in are
+// not, and a fast level is what keeps the server from waiting on it.
+const ZSTD_LEVEL: i32 = 1;
+
+// Packs a chunk of encoded frames into one Compressed frame.
+pub fn compress_frames(chunk: &[u8], codec: &impl Codec) -> io::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    encode_frame(&Frame::Compressed(zstd::bulk::compress(chunk, ZSTD_LEVEL)?), codec, &mut out);
+    Ok(out)
+}
+
+// The frames packed into a Compressed frame. A few bytes can inflate to
+// gigabytes, so no more than MAX_MESSAGE_LEN are inflated.
+pub fn decompress_frames(data: &[u8], codec: &impl Codec) -> Result<Vec<Frame>, Error> {
+    let mut chunk = Vec::new();
+    let decoder = zstd::stream::read::Decoder::new(data).map_err(|e| Error::Decode(e.to_string()))?;
+    decoder.take(MAX_MESSAGE_LEN as u64 + 1).read_to_end(&mut chunk).map_err(|e| Error::Decode(e.to_string()))?;
+    if chunk.len() > MAX_MESSAGE_LEN {
+        return Err(Error::Decode(String::from("compressed frames too large")));
+    }
+    let mut reader = chunk.as_slice();
+    let mut frames = Vec::new();
+    while !reader.is_empty() {
+        frames.push(read_frame(&mut reader, codec).ok_or_else(|| Error::Decode(String::from("truncated compressed frames")))?);
+    }
+    Ok(frames)
+}
+
+pub fn encode_frame(frame: &Frame, codec: &impl Codec, out: &mut Vec<u8>) {
+    let encoded = codec.encode(frame).unwrap();
+    out.extend_from_slice(&(encoded.len() as u32).to_le_bytes());
+    out.extend_from_slice(&encoded);
+}
+
+pub fn write_frame(writer: &mut impl Write, codec: &impl Codec, frame: &Frame) -> io::Result<()> {
+    let mut out = Vec::new();
+    encode_frame(frame, codec, &mut out);
+    writer.write_all(&out)?;
+    writer.flush()
+}
+
+// None once the other side closed the stream or sent something unreadable,
+// such as a length beyond MAX_MESSAGE_LEN.
+pub fn read_frame(reader: &mut impl Read, codec: &impl Codec) -> Option<Frame> {
+    let mut len = [0; 4];
+    reader.read_exact(&mut len).ok()?;
+    let len = u32::from_le_bytes(len) as usize;
+    if len > MAX_MESSAGE_LEN {
+        return None;
+    }
+    let mut encoded = vec![0; len];
+    reader.read_exact(&mut encoded).ok()?;
+    codec.decode(&encoded).ok()
+}
+
+// How a --connect client talks to a --listen server. Servers tell the two
+// apart by the first byte of the request, so they answer both.
+//
+// With json, for tools that have no bincode implementation, the request is a
+// single line holding one object:
+//   {"version": 7, "token": "...", "trace_id": 0, "args": ["--files", "main"]}
+// `args` are the arguments of the hanoi client and are read the same way;
+// paths in them must be absolute. `trace_id` may be left out. Every reply is
+// then one object per line, with its kind in "type":
+//   {"type": "result", "text": "src/main.rs:12: fn main() {"}
+//   {"type": "error", "message": "..."}
+//   {"type": "warning", "message": "..."}
+//   {"type": "progress", "message": "..."}
+//   {"type": "trace", "id": 0, "server": "...", "stage": "...", "micros": 0}
+//   {"type": "stats", "stats": {"root": "...", "pid": 0, "uptime_secs": 0,
+//     "files": 0, "total_bytes": 0, "index_bytes": 0, "resident_bytes": 0,
+//     "last_change": 0, "children": ["..."]}}
+//   {"type": "version", "release": "0.1.0", "protocol": 7}
+//   {"type": "end", "last": false}
+// The replies are over after an "end" with "last" set. Fields are only ever
+// added, so clients should ignore the ones they don't know.
+//
+// Despite its name, bincode only stands for the length prefixed frames here:
+// the Args and the replies can also be MessagePack or CBOR, see codec.rs.
+#[derive(Encode, Decode, Serialize, Deserialize, ValueEnum, Clone, Copy, PartialEq, Debug, Default)]
+pub enum Protocol {
+    #[default]
+    Bincode,
+    Json,
+}
+
+pub struct JsonRequest {
+    pub version: u32,
+    pub token: String,
+    pub trace_id: u64,
+    pub args: Vec<String>,
+}
+
+impl JsonRequest {
+    pub fn to_line(&self) -> String {
+        format!("{}\n", json!({ "version": self.version, "token": self.token, "trace_id": self.trace_id, "args": self.args }))
+    }
+
+    pub fn parse(line: &str) -> Result<JsonRequest, String> {
+        let value: Value = serde_json::from_str(line).map_err(|e| e.to_string())?;
+        let version = value["version"].as_u64().ok_or("\"version\" must be a number")?;
+        let args = value["args"]
+            .as_array()
+            .ok_or("\"args\" must be an array")?
+            .iter()
+            .map(|arg| arg.as_str().map(String::from).ok_or("\"args\" must only hold strings"))
+            .collect::<Result<Vec<String>, &str>>()?;
+        Ok(JsonRequest {
+            version: version as u32,
+            token: value["token"].as_str().unwrap_or_default().to_string(),
+            trace_id: value["trace_id"].as_u64().unwrap_or(0),
+            args,
+        })
+    }
+}
+
+impl Frame {
+    pub fn to_json(&self) -> Value {
+        match self {
+            Frame::ResultLine(text) => json!({ "type": "result", "text": text }),
+            Frame::Error(message) => json!({ "type": "error", "message": message }),
+            Frame::Warning(message) => json!({ "type": "warning", "message": message }),
+            Frame::Progress(message) => json!({ "type": "progress", "message": message }),
+            Frame::Trace { id, server, stage, micros } => json!({ "type": "trace", "id": id, "server": server, "stage": stage, "micros": micros }),
+            Frame::EndOfResults { last } => json!({ "type": "end", "last": last }),
+            // Only ever sent to bincode clients
+            Frame::Compressed(_) => json!({ "type": "compressed" }),
+            Frame::Tagged { tag, frame } => json!({ "type": "tagged", "tag": tag, "frame": frame.to_json() }),
+            Frame::Stats(stats) => json!({ "type": "stats", "stats": stats.to_json() }),
+            Frame::Version { release, protocol } => json!({ "type": "version", "release": release, "protocol": protocol }),
+            Frame::QueryStats { id, server, scanned, skipped, matches, micros } => {
+                json!({ "type": "query_stats", "id": id, "server": server, "scanned": scanned, "skipped": skipped, "matches": matches, "micros": micros })
+            }
+        }
+    }
+
+    fn from_json(value: &Value) -> Option<Frame> {
+        let string = |key: &str| value[key].as_str().map(String::from);
+        Some(match value["type"].as_str()? {
+            "result" => Frame::ResultLine(string("text")?),
+            "error" => Frame::Error(string("message")?),
+            "warning" => Frame::Warning(string("message")?),
+            "progress" => Frame::Progress(string("message")?),
+            "trace" => Frame::Trace { id: value["id"].as_u64()?, server: string("server")?, stage: string("stage")?, micros: value["micros"].as_u64()? },
+            "end" => Frame::EndOfResults { last: value["last"].as_bool()? },
+            "stats" => Frame::Stats(ServerStats::from_json(&value["stats"])?),
+            "version" => Frame::Version { release: string("release")?, protocol: value["protocol"].as_u64()? as u32 },
+            "query_stats" => Frame::QueryStats {
+                id: value["id"].as_u64()?,
+                server: string("server")?,
+                scanned: value["scanned"].as_u64()?,
+                skipped: value["skipped"].as_u64()?,
+                matches: value["matches"].as_u64()?,
+                micros: value["micros"].as_u64()?,
+            },
+            "tagged" => Frame::Tagged { tag: value["tag"].as_u64()?, frame: Box::new(Frame::from_json(&value["frame"])?) },
+            _ => return None,
+        })
+    }
+}
+
+impl Protocol {
+    pub fn write_frame(self, writer: &mut impl Write, codec: &impl Codec, frame: &Frame) -> io::Result<()> {
+        match self {
+            Protocol::Bincode => write_frame(writer, codec, frame),
+            Protocol::Json => {
+                writeln!(writer, "{}", frame.to_json())?;
+                writer.flush()
+            }
+        }
+    }
+
+    pub fn read_frame(self, reader: &mut impl BufRead, codec: &impl Codec) -> Option<Frame> {
+        match self {
+            Protocol::Bincode => read_frame(reader, codec),
+            Protocol::Json => {
+                let mut line = String::new();
+                if reader.read_line(&mut line).ok()? == 0 {
+                    return None;
+                }
+                Frame::from_json(&serde_json::from_str(&line).ok()?)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_round_trip_in_every_codec() {
+        let stats = ServerStats {
+            root: String::from("/src"),
+            pid: 1,
+            uptime_secs: 2,
+            files: 3,
+            total_bytes: 4,
+            index_bytes: 5,
+            resident_bytes: None,
+            last_change: Some(6),
+            children: vec![String::from("/src/vendor")],
+        };
+        let frames = [
+            Frame::ResultLine(String::from("/src/main.rs:1: fn main() {}")),
+            Frame::Error(String::from("failed")),
+            Frame::Trace { id: 1, server: String::from("/src"), stage: String::from("search"), micros: 2 },
+            Frame::Compressed(vec![0, 1, 2]),
+            Frame::Tagged { tag: 3, frame: Box::new(Frame::EndOfResults { last: false }) },
+            Frame::Stats(stats),
+            Frame::Version { release: String::from(RELEASE), protocol: PROTOCOL_VERSION },
+            Frame::QueryStats { id: 1, server: String::from("/src"), scanned: 2, skipped: 3, matches: 4, micros: 5 },
+            Frame::EndOfResults { last: true },
+        ];
+        for encoding in Encoding::value_variants() {
+            let mut stream = Vec::new();
+            for frame in &frames {
+                write_frame(&mut stream, encoding, frame).unwrap();
+            }
+            let mut reader = stream.as_slice();
+            let read: Vec<Frame> = std::iter::from_fn(|| read_frame(&mut reader, encoding)).collect();
+            assert_eq!(read, frames, "{:?}", encoding);
+        }
+    }
+
+    #[test]
+    fn headers_round_trip_in_every_codec() {
+        for encoding in Encoding::value_variants() {
+            let header = Header { encoding: *encoding, tag: 7, version_only: true, ..Header::new(true, 42, String::from("secret")) };
+            let read: Header = Codec::decode(encoding, &Codec::encode(encoding, &header).unwrap()).unwrap();
+            assert_eq!(
+                (read.version, read.main_server, read.trace_id, read.sent_at, read.token, read.encoding, read.tag, read.release, read.version_only),
+                (PROTOCOL_VERSION, true, 42, header.sent_at, String::from("secret"), *encoding, 7, String::from(RELEASE), true)
+            );
+        }
+    }
+
+    #[test]
+    fn compressed_frames_round_trip() {
+        let frames = [Frame::ResultLine(String::from("/src/main.rs:1: fn main() {}")), Frame::Warning(String::from("slow")), Frame::EndOfResults { last: true }];
+        let mut chunk = Vec::new();
+        for frame in &frames {
+            encode_frame(frame, &Encoding::Bincode, &mut chunk);
+        }
+        let packed = compress_frames(&chunk, &Encoding::Bincode).unwrap();
+        let Some(Frame::Compressed(data)) = read_frame(&mut packed.as_slice(), &Encoding::Bincode) else {
+            panic!("not a compressed frame");
+        };
+        assert_eq!(decompress_frames(&data, &Encoding::Bincode).unwrap(), frames);
+    }
+
+    #[test]
+    fn compressed_frames_inflate_to_the_message_limit_only() {
+        let data = zstd::bulk::compress(&vec![0; MAX_MESSAGE_LEN + 1], ZSTD_LEVEL).unwrap();
+        assert!(matches!(decompress_frames(&data, &Encoding::Bincode), Err(Error::Decode(_))));
+    }
+}
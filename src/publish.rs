@@ -0,0 +1,103 @@
+use crate::{
+    replies::{ReplyStream, SharedReplyStream},
+    Args, Indexer2,
+};
+
+use std::{
+    collections::{HashMap, HashSet},
+    io::{self, Write},
+};
+
+struct Publication {
+    args: Args,
+    // The clients attached to this query
+    subscribers: Vec<SharedReplyStream>,
+}
+
+// The query of a `hanoi watch` client and the matches it was last sent.
+struct Watch {
+    args: Args,
+    matches: Vec<String>,
+    watcher: SharedReplyStream,
+}
+
+// Queries published under a name. Every client that subscribes gets the
+// current results and then a fresh result set whenever the index changes.
+#[derive(Default)]
+pub struct Publications {
+    publications: HashMap<String, Publication>,
+    watches: Vec<Watch>,
+}
+
+impl Publications {
+    pub fn publish(&mut self, name: &str, args: &Args) {
+        let subscribers = self.publications.remove(name).map(|publication| publication.subscribers).unwrap_or_default();
+        self.publications.insert(String::from(name), Publication {
+            args: args.clone(),
+            subscribers,
+        });
+    }
+
+    // The published query, for the first result set of a new subscriber.
+    pub fn published(&self, name: &str) -> Option<Args> {
+        self.publications.get(name).map(|publication| publication.args.clone())
+    }
+
+    pub fn subscribe(&mut self, name: &str, client_reader: SharedReplyStream) {
+        if let Some(publication) = self.publications.get_mut(name) {
+            publication.subscribers.push(client_reader);
+        }
+    }
+
+    pub fn watch(&mut self, args: &Args, matches: Vec<String>, watcher: SharedReplyStream) {
+        self.watches.push(Watch { args: args.clone(), matches, watcher });
+    }
+
+    // Pushes new results to every subscriber, and what changed to every
+    // watcher, and forgets the ones that went away. Watchers whose matches
+    // stay the same hear nothing.
+    pub fn notify(&mut self, indexer: &Indexer2) {
+        self.watches.retain_mut(|watch| {
+            let matches = matches(&watch.args, indexer);
+            if matches == watch.matches {
+                return true;
+            }
+            let mut client_reader = watch.watcher.lock().unwrap_or_else(|e| e.into_inner());
+            let sent = send_changes(&watch.matches, &matches, &mut client_reader).and_then(|_| client_reader.end_batch());
+            watch.matches = matches;
+            sent.is_ok()
+        });
+        for (name, publication) in &mut self.publications {
+            publication.subscribers.retain(|subscriber| {
+                let mut client_reader = subscriber.lock().unwrap_or_else(|e| e.into_inner());
+                send_results(name, &publication.args, indexer, &mut client_reader);
+                client_reader.end_batch().is_ok()
+            });
+        }
+    }
+}
+
+// The matches of `args`, in the order find reports them.
+pub fn matches(args: &Args, indexer: &Indexer2) -> Vec<String> {
+    let mut found = Vec::new();
+    indexer.find(args, &mut found);
+    String::from_utf8_lossy(&found).lines().map(String::from).collect()
+}
+
+// Sends the matches of `before` that are gone as "- match" and the new ones
+// of `after` as "+ match".
+pub fn send_changes(before: &[String], after: &[String], reader: &mut ReplyStream) -> io::Result<()> {
+    let (before_set, after_set): (HashSet<&String>, HashSet<&String>) = (before.iter().collect(), after.iter().collect());
+    for removed in before.iter().filter(|line| !after_set.contains(line)) {
+        writeln!(reader, "- {}", removed)?;
+    }
+    for added in after.iter().filter(|line| !before_set.contains(line)) {
+        writeln!(reader, "+ {}", added)?;
+    }
+    Ok(())
+}
+
+pub fn send_results(name: &str, args: &Args, indexer: &Indexer2, reader: &mut ReplyStream) {
+    let _ = writeln!(reader, "== {} ==", name);
+    indexer.find(args, reader);
+}
@@ -0,0 +1,101 @@
+use crate::vfs::{Vfs, VfsMetadata};
+
+use std::{
+    collections::HashMap,
+    fmt,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+};
+
+#[derive(Clone, Copy)]
+pub enum ReadFailure {
+    Permission,
+    Io,
+    Encoding,
+    TooLarge,
+}
+
+#[derive(Default, Clone, Copy)]
+pub struct FailureCounts {
+    pub permission: usize,
+    pub io: usize,
+    pub encoding: usize,
+    pub too_large: usize,
+}
+
+impl FailureCounts {
+    fn add(&mut self, failure: ReadFailure) {
+        match failure {
+            ReadFailure::Permission => self.permission += 1,
+            ReadFailure::Io => self.io += 1,
+            ReadFailure::Encoding => self.encoding += 1,
+            ReadFailure::TooLarge => self.too_large += 1,
+        }
+    }
+
+    fn merge(&mut self, other: &FailureCounts) {
+        self.permission += other.permission;
+        self.io += other.io;
+        self.encoding += other.encoding;
+        self.too_large += other.too_large;
+    }
+
+    pub fn total(&self) -> usize {
+        self.permission + self.io + self.encoding + self.too_large
+    }
+}
+
+impl fmt::Display for FailureCounts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "permission: {}, io: {}, encoding: {}, too large: {}", self.permission, self.io, self.encoding, self.too_large)
+    }
+}
+
+// Read failures grouped by the directory containing the file.
+#[derive(Default)]
+pub struct ReadFailures {
+    pub dirs: HashMap<PathBuf, FailureCounts>,
+}
+
+impl ReadFailures {
+    pub fn record(&mut self, path: &Path, failure: ReadFailure) {
+        let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        self.dirs.entry(dir).or_default().add(failure);
+    }
+
+    pub fn extend(&mut self, other: ReadFailures) {
+        for (dir, counts) in other.dirs {
+            self.dirs.entry(dir).or_default().merge(&counts);
+        }
+    }
+
+    pub fn total(&self) -> FailureCounts {
+        let mut total = FailureCounts::default();
+        for counts in self.dirs.values() {
+            total.merge(counts);
+        }
+        total
+    }
+}
+
+fn classify(e: io::Error) -> ReadFailure {
+    match e.kind() {
+        ErrorKind::PermissionDenied => ReadFailure::Permission,
+        ErrorKind::InvalidData => ReadFailure::Encoding,
+        _ => ReadFailure::Io,
+    }
+}
+
+pub fn stat_file(vfs: &dyn Vfs, path: &Path, max_file_size: Option<u64>) -> Result<VfsMetadata, ReadFailure> {
+    let metadata = vfs.metadata(path).map_err(classify)?;
+    if max_file_size.is_some_and(|max_file_size| metadata.len > max_file_size) {
+        return Err(ReadFailure::TooLarge);
+    }
+    Ok(metadata)
+}
+
+pub fn read_file(vfs: &dyn Vfs, path: &Path, max_file_size: Option<u64>) -> Result<(String, VfsMetadata), ReadFailure> {
+    let metadata = stat_file(vfs, path, max_file_size)?;
+    let text = vfs.read_to_string(path).map_err(classify)?;
+    Ok((text, metadata))
+}
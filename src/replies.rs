@@ -0,0 +1,169 @@
+use crate::{
+    codec::Encoding,
+    protocol::{compress_frames, encode_frame, Frame},
+};
+
+use std::{
+    io::{self, ErrorKind, Write},
+    mem,
+    sync::{
+        mpsc::{self, SyncSender},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+};
+
+// Frames are sent once this many bytes of them pile up.
+const CHUNK_SIZE: usize = 16 * 1024;
+// Chunks waiting for a slow client before the server has to wait too.
+const QUEUED_CHUNKS: usize = 64;
+
+// Replies to a client over the stream its request came in on. Every line
+// written becomes a ResultLine frame, and frames are sent in chunks by a
+// writer thread so the server keeps searching while the client catches up,
+// up to QUEUED_CHUNKS ahead. Dropping the stream ends the replies with
+// EndOfResults.
+pub struct ReplyStream {
+    line: Vec<u8>,
+    chunk: Vec<u8>,
+    last: bool,
+    encoding: Encoding,
+    compress: bool,
+    tag: Option<u64>,
+    sender: Option<SyncSender<Vec<u8>>>,
+    writer: Option<JoinHandle<()>>,
+}
+
+impl ReplyStream {
+    pub fn new<W: Write + Send + 'static>(mut writer: W, encoding: Encoding) -> ReplyStream {
+        let (sender, receiver) = mpsc::sync_channel::<Vec<u8>>(QUEUED_CHUNKS);
+        let writer = thread::spawn(move || {
+            for chunk in receiver {
+                if writer.write_all(&chunk).and_then(|_| writer.flush()).is_err() {
+                    // The client went away, the server sees it on its next send
+                    return;
+                }
+            }
+        });
+        ReplyStream {
+            line: Vec::new(),
+            chunk: Vec::with_capacity(CHUNK_SIZE),
+            last: false,
+            encoding,
+            compress: false,
+            tag: None,
+            sender: Some(sender),
+            writer: Some(writer),
+        }
+    }
+
+    // Sends every chunk as one Compressed frame, for --compress.
+    pub fn compressed(mut self, compress: bool) -> ReplyStream {
+        self.compress = compress;
+        self
+    }
+
+    // Wraps every frame in a Tagged one, for the requests of a --session.
+    pub fn tagged(mut self, tag: Option<u64>) -> ReplyStream {
+        self.tag = tag;
+        self
+    }
+
+    pub fn send(&mut self, frame: Frame) -> io::Result<()> {
+        let frame = match self.tag {
+            Some(tag) => Frame::Tagged { tag, frame: Box::new(frame) },
+            None => frame,
+        };
+        encode_frame(&frame, &self.encoding, &mut self.chunk);
+        if self.chunk.len() >= CHUNK_SIZE {
+            self.send_chunk()?;
+        }
+        Ok(())
+    }
+
+    // Makes the EndOfResults sent on drop tell the client that no server has
+    // anything more for it.
+    pub fn end_all(&mut self) {
+        self.last = true;
+    }
+
+    // Ends one set of replies to a client that stays attached for more.
+    pub fn end_batch(&mut self) -> io::Result<()> {
+        self.send(Frame::EndOfResults { last: false })?;
+        self.send_chunk()
+    }
+
+    fn send_chunk(&mut self) -> io::Result<()> {
+        let mut chunk = mem::take(&mut self.chunk);
+        if self.compress && !chunk.is_empty() {
+            chunk = compress_frames(&chunk, &self.encoding)?;
+        }
+        match &self.sender {
+            Some(sender) if !chunk.is_empty() => sender.send(chunk).map_err(|_| io::Error::from(ErrorKind::BrokenPipe)),
+            _ => Ok(()),
+        }
+    }
+}
+
+// The stream of a --session, shared by the replies to all of its requests.
+// A chunk only holds whole frames and is written under the lock in one go,
+// so the frames of different requests never interleave.
+pub struct SharedWriter<W>(Arc<Mutex<W>>);
+
+impl<W> SharedWriter<W> {
+    pub fn new(writer: W) -> SharedWriter<W> {
+        SharedWriter(Arc::new(Mutex::new(writer)))
+    }
+}
+
+impl<W> Clone for SharedWriter<W> {
+    fn clone(&self) -> SharedWriter<W> {
+        SharedWriter(Arc::clone(&self.0))
+    }
+}
+
+impl<W: Write> Write for SharedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).write_all(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).flush()
+    }
+}
+
+// The replies of a client that stays attached, to events or a published
+// query, written to by whichever thread has something new for it.
+pub type SharedReplyStream = Arc<Mutex<ReplyStream>>;
+
+impl Write for ReplyStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.line.extend_from_slice(buf);
+        while let Some(end) = self.line.iter().position(|&b| b == b'\n') {
+            let rest = self.line.split_off(end + 1);
+            let line = mem::replace(&mut self.line, rest);
+            self.send(Frame::ResultLine(String::from_utf8_lossy(&line[..end]).into_owned()))?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.send_chunk()
+    }
+}
+
+impl Drop for ReplyStream {
+    fn drop(&mut self) {
+        if !self.line.is_empty() {
+            let line = mem::take(&mut self.line);
+            let _ = self.send(Frame::ResultLine(String::from_utf8_lossy(&line).into_owned()));
+        }
+        let _ = self.send(Frame::EndOfResults { last: self.last });
+        let _ = self.send_chunk();
+        self.sender = None;
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+}
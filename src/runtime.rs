@@ -0,0 +1,355 @@
+use crate::{
+    auth,
+    codec::Bincode,
+    convert_path,
+    error::Error,
+    protocol::{read_frame, Frame, Header},
+    transport::Transport,
+    write_request, write_to_pipe, Args,
+};
+
+use clap::Parser;
+use interprocess::local_socket::{LocalSocketListener, LocalSocketStream};
+
+use std::{
+    env,
+    fs::{self, OpenOptions},
+    io::{self, BufReader, ErrorKind},
+    path::{Path, PathBuf},
+    process::{self, Command, Stdio},
+    time::Duration,
+};
+#[cfg(unix)]
+use signal_hook::{
+    consts::{SIGINT, SIGTERM},
+    iterator::Signals,
+};
+
+// Long enough for a server that is busy, not for one that hangs.
+const PING_TIMEOUT: Duration = Duration::from_secs(3);
+
+// Per user directory for what servers only need while they run: their
+// sockets, tokens and the registry of running servers. Only its owner may
+// enter it, even where it falls back to the shared temp directory.
+pub fn runtime_dir() -> PathBuf {
+    let base = if cfg!(target_os = "windows") {
+        env::var_os("LOCALAPPDATA").map(PathBuf::from)
+    } else {
+        env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from)
+    };
+    base.unwrap_or_else(env::temp_dir).join("hanoi")
+}
+
+// Creates `name` under the runtime directory.
+pub fn create_dir(name: &str) -> io::Result<PathBuf> {
+    let base = runtime_dir();
+    let dir = base.join(name);
+    fs::create_dir_all(&dir)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        fs::set_permissions(&base, fs::Permissions::from_mode(0o700))?;
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o700))?;
+    }
+    Ok(dir)
+}
+
+// The local socket of the server for `address`. Named pipes have a namespace
+// of their own but it is shared by every user, so theirs is in the name.
+pub fn socket_name(address: &Path) -> PathBuf {
+    if cfg!(target_os = "windows") {
+        let user = env::var("USERNAME").unwrap_or_default();
+        PathBuf::from(format!("hanoi-{}-{}", user, convert_path(address).display()))
+    } else {
+        runtime_dir().join("sockets").join(convert_path(address)).with_extension("sock")
+    }
+}
+
+// The address of the server for `root`, which names its socket and registry
+// entry. Named servers have their own, like shards.
+pub fn address(root: &Path, name: Option<&str>) -> PathBuf {
+    match name {
+        Some(name) => root.join(format!("#name-{}", name)),
+        None => root.to_path_buf(),
+    }
+}
+
+// Every running server has a file under "servers" telling its root, socket
+// and process, so clients know where to look without trying every socket.
+// It is also the lock on its root: it is created before the socket is bound
+// and only one server can create it, while one whose process is gone counts
+// as never written.
+fn registry_path(address: &Path) -> PathBuf {
+    runtime_dir().join("servers").join(convert_path(address))
+}
+
+fn registry_field<'a>(entry: &'a str, name: &str) -> Option<&'a str> {
+    entry.lines().find_map(|line| line.strip_prefix(name)?.strip_prefix('='))
+}
+
+// The process of the server that registered `entry`, if it still runs.
+fn registered_pid(entry: &Path) -> Option<u32> {
+    let text = fs::read_to_string(entry).ok()?;
+    registry_field(&text, "pid")?.parse().ok().filter(|pid| process_alive(*pid))
+}
+
+#[cfg(unix)]
+fn process_alive(pid: u32) -> bool {
+    // SAFETY: signal 0 only checks that the process exists
+    unsafe { libc::kill(pid as libc::pid_t, 0) == 0 || io::Error::last_os_error().kind() == ErrorKind::PermissionDenied }
+}
+
+#[cfg(not(unix))]
+fn process_alive(pid: u32) -> bool {
+    let filter = format!("PID eq {}", pid);
+    Command::new("tasklist")
+        .args(["/FI", &filter, "/NH"])
+        .stderr(Stdio::null())
+        .output()
+        .is_ok_and(|output| String::from_utf8_lossy(&output.stdout).split_whitespace().any(|word| word == pid.to_string()))
+}
+
+// A server found in the registry.
+pub struct ServerEntry {
+    pub root: PathBuf,
+    pub pid: Option<u32>,
+}
+
+// Every registered server whose process still runs, by root. Entries of
+// servers that went away are removed.
+pub fn list_servers() -> Vec<ServerEntry> {
+    let Ok(entries) = fs::read_dir(runtime_dir().join("servers")) else {
+        return Vec::new();
+    };
+    let mut servers = Vec::new();
+    for entry in entries.flatten() {
+        let Ok(text) = fs::read_to_string(entry.path()) else {
+            continue;
+        };
+        let Some(root) = registry_field(&text, "root").map(PathBuf::from) else {
+            continue;
+        };
+        let Some(pid) = registered_pid(&entry.path()) else {
+            let _ = fs::remove_file(entry.path());
+            continue;
+        };
+        servers.push(ServerEntry { root, pid: Some(pid) });
+    }
+    servers.sort_by(|a, b| a.root.cmp(&b.root));
+    servers
+}
+
+// Removes the server from the registry when dropped.
+pub struct Registration {
+    address: PathBuf,
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(registry_path(&self.address));
+        if !cfg!(target_os = "windows") {
+            let _ = fs::remove_file(socket_name(&self.address));
+        }
+    }
+}
+
+// Registers the server for `address` and binds its socket. Fails with
+// AddrInUse while another server for it runs. What a server that died left
+// behind is replaced.
+pub fn bind(address: &Path, server_name: Option<&str>) -> io::Result<(LocalSocketListener, Registration)> {
+    let name = socket_name(address);
+    register(address, &name, server_name)?;
+    let registration = Registration { address: address.to_path_buf() };
+    if !cfg!(target_os = "windows") {
+        create_dir("sockets")?;
+        let _ = fs::remove_file(&name);
+    }
+    let listener = LocalSocketListener::bind(name.as_path())?;
+    Ok((listener, registration))
+}
+
+// Creates the registry entry of this process for `address`. It is written
+// aside and linked into place, which fails if the entry exists, so no
+// server ever reads half an entry or takes over one another server just
+// wrote.
+fn register(address: &Path, socket: &Path, server_name: Option<&str>) -> io::Result<()> {
+    let dir = create_dir("servers")?;
+    let entry = registry_path(address);
+    let written = dir.join(format!(".{}", process::id()));
+    let mut text = format!("root={}\nsocket={}\npid={}\n", address.display(), socket.display(), process::id());
+    if let Some(server_name) = server_name {
+        text.push_str(&format!("name={}\n", server_name));
+    }
+    fs::write(&written, text)?;
+    let mut linked = fs::hard_link(&written, &entry);
+    if linked.as_ref().is_err_and(|e| e.kind() == ErrorKind::AlreadyExists) && registered_pid(&entry).is_none() {
+        let _ = fs::remove_file(&entry);
+        linked = fs::hard_link(&written, &entry);
+    }
+    let _ = fs::remove_file(&written);
+    linked.map_err(|e| if e.kind() == ErrorKind::AlreadyExists { io::Error::from(ErrorKind::AddrInUse) } else { e })
+}
+
+// Calls `shut_down` on the first SIGINT or SIGTERM, from a thread of its own
+// since a signal handler can do next to nothing. A second one ends the
+// process right away. On Windows Ctrl-C still ends the process at once.
+#[cfg(unix)]
+pub fn on_termination(shut_down: impl FnOnce() + Send + 'static) -> io::Result<()> {
+    let mut signals = Signals::new([SIGINT, SIGTERM])?;
+    std::thread::spawn(move || {
+        let mut shut_down = Some(shut_down);
+        for signal in signals.forever() {
+            match shut_down.take() {
+                Some(shut_down) => {
+                    std::thread::spawn(shut_down);
+                }
+                None => process::exit(128 + signal),
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn on_termination(_shut_down: impl FnOnce() + Send + 'static) -> io::Result<()> {
+    Ok(())
+}
+
+// Connects to the server for `path` or the closest of its parent
+// directories that has one, at the socket its registry entry names.
+// Entries of servers whose process is gone are removed on the way.
+pub fn find_server(path: &Path) -> Option<(PathBuf, LocalSocketStream)> {
+    path.ancestors().find_map(|root| {
+        let entry = registry_path(root);
+        let text = fs::read_to_string(&entry).ok()?;
+        let socket = registry_field(&text, "socket").map_or_else(|| socket_name(root), PathBuf::from);
+        match LocalSocketStream::connect(socket) {
+            Ok(stream) => Some((root.to_path_buf(), stream)),
+            Err(_) => {
+                // A server that is starting has its entry before its socket
+                if registered_pid(&entry).is_none() {
+                    let _ = fs::remove_file(entry);
+                }
+                None
+            }
+        }
+    })
+}
+
+// Connects to the server started with --name `name`, wherever its root is.
+pub fn find_named_server(name: &str) -> Option<(PathBuf, LocalSocketStream)> {
+    let entries = fs::read_dir(runtime_dir().join("servers")).ok()?;
+    entries.flatten().find_map(|entry| {
+        let text = fs::read_to_string(entry.path()).ok()?;
+        if registry_field(&text, "name") != Some(name) {
+            return None;
+        }
+        let root = PathBuf::from(registry_field(&text, "root")?);
+        let socket = registry_field(&text, "socket").map_or_else(|| socket_name(&root), PathBuf::from);
+        LocalSocketStream::connect(socket).ok().map(|stream| (root, stream))
+    })
+}
+
+// Sends a --ping request to the server for `root`. Only a server whose
+// accept loop still runs answers it: one that hangs or was stopped keeps
+// its socket but never replies. Ok(false) while it is still building its
+// index.
+pub fn ping(root: &Path) -> Result<bool, Error> {
+    let stream = LocalSocketStream::connect(socket_name(root))?;
+    stream.set_timeout(PING_TIMEOUT)?;
+    let mut reader = BufReader::new(stream);
+    write_request(&mut reader, &Args::parse_from(["hanoi", "--ping"]), 0, auth::read_token(root))?;
+    let mut ready = true;
+    loop {
+        match read_frame(&mut reader, &Bincode) {
+            Some(Frame::EndOfResults { .. }) => return Ok(ready),
+            Some(Frame::Progress(_)) => ready = false,
+            Some(_) => {}
+            None => return Err(Error::Pipe(io::Error::from(io::ErrorKind::TimedOut))),
+        }
+    }
+}
+
+// The release and protocol version of the server for `root`. Asked in the
+// header alone, which servers of every release read; ones from before
+// version_only was added wait for Args and time out.
+pub fn server_version(root: &Path) -> Result<(String, u32), Error> {
+    let stream = LocalSocketStream::connect(socket_name(root))?;
+    stream.set_timeout(PING_TIMEOUT)?;
+    let mut reader = BufReader::new(stream);
+    write_to_pipe(&mut reader, Header { version_only: true, ..Header::new(true, 0, auth::read_token(root)) }, &Bincode)?;
+    loop {
+        match read_frame(&mut reader, &Bincode) {
+            Some(Frame::Version { release, protocol }) => return Ok((release, protocol)),
+            Some(_) => {}
+            None => return Err(Error::Pipe(io::Error::from(io::ErrorKind::TimedOut))),
+        }
+    }
+}
+
+// The process of the server for `root`, as registered when it started.
+pub fn server_pid(root: &Path) -> Option<u32> {
+    let entry = fs::read_to_string(registry_path(root)).ok()?;
+    registry_field(&entry, "pid")?.parse().ok()
+}
+
+// Stops the server for `root` that no longer answers and removes what it
+// left behind, so a new one can take its place.
+pub fn clean_up(root: &Path, pid: Option<u32>) {
+    if let Some(pid) = pid {
+        #[cfg(unix)]
+        // SAFETY: kill only sends a signal
+        unsafe {
+            libc::kill(pid as libc::pid_t, libc::SIGKILL);
+        }
+        #[cfg(not(unix))]
+        let _ = Command::new("taskkill").args(["/F", "/PID", &pid.to_string()]).stdout(Stdio::null()).status();
+    }
+    drop(Registration { address: root.to_path_buf() });
+}
+
+// Where a server started in the background writes its output.
+pub fn log_path(root: &Path) -> PathBuf {
+    runtime_dir().join("logs").join(convert_path(root)).with_extension("log")
+}
+
+// Starts this binary with `args` as the server for `root`, detached from the
+// terminal so it keeps running once that is closed, with its output
+// appended to the log of `root`. Returns the process id.
+pub fn spawn_detached(root: &Path, args: &[String]) -> Result<u32, Error> {
+    let spawn_error = |e: io::Error| Error::Spawn(root.to_path_buf(), e);
+    let binary = env::current_exe().map_err(spawn_error)?;
+    create_dir("logs").map_err(spawn_error)?;
+    let log_path = log_path(root);
+    let log = OpenOptions::new().create(true).append(true).open(&log_path).map_err(|e| Error::Write(log_path.clone(), e))?;
+    let log_err = log.try_clone().map_err(|e| Error::Write(log_path, e))?;
+    let mut command = Command::new(binary);
+    command.args(args).stdin(Stdio::null()).stdout(log).stderr(log_err);
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+
+        // SAFETY: setsid is safe to call between fork and exec
+        unsafe {
+            command.pre_exec(|| {
+                libc::setsid();
+                Ok(())
+            });
+        }
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+
+        const DETACHED_PROCESS: u32 = 0x8;
+        const CREATE_NEW_PROCESS_GROUP: u32 = 0x200;
+        command.creation_flags(DETACHED_PROCESS | CREATE_NEW_PROCESS_GROUP);
+    }
+    command.spawn().map(|child| child.id()).map_err(spawn_error)
+}
+
+// Starts a server for `root` in the background.
+pub fn start_server(root: &Path) -> Result<(), Error> {
+    spawn_detached(root, &[String::from("serve"), format!("--root={}", root.display())]).map(|_| ())
+}
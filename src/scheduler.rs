@@ -0,0 +1,136 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    net::IpAddr,
+    sync::{Condvar, Mutex},
+};
+
+// Requests a remote client may have waiting before more are refused.
+const MAX_WAITING_PER_CLIENT: usize = 32;
+
+// Decides when the requests of remote clients are relayed to the server, so
+// one client sending many at once can't keep the others waiting. At most
+// `max_running` run at a time and `max_per_client` of them for one client.
+// Clients with requests waiting take turns as slots free up, in the order
+// they first had to wait.
+pub struct Scheduler {
+    state: Mutex<State>,
+    turn: Condvar,
+    max_running: usize,
+    max_per_client: usize,
+}
+
+#[derive(Default)]
+struct State {
+    running: usize,
+    running_per_client: HashMap<IpAddr, usize>,
+    // Tickets waiting per client, served first come first served
+    waiting: HashMap<IpAddr, VecDeque<u64>>,
+    // Clients with tickets waiting, the next to get a slot first
+    rotation: VecDeque<IpAddr>,
+    next_ticket: u64,
+}
+
+impl State {
+    fn running(&self, client: IpAddr) -> usize {
+        self.running_per_client.get(&client).copied().unwrap_or(0)
+    }
+}
+
+// A slot taken by a request, given back when dropped.
+pub struct Turn<'a> {
+    scheduler: &'a Scheduler,
+    client: IpAddr,
+}
+
+impl Scheduler {
+    pub fn new(max_running: usize, max_per_client: usize) -> Scheduler {
+        Scheduler { state: Mutex::new(State::default()), turn: Condvar::new(), max_running: max_running.max(1), max_per_client: max_per_client.max(1) }
+    }
+
+    // Waits until a request of `client` may run. None if the client already
+    // has too many waiting.
+    pub fn wait_turn(&self, client: IpAddr) -> Option<Turn<'_>> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let ticket = state.next_ticket;
+        state.next_ticket += 1;
+        let waiting = state.waiting.entry(client).or_default();
+        if waiting.len() >= MAX_WAITING_PER_CLIENT {
+            return None;
+        }
+        waiting.push_back(ticket);
+        if !state.rotation.contains(&client) {
+            state.rotation.push_back(client);
+        }
+        while !self.is_turn(&state, client, ticket) {
+            state = self.turn.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+        state.running += 1;
+        *state.running_per_client.entry(client).or_default() += 1;
+        let waiting = state.waiting.get_mut(&client).unwrap();
+        waiting.pop_front();
+        let done_waiting = waiting.is_empty();
+        state.rotation.retain(|rotated| *rotated != client);
+        if done_waiting {
+            state.waiting.remove(&client);
+        } else {
+            state.rotation.push_back(client);
+        }
+        // Another client may be next for a slot still free
+        self.turn.notify_all();
+        Some(Turn { scheduler: self, client })
+    }
+
+    // Whether `ticket` is the oldest of `client` and `client` the first in
+    // the rotation that can run one more request.
+    fn is_turn(&self, state: &State, client: IpAddr, ticket: u64) -> bool {
+        if state.running >= self.max_running || state.waiting[&client].front() != Some(&ticket) {
+            return false;
+        }
+        state.rotation.iter().find(|rotated| state.running(**rotated) < self.max_per_client) == Some(&client)
+    }
+}
+
+impl Drop for Turn<'_> {
+    fn drop(&mut self) {
+        let mut state = self.scheduler.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.running -= 1;
+        if let Some(running) = state.running_per_client.get_mut(&self.client) {
+            *running -= 1;
+            if *running == 0 {
+                state.running_per_client.remove(&self.client);
+            }
+        }
+        self.scheduler.turn.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{thread, time::Duration};
+
+    #[test]
+    fn clients_take_turns() {
+        let scheduler = Scheduler::new(1, 1);
+        let (busy, quiet): (IpAddr, IpAddr) = ("10.0.0.1".parse().unwrap(), "10.0.0.2".parse().unwrap());
+        let served = Mutex::new(Vec::new());
+        let (scheduler, served) = (&scheduler, &served);
+        let running = scheduler.wait_turn(busy).unwrap();
+        thread::scope(|scope| {
+            // The busy client queues three requests before the quiet one
+            // queues its only one
+            for (client, waiting) in [(busy, 1), (busy, 2), (busy, 3), (quiet, 1)] {
+                scope.spawn(move || {
+                    let _turn = scheduler.wait_turn(client).unwrap();
+                    served.lock().unwrap().push(client);
+                });
+                while scheduler.state.lock().unwrap().waiting.get(&client).map_or(0, VecDeque::len) < waiting {
+                    thread::sleep(Duration::from_millis(1));
+                }
+            }
+            drop(running);
+        });
+        assert_eq!(*served.lock().unwrap(), [busy, quiet, busy, busy]);
+    }
+}
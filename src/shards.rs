@@ -0,0 +1,54 @@
+use bincode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+
+use std::{
+    collections::hash_map::DefaultHasher,
+    fmt,
+    hash::Hasher,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+// One of `count` child servers splitting a root by path hash, written as
+// "index/count" on the command line.
+#[derive(Encode, Decode, Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct Shard {
+    pub index: usize,
+    pub count: usize,
+}
+
+impl Shard {
+    pub fn all(count: usize) -> impl Iterator<Item = Shard> {
+        (0..count).map(move |index| Shard { index, count })
+    }
+
+    // `relative_path` is relative to the root so every shard agrees.
+    pub fn contains(&self, relative_path: &Path) -> bool {
+        let mut hasher = DefaultHasher::new();
+        hasher.write(relative_path.display().to_string().replace('\\', "/").as_bytes());
+        hasher.finish() % self.count as u64 == self.index as u64
+    }
+
+    // Shards of a root listen under their own names, not the root's.
+    pub fn address(&self, root: &Path) -> PathBuf {
+        root.join(format!("#shard-{}-of-{}", self.index, self.count))
+    }
+}
+
+impl FromStr for Shard {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Shard, String> {
+        let parsed = value.split_once('/').and_then(|(index, count)| Some((index.trim().parse().ok()?, count.trim().parse().ok()?)));
+        match parsed {
+            Some((index, count)) if index < count => Ok(Shard { index, count }),
+            _ => Err(format!("expected \"index/count\" with index < count, found \"{}\"", value)),
+        }
+    }
+}
+
+impl fmt::Display for Shard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.index, self.count)
+    }
+}
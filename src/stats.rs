@@ -0,0 +1,113 @@
+use crate::{format_system_time, options::ByteSize};
+
+use bincode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use std::{
+    fmt,
+    time::{Duration, UNIX_EPOCH},
+};
+
+// What every server tells about itself in reply to --status, before the
+// details of its index.
+#[derive(Encode, Decode, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ServerStats {
+    pub root: String,
+    pub pid: u32,
+    pub uptime_secs: u64,
+    pub files: u64,
+    // Sizes of the indexed files as they are on disk
+    pub total_bytes: u64,
+    // What the index takes, and all of the server where the OS tells
+    pub index_bytes: u64,
+    pub resident_bytes: Option<u64>,
+    // Seconds since the epoch of the last change the watcher reported
+    pub last_change: Option<u64>,
+    // Roots of the shards and additional directories it forwards to
+    pub children: Vec<String>,
+}
+
+impl ServerStats {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "root": self.root,
+            "pid": self.pid,
+            "uptime_secs": self.uptime_secs,
+            "files": self.files,
+            "total_bytes": self.total_bytes,
+            "index_bytes": self.index_bytes,
+            "resident_bytes": self.resident_bytes,
+            "last_change": self.last_change,
+            "children": self.children,
+        })
+    }
+
+    pub fn from_json(value: &Value) -> Option<ServerStats> {
+        Some(ServerStats {
+            root: value["root"].as_str()?.to_string(),
+            pid: value["pid"].as_u64()? as u32,
+            uptime_secs: value["uptime_secs"].as_u64()?,
+            files: value["files"].as_u64()?,
+            total_bytes: value["total_bytes"].as_u64()?,
+            index_bytes: value["index_bytes"].as_u64()?,
+            resident_bytes: value["resident_bytes"].as_u64(),
+            last_change: value["last_change"].as_u64(),
+            children: value["children"].as_array()?.iter().filter_map(|child| child.as_str().map(String::from)).collect(),
+        })
+    }
+}
+
+// "2d 3h", "5m 12s", the two largest units.
+fn format_uptime(secs: u64) -> String {
+    let units = [(86_400, "d"), (3600, "h"), (60, "m"), (1, "s")];
+    let parts: Vec<String> = units
+        .iter()
+        .scan(secs, |left, (size, unit)| {
+            let count = *left / size;
+            *left %= size;
+            Some((count, unit))
+        })
+        .skip_while(|(count, _)| *count == 0)
+        .take(2)
+        .map(|(count, unit)| format!("{}{}", count, unit))
+        .collect();
+    if parts.is_empty() {
+        String::from("0s")
+    } else {
+        parts.join(" ")
+    }
+}
+
+impl fmt::Display for ServerStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "root: {} (process {}, up {})", self.root, self.pid, format_uptime(self.uptime_secs))?;
+        writeln!(f, "files: {} ({} on disk)", self.files, ByteSize(self.total_bytes))?;
+        match self.resident_bytes {
+            Some(resident) => writeln!(f, "memory: {} for the index, {} in all", ByteSize(self.index_bytes), ByteSize(resident))?,
+            None => writeln!(f, "memory: {} for the index", ByteSize(self.index_bytes))?,
+        }
+        let last_change = self.last_change.map_or_else(|| String::from("none since the start"), |secs| format_system_time(UNIX_EPOCH + Duration::from_secs(secs)));
+        writeln!(f, "last change: {}", last_change)?;
+        if self.children.is_empty() {
+            write!(f, "child servers: none")
+        } else {
+            write!(f, "child servers: {}", self.children.join(", "))
+        }
+    }
+}
+
+// The resident set of this process.
+#[cfg(target_os = "linux")]
+pub fn resident_memory() -> Option<u64> {
+    let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+    let pages: u64 = statm.split_whitespace().nth(1)?.parse().ok()?;
+    // SAFETY: sysconf only reads a configuration value
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    Some(pages * u64::try_from(page_size).ok()?)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn resident_memory() -> Option<u64> {
+    None
+}
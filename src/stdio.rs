@@ -0,0 +1,142 @@
+use crate::{auth, messages::message, protocol::Frame, runtime, transport, Args, Cli};
+
+use rand::Rng;
+use serde_json::{json, Value};
+
+use std::{
+    collections::HashMap,
+    env,
+    io::{self, BufRead, Stdout, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+// JSON-RPC error codes.
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const SERVER_ERROR: i64 = -32000;
+
+// Answers editor plugins with JSON-RPC 2.0 over stdin and stdout for
+// --stdio, one JSON object per line either way:
+//   {"jsonrpc":"2.0","id":1,"method":"search","params":{"term":"main","args":["--word"]}}
+//   {"jsonrpc":"2.0","method":"result","params":{"id":1,"text":"/src/main.rs:12: fn main() {"}}
+//   {"id":1,"jsonrpc":"2.0","result":{"complete":true}}
+// "files" and "status" take "args" too, and "query" runs nothing but its
+// "args", as the hanoi client reads them. Every frame of the replies is a
+// notification named by its type in protocol.rs, with the id of its query,
+// so --events and watch stream until {"method":"cancel","params":{"id":1}}.
+// Queries run side by side, each is answered once it is done.
+pub fn stdio_main(args: &Args) {
+    let dir = args.daemon.as_ref().map_or_else(|| env::current_dir().unwrap_or_default(), PathBuf::from);
+    let output = Arc::new(Mutex::new(io::stdout()));
+    let mut queries: HashMap<String, Arc<AtomicBool>> = HashMap::new();
+    for line in io::stdin().lock().lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let request: Value = match serde_json::from_str(&line) {
+            Ok(request) => request,
+            Err(e) => {
+                send(&output, error(Value::Null, PARSE_ERROR, e.to_string()));
+                continue;
+            }
+        };
+        let id = request["id"].clone();
+        let params = &request["params"];
+        let extra_args = params["args"].as_array().into_iter().flatten().filter_map(|arg| arg.as_str().map(String::from));
+        let client_args: Vec<String> = match request["method"].as_str().unwrap_or_default() {
+            // After "--" so terms starting with a dash aren't read as flags
+            "search" => match params["term"].as_str() {
+                Some(term) => extra_args.chain([String::from("--"), term.to_string()]).collect(),
+                None => {
+                    send(&output, error(id, INVALID_PARAMS, message!(InvalidRequest, "missing the term parameter")));
+                    continue;
+                }
+            },
+            "files" => extra_args.chain([String::from("--files")]).collect(),
+            "status" => extra_args.chain([String::from("--status")]).collect(),
+            "query" => extra_args.collect(),
+            "cancel" => {
+                if let Some(cancelled) = queries.remove(&params["id"].to_string()) {
+                    cancelled.store(true, Ordering::Relaxed);
+                }
+                if !id.is_null() {
+                    send(&output, json!({ "jsonrpc": "2.0", "id": id, "result": null }));
+                }
+                continue;
+            }
+            method => {
+                send(&output, error(id, METHOD_NOT_FOUND, format!("unknown method {}", method)));
+                continue;
+            }
+        };
+        let cancelled = Arc::new(AtomicBool::new(false));
+        if let Some(replaced) = queries.insert(id.to_string(), Arc::clone(&cancelled)) {
+            replaced.store(true, Ordering::Relaxed);
+        }
+        start(id, client_args, &dir, args.server.clone(), Arc::clone(&output), cancelled);
+    }
+    // Streams like --events only notice at their next frame
+    for cancelled in queries.values() {
+        cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+// Sends the frames of query `id` as notifications until it ends or is
+// cancelled, then answers it.
+fn start(id: Value, client_args: Vec<String>, dir: &Path, server: Option<String>, output: Arc<Mutex<Stdout>>, cancelled: Arc<AtomicBool>) {
+    let dir = dir.to_path_buf();
+    thread::spawn(move || {
+        let args = match Cli::try_parse_remote(client_args) {
+            Ok(args) => args,
+            Err(e) => {
+                send(&output, error(id, INVALID_PARAMS, message!(InvalidRequest, e.to_string().trim_end())));
+                return;
+            }
+        };
+        let found = match server.as_ref() {
+            Some(name) => runtime::find_named_server(name),
+            None => runtime::find_server(&dir),
+        };
+        let Some((address, _)) = found else {
+            send(&output, error(id, SERVER_ERROR, message!(NoServer)));
+            return;
+        };
+        let mut complete = false;
+        transport::forward(args, rand::thread_rng().gen(), &runtime::socket_name(&address), auth::read_token(&address), |frame| {
+            if cancelled.load(Ordering::Relaxed) {
+                return false;
+            }
+            if *frame == (Frame::EndOfResults { last: true }) {
+                complete = true;
+                return true;
+            }
+            let mut params = frame.to_json();
+            let method = params["type"].take();
+            if let Some(params) = params.as_object_mut() {
+                params.remove("type");
+            }
+            params["id"] = id.clone();
+            send(&output, json!({ "jsonrpc": "2.0", "method": method, "params": params }))
+        });
+        send(&output, json!({ "jsonrpc": "2.0", "id": id, "result": { "complete": complete } }));
+    });
+}
+
+fn error(id: Value, code: i64, message: String) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+// Whether the plugin is still there to read `message`.
+fn send(output: &Mutex<Stdout>, message: Value) -> bool {
+    let mut output = output.lock().unwrap_or_else(|e| e.into_inner());
+    writeln!(output, "{}", message).and_then(|_| output.flush()).is_ok()
+}
@@ -0,0 +1,243 @@
+use crate::{
+    auth, codec::{Bincode, Encoding}, events, messages::message, options::HumanDuration, protocol::{read_frame, Frame}, runtime, shards::Shard,
+    spawn_child_server, watchdog, write_request, Args, ChildCommand,
+};
+
+use interprocess::local_socket::LocalSocketStream;
+use rand::Rng;
+use serde_json::json;
+use tracing::{error, info, warn};
+
+use std::{
+    collections::HashMap,
+    io::BufReader,
+    mem,
+    path::{Path, PathBuf},
+    process::Child,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+// How long a stopping server waits for its child servers to exit before it
+// kills them.
+const CHILD_STOP_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Child servers are checked this often. One that exited or stopped answering
+// is started again after a backoff, which doubles with every restart that
+// didn't last so a child that can't start doesn't keep the machine busy.
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+const FIRST_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+// A child that ran this long starts over from the first backoff, and a new
+// one has this long to take connections before it is pinged.
+const SETTLE_TIME: Duration = Duration::from_secs(30);
+
+struct ChildServer {
+    address: PathBuf,
+    // What it was started with, to start it again
+    root: PathBuf,
+    shard: Option<Shard>,
+    command: Option<ChildCommand>,
+    // None while it is down
+    process: Option<Child>,
+    started: Instant,
+    backoff: Duration,
+    restart_at: Instant,
+}
+
+impl ChildServer {
+    fn start(&mut self, args: &Args) {
+        self.started = Instant::now();
+        match spawn_child_server(args, &self.root, self.shard, self.command.as_ref()) {
+            Ok(process) => self.process = Some(process),
+            Err(e) => {
+                error!("{}", e);
+                self.schedule_restart();
+            }
+        }
+    }
+
+    fn schedule_restart(&mut self) {
+        self.backoff = if self.started.elapsed() >= SETTLE_TIME { FIRST_BACKOFF } else { (self.backoff * 2).clamp(FIRST_BACKOFF, MAX_BACKOFF) };
+        self.restart_at = Instant::now() + self.backoff;
+    }
+}
+
+// The servers requests are forwarded to, and the processes this server
+// started. Additional directories come and go with the .hanoi of the root.
+pub struct ChildServers {
+    pub forward_dirs: Vec<PathBuf>,
+    servers: Vec<ChildServer>,
+    args: Args,
+    // Cleared once the server stops, so children that exit with it are not
+    // started again
+    supervised: bool,
+}
+
+impl ChildServers {
+    pub fn new(args: &Args) -> ChildServers {
+        ChildServers { forward_dirs: Vec::new(), servers: Vec::new(), args: args.clone(), supervised: true }
+    }
+
+    // Starts the server for `address`, which serves `root` or one shard of
+    // it.
+    pub fn spawn(&mut self, address: PathBuf, root: &Path, shard: Option<Shard>, command: Option<&ChildCommand>) {
+        let mut server = ChildServer {
+            address,
+            root: root.to_path_buf(),
+            shard,
+            command: command.cloned(),
+            process: None,
+            started: Instant::now(),
+            backoff: Duration::ZERO,
+            restart_at: Instant::now(),
+        };
+        server.start(&self.args);
+        self.servers.push(server);
+    }
+
+    // How long until the server for `address` is started again, if it is
+    // down.
+    pub fn restarting_in(&self, address: &Path) -> Option<Duration> {
+        self.servers
+            .iter()
+            .find(|server| server.address == address && server.process.is_none())
+            .map(|server| server.restart_at.saturating_duration_since(Instant::now()))
+    }
+
+    // Starts servers for the additional directories that were added to the
+    // .hanoi and stops the ones of the directories that were removed.
+    pub fn update_additional_dirs(&mut self, additional_dirs: &[PathBuf], child_commands: &HashMap<PathBuf, ChildCommand>) {
+        let removed: Vec<PathBuf> = self.forward_dirs.iter().filter(|dir| !additional_dirs.contains(dir)).cloned().collect();
+        for dir in &removed {
+            request_stop(&self.args, dir);
+        }
+        let (mut stopped, servers) = mem::take(&mut self.servers).into_iter().partition(|server| removed.contains(&server.address));
+        self.servers = servers;
+        stop_children(&mut stopped);
+        self.forward_dirs.retain(|dir| !removed.contains(dir));
+        let added: Vec<PathBuf> = additional_dirs.iter().filter(|dir| !self.forward_dirs.contains(dir)).cloned().collect();
+        for dir in added {
+            self.spawn(dir.clone(), &dir, None, child_commands.get(&dir));
+            self.forward_dirs.push(dir);
+        }
+    }
+
+    // Notes the children that exited and starts the ones whose backoff is
+    // over. Returns how long until the next check.
+    fn check(&mut self) -> Duration {
+        if !self.supervised {
+            return HEALTH_CHECK_INTERVAL;
+        }
+        for server in &mut self.servers {
+            if let Some(status) = server.process.as_mut().and_then(|process| process.try_wait().ok().flatten()) {
+                server.process = None;
+                server.schedule_restart();
+                warn!("{}", message!(ChildDown, server.address.display(), status, HumanDuration(server.backoff)));
+                events::emit("child_exited", json!({ "root": server.address, "status": status.to_string() }));
+            }
+            if server.process.is_none() && Instant::now() >= server.restart_at {
+                // What a killed child left behind would keep the new one out
+                runtime::clean_up(&server.address, None);
+                server.start(&self.args);
+                if server.process.is_some() {
+                    info!("{}", message!(ChildRestarted, server.address.display()));
+                    events::emit("child_restarted", json!({ "root": server.address }));
+                }
+            }
+        }
+        // A restart doesn't wait for the next regular check
+        self.servers
+            .iter()
+            .filter(|server| server.process.is_none())
+            .map(|server| server.restart_at.saturating_duration_since(Instant::now()))
+            .fold(HEALTH_CHECK_INTERVAL, Duration::min)
+    }
+
+    // Kills the children that are still running but no longer answer, so
+    // the next check starts them again.
+    fn kill_unresponsive(&mut self, unresponsive: &[PathBuf]) {
+        for server in self.servers.iter_mut().filter(|server| unresponsive.contains(&server.address)) {
+            if let Some(process) = server.process.as_mut() {
+                warn!("{}", message!(ChildUnresponsive, server.address.display()));
+                let _ = process.kill();
+                let _ = process.wait();
+            }
+        }
+    }
+
+    // Waits for the children, which were sent the --stop request too.
+    pub fn stop(&mut self) {
+        self.supervised = false;
+        stop_children(&mut self.servers);
+    }
+
+    // Sends --stop to every child, for a server that stops without being
+    // asked to by a client.
+    pub fn request_stop_all(&mut self) {
+        self.supervised = false;
+        for server in &self.servers {
+            request_stop(&self.args, &server.address);
+        }
+    }
+
+    pub fn stop_supervising(&mut self) {
+        self.supervised = false;
+    }
+}
+
+// Checks the child servers in the background for as long as the server runs.
+pub fn supervise(child_servers: Arc<Mutex<ChildServers>>) {
+    thread::spawn(move || {
+        let mut last_ping = Instant::now();
+        loop {
+            let wait = watchdog::lock("child servers", &child_servers).check();
+            thread::sleep(wait);
+            if last_ping.elapsed() < HEALTH_CHECK_INTERVAL {
+                continue;
+            }
+            last_ping = Instant::now();
+            // Pinging takes a while, the children are not locked meanwhile
+            let settled: Vec<PathBuf> = watchdog::lock("child servers", &child_servers)
+                .servers
+                .iter()
+                .filter(|server| server.process.is_some() && server.started.elapsed() >= SETTLE_TIME)
+                .map(|server| server.address.clone())
+                .collect();
+            let unresponsive: Vec<PathBuf> = settled.into_iter().filter(|address| runtime::ping(address).is_err()).collect();
+            if !unresponsive.is_empty() {
+                watchdog::lock("child servers", &child_servers).kill_unresponsive(&unresponsive);
+            }
+        }
+    });
+}
+
+// Sends --stop to the server of `dir` and waits for its answer.
+fn request_stop(args: &Args, dir: &Path) {
+    let Ok(stream) = LocalSocketStream::connect(runtime::socket_name(dir)) else {
+        return;
+    };
+    let mut stop_args = args.clone();
+    stop_args.stop = true;
+    stop_args.main_server = true;
+    stop_args.codec = Encoding::Bincode;
+    let mut reader = BufReader::new(stream);
+    if write_request(&mut reader, &stop_args, rand::thread_rng().gen(), auth::read_token(dir)).is_ok() {
+        while read_frame(&mut reader, &Bincode).is_some_and(|frame| !matches!(frame, Frame::EndOfResults { last: true })) {}
+    }
+}
+
+// Waits for the child servers to exit and kills the ones that don't in time.
+fn stop_children(servers: &mut [ChildServer]) {
+    let deadline = Instant::now() + CHILD_STOP_TIMEOUT;
+    for process in servers.iter_mut().filter_map(|server| server.process.as_mut()) {
+        while matches!(process.try_wait(), Ok(None)) && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(50));
+        }
+        if matches!(process.try_wait(), Ok(None)) {
+            let _ = process.kill();
+            let _ = process.wait();
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use crate::compaction;
+
+use regex::Regex;
+
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::{Arc, OnceLock},
+};
+
+pub struct Symbol {
+    pub name: String,
+    pub kind: &'static str,
+    pub line: usize,
+    // How many definitions it is nested in, always 0 for the regexes
+    pub depth: usize,
+}
+
+struct LanguageRules {
+    extensions: &'static [&'static str],
+    rules: Vec<(&'static str, Regex)>,
+}
+
+// Each rule captures the defined name in the `name` group. These are
+// deliberately loose: a definition that is missed is better than a hang on
+// a pathological regex.
+fn language_rules() -> &'static Vec<LanguageRules> {
+    static RULES: OnceLock<Vec<LanguageRules>> = OnceLock::new();
+    RULES.get_or_init(|| {
+        let rule = |kind, pattern: &str| (kind, Regex::new(pattern).unwrap());
+        vec![
+            LanguageRules {
+                extensions: &["rs"],
+                rules: vec![
+                    rule("function", r"^\s*(?:pub(?:\([^)]*\))?\s+)?(?:const\s+)?(?:async\s+)?(?:unsafe\s+)?(?:extern\s+\S+\s+)?fn\s+(?P<name>\w+)"),
+                    rule("struct", r"^\s*(?:pub(?:\([^)]*\))?\s+)?struct\s+(?P<name>\w+)"),
+                    rule("enum", r"^\s*(?:pub(?:\([^)]*\))?\s+)?enum\s+(?P<name>\w+)"),
+                    rule("trait", r"^\s*(?:pub(?:\([^)]*\))?\s+)?(?:unsafe\s+)?trait\s+(?P<name>\w+)"),
+                    rule("type", r"^\s*(?:pub(?:\([^)]*\))?\s+)?type\s+(?P<name>\w+)"),
+                    rule("macro", r"^\s*macro_rules!\s+(?P<name>\w+)"),
+                ],
+            },
+            LanguageRules {
+                extensions: &["c", "h", "cc", "cpp", "cxx", "hh", "hpp", "hxx"],
+                rules: vec![
+                    rule("class", r"^\s*(?:template\s*<[^>]*>\s*)?class\s+(?:\w+\s+)?(?P<name>\w+)\s*(?:final\s*)?[:{]?\s*$"),
+                    rule("struct", r"^\s*(?:typedef\s+)?struct\s+(?P<name>\w+)\s*\{?\s*$"),
+                    rule("enum", r"^\s*(?:typedef\s+)?enum\s+(?:class\s+)?(?P<name>\w+)"),
+                    rule("macro", r"^\s*#\s*define\s+(?P<name>\w+)"),
+                    rule("function", r"^[\w:<>\*&\s]+?[\s\*&](?P<name>[A-Za-z_][\w:~]*)\s*\([^;]*\)\s*(?:const\s*)?\{?\s*$"),
+                ],
+            },
+            LanguageRules {
+                extensions: &["py"],
+                rules: vec![
+                    rule("function", r"^\s*(?:async\s+)?def\s+(?P<name>\w+)"),
+                    rule("class", r"^\s*class\s+(?P<name>\w+)"),
+                ],
+            },
+            LanguageRules {
+                extensions: &["js", "jsx", "ts", "tsx", "mjs"],
+                rules: vec![
+                    rule("function", r"^\s*(?:export\s+)?(?:default\s+)?(?:async\s+)?function\s*\*?\s*(?P<name>\w+)"),
+                    rule("class", r"^\s*(?:export\s+)?(?:default\s+)?(?:abstract\s+)?class\s+(?P<name>\w+)"),
+                    rule("interface", r"^\s*(?:export\s+)?interface\s+(?P<name>\w+)"),
+                    rule("type", r"^\s*(?:export\s+)?type\s+(?P<name>\w+)\s*="),
+                    rule("function", r"^\s*(?:export\s+)?(?:const|let|var)\s+(?P<name>\w+)\s*=\s*(?:async\s*)?(?:\([^)]*\)|\w+)\s*=>"),
+                ],
+            },
+            LanguageRules {
+                extensions: &["go"],
+                rules: vec![
+                    rule("function", r"^func\s+(?:\([^)]*\)\s*)?(?P<name>\w+)"),
+                    rule("type", r"^type\s+(?P<name>\w+)"),
+                ],
+            },
+            LanguageRules {
+                extensions: &["java", "cs", "kt"],
+                rules: vec![
+                    rule("class", r"^\s*(?:(?:public|private|protected|internal|static|abstract|final|sealed|partial|data)\s+)*(?:class|interface|enum|record|struct|object)\s+(?P<name>\w+)"),
+                ],
+            },
+        ]
+    })
+}
+
+// Control flow that the C-like function rule would otherwise mistake for a
+// definition, e.g. `    else if (x) {`.
+const KEYWORDS: &[&str] = &["if", "else", "for", "while", "switch", "return", "sizeof", "catch"];
+
+pub fn extract_symbols(path: &Path, text: &str) -> Vec<Symbol> {
+    #[cfg(feature = "tree-sitter")]
+    if let Some(symbols) = crate::parsers::extract_symbols(path, text) {
+        return symbols;
+    }
+    let mut symbols = Vec::new();
+    let extension = match path.extension().and_then(|extension| extension.to_str()) {
+        Some(extension) => extension,
+        None => return symbols,
+    };
+    let language = match language_rules().iter().find(|language| language.extensions.contains(&extension)) {
+        Some(language) => language,
+        None => return symbols,
+    };
+    for (line_index, line) in text.lines().enumerate() {
+        for (kind, regex) in &language.rules {
+            if let Some(name) = regex.captures(line).and_then(|captures| captures.name("name")) {
+                if KEYWORDS.contains(&name.as_str()) {
+                    continue;
+                }
+                symbols.push(Symbol {
+                    name: name.as_str().to_string(),
+                    kind,
+                    line: line_index + 1,
+                    depth: 0,
+                });
+                break;
+            }
+        }
+    }
+    symbols
+}
+
+#[derive(Default)]
+pub struct SymbolIndex {
+    files: HashMap<Arc<Path>, Vec<Symbol>>,
+}
+
+impl SymbolIndex {
+    pub fn update(&mut self, path: Arc<Path>, text: &str) {
+        let symbols = extract_symbols(&path, text);
+        self.set(path, symbols);
+    }
+
+    pub fn set(&mut self, path: Arc<Path>, symbols: Vec<Symbol>) {
+        if symbols.is_empty() {
+            self.files.remove(&path);
+        } else {
+            self.files.insert(path, symbols);
+        }
+    }
+
+    pub fn remove(&mut self, path: &Path) {
+        self.files.remove(path);
+    }
+
+    pub fn slack(&self) -> usize {
+        compaction::slack(&self.files)
+    }
+
+    pub fn used(&self) -> usize {
+        compaction::used(&self.files)
+    }
+
+    pub fn shrink(&mut self) -> usize {
+        compaction::shrink(&mut self.files)
+    }
+
+    pub fn extend(&mut self, other: SymbolIndex) {
+        self.files.extend(other.files);
+    }
+
+    pub fn find<'a>(&'a self, name: &'a str) -> impl Iterator<Item = (&'a Arc<Path>, &'a Symbol)> + 'a {
+        self.all().filter(move |(_, symbol)| symbol.name == name)
+    }
+
+    // The symbols of `path` in the order they are defined.
+    pub fn of(&self, path: &Path) -> &[Symbol] {
+        self.files.get(path).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn all(&self) -> impl Iterator<Item = (&Arc<Path>, &Symbol)> {
+        self.files.iter().flat_map(|(path, symbols)| symbols.iter().map(move |symbol| (path, symbol)))
+    }
+}
@@ -0,0 +1,90 @@
+use crate::{
+    auth,
+    messages::message,
+    output::{relative_to, split_match},
+    protocol::Frame,
+    runtime, transport, Args, EXIT_ERROR,
+};
+
+use rand::Rng;
+
+use std::{
+    env, fs,
+    io::{self, Write},
+    path::Path,
+    process::ExitCode,
+};
+
+// Writes the symbols of the index of the server for `root` as a ctags file,
+// for editors that jump to definitions with one but have no language server:
+//   main	src/main.rs	12;"	f
+// The paths are relative to the directory of the file, lines are sorted by
+// name so vim and emacs can look them up with a binary search.
+pub fn tags_main(args: &Args, root: &Path) -> ExitCode {
+    let output = args.output.as_deref().unwrap_or("tags");
+    let cwd = env::current_dir().unwrap_or_default();
+    // Canonical, paths of the index are relative to it without any ..
+    let base = match output {
+        "-" => cwd.clone(),
+        _ => cwd.join(output).parent().and_then(|dir| fs::canonicalize(dir).ok()).unwrap_or_else(|| cwd.clone()),
+    };
+    let mut lines = Vec::new();
+    let mut error = None;
+    transport::forward(args.clone(), rand::thread_rng().gen(), &runtime::socket_name(root), auth::read_token(root), |frame| {
+        match frame {
+            Frame::ResultLine(line) => lines.push(line.trim_end().to_string()),
+            Frame::Error(message) => error = Some(message.clone()),
+            _ => {}
+        }
+        true
+    });
+    if let Some(message) = error {
+        eprintln!("{}", message);
+        return ExitCode::from(EXIT_ERROR);
+    }
+    let mut tags: Vec<String> = lines.iter().filter_map(|line| tag(line, &base)).collect();
+    tags.sort();
+    tags.dedup();
+    let mut file = String::from("!_TAG_FILE_FORMAT\t2\t/extended format/\n!_TAG_FILE_SORTED\t1\t/0=unsorted, 1=sorted, 2=foldcase/\n!_TAG_PROGRAM_NAME\thanoi\t//\n");
+    for tag in &tags {
+        file.push_str(tag);
+        file.push('\n');
+    }
+    let written = match output {
+        "-" => io::stdout().write_all(file.as_bytes()),
+        _ => fs::write(output, file),
+    };
+    match written {
+        Ok(()) if output == "-" => ExitCode::SUCCESS,
+        Ok(()) => {
+            eprintln!("{}", message!(TagsWritten, tags.len(), output));
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("{}", message!(TagsError, output, e));
+            ExitCode::from(EXIT_ERROR)
+        }
+    }
+}
+
+// A "path:line: kind name" line of the server as a tag.
+fn tag(line: &str, base: &Path) -> Option<String> {
+    let (path, line_num, symbol) = split_match(line)?;
+    let (kind, name) = symbol.split_once(' ')?;
+    let path = relative_to(Path::new(path), base);
+    Some(format!("{}\t{}\t{};\"\t{}", name, path.display(), line_num, kind_letter(kind)))
+}
+
+// The kinds of symbols.rs as the letters of universal-ctags.
+fn kind_letter(kind: &str) -> char {
+    match kind {
+        "function" => 'f',
+        "struct" => 's',
+        "class" | "impl" => 'c',
+        "enum" => 'g',
+        "interface" | "trait" => 'i',
+        "type" => 't',
+        "macro" => 'd',
+        _ => 'v',
+    }
+}
@@ -0,0 +1,162 @@
+use crate::{
+    auth::{self, Caller},
+    messages::message,
+};
+
+use std::{collections::HashMap, path::PathBuf};
+
+// Index namespaces hosted by a shared daemon for other users, declared in the
+// daemon's .hanoi:
+//
+//   [tenants]
+//   alice/web = /home/alice/web
+//
+//   [tenant_readers]
+//   alice/web = bob, carol
+//
+//   [tenant_tokens]
+//   alice = 3bb8a4e1c0f94d27
+//
+// Every tenant root is served by its own child server with the filters from
+// its own .hanoi. Only the owning user and the listed readers may query it,
+// as told by the token they present to --listen or --http. The local socket
+// only answers the owner of the daemon. A tenant token only opens the
+// tenants of its user, the token of the server opens everything.
+struct Tenant {
+    root: PathBuf,
+    readers: Vec<String>,
+}
+
+#[derive(Default)]
+pub struct Tenants {
+    tenants: HashMap<String, Tenant>,
+    // The user of every tenant token
+    tokens: Vec<(String, String)>,
+}
+
+fn split_name(line: &str) -> Result<(&str, &str), String> {
+    match line.split_once('=') {
+        Some((name, value)) if name.trim().split_once('/').is_some_and(|(user, label)| !user.is_empty() && !label.is_empty()) => Ok((name.trim(), value.trim())),
+        _ => Err(format!("expected \"user/label = ...\", found \"{}\"", line)),
+    }
+}
+
+impl Tenants {
+    pub fn parse_tenant(&mut self, line: &str) -> Result<(), String> {
+        let (name, root) = split_name(line)?;
+        self.tenants.insert(String::from(name), Tenant {
+            root: PathBuf::from(root),
+            readers: Vec::new(),
+        });
+        Ok(())
+    }
+
+    pub fn parse_readers(&mut self, line: &str) -> Result<(), String> {
+        let (name, readers) = split_name(line)?;
+        match self.tenants.get_mut(name) {
+            Some(tenant) => {
+                tenant.readers.extend(readers.split(',').map(str::trim).filter(|reader| !reader.is_empty()).map(String::from));
+                Ok(())
+            }
+            None => Err(format!("\"{}\" is not listed in [tenants]", name)),
+        }
+    }
+
+    pub fn parse_token(&mut self, line: &str) -> Result<(), String> {
+        match line.split_once('=').map(|(user, token)| (user.trim(), token.trim())) {
+            Some((user, token)) if !user.is_empty() && !token.is_empty() => {
+                self.tokens.push((String::from(token), String::from(user)));
+                Ok(())
+            }
+            _ => Err(format!("expected \"user = token\", found \"{}\"", line)),
+        }
+    }
+
+    pub fn roots(&self) -> impl Iterator<Item = &PathBuf> {
+        self.tenants.values().map(|tenant| &tenant.root)
+    }
+
+    // The root serving `name` if `caller` may query it, otherwise the
+    // message to send back. The user the daemon runs as reads every tenant
+    // root already.
+    pub fn resolve(&self, name: &str, caller: &Caller) -> Result<&PathBuf, String> {
+        let tenant = self.tenants.get(name).ok_or_else(|| message!(UnknownTenant, name))?;
+        let owner = name.split('/').next().unwrap_or("");
+        match caller {
+            Caller::Owner => Ok(&tenant.root),
+            Caller::User(user) if user == owner || tenant.readers.iter().any(|reader| reader == user) => Ok(&tenant.root),
+            Caller::User(user) => Err(message!(TenantAccessDenied, user, name)),
+        }
+    }
+
+    // Who presents `token` to --listen or --http, None for a token neither
+    // the server nor a tenant has.
+    pub fn caller_of_token(&self, server_token: &str, token: &str) -> Option<Caller> {
+        if auth::matches(server_token, token) {
+            return Some(Caller::Owner);
+        }
+        // Every token is compared, so the time taken doesn't tell which matched
+        let found = self.tokens.iter().fold(None, |found, (expected, user)| if auth::matches(expected, token) { Some(user) } else { found });
+        found.map(|user| Caller::User(user.clone()))
+    }
+
+    // Whether `caller` may send a request for `tenant`, None being the
+    // index of the server itself. Tenant users may only send the requests
+    // that read the index, see reads_only.
+    pub fn authorize(&self, caller: &Caller, tenant: Option<&str>, reads_only: bool) -> Result<(), String> {
+        match (caller, tenant) {
+            (Caller::Owner, _) => Ok(()),
+            (_, Some(name)) if reads_only => self.resolve(name, caller).map(|_| ()),
+            (_, _) => Err(message!(AccessDenied)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tenants() -> Tenants {
+        let mut tenants = Tenants::default();
+        tenants.parse_tenant("alice/web = /srv/alice").unwrap();
+        tenants.parse_readers("alice/web = bob").unwrap();
+        tenants.parse_token("carol = carol-token").unwrap();
+        tenants
+    }
+
+    #[test]
+    fn only_owner_and_readers_resolve() {
+        let tenants = tenants();
+        let user = |name: &str| Caller::User(String::from(name));
+        assert!(tenants.resolve("alice/web", &user("alice")).is_ok());
+        assert!(tenants.resolve("alice/web", &user("bob")).is_ok());
+        assert!(tenants.resolve("alice/web", &user("carol")).is_err());
+        assert!(tenants.resolve("alice/web", &Caller::Owner).is_ok());
+        assert!(tenants.resolve("alice/app", &Caller::Owner).is_err());
+    }
+
+    #[test]
+    fn tokens_name_their_caller() {
+        let tenants = tenants();
+        assert_eq!(tenants.caller_of_token("server-token", "server-token"), Some(Caller::Owner));
+        assert_eq!(tenants.caller_of_token("server-token", "carol-token"), Some(Caller::User(String::from("carol"))));
+        assert_eq!(tenants.caller_of_token("server-token", "alice"), None);
+    }
+
+    #[test]
+    fn tenant_tokens_only_open_tenants() {
+        let tenants = tenants();
+        let carol = Caller::User(String::from("carol"));
+        assert!(tenants.authorize(&carol, None, true).is_err());
+        assert!(tenants.authorize(&carol, Some("alice/web"), true).is_err());
+        assert!(tenants.authorize(&Caller::Owner, None, false).is_ok());
+    }
+
+    #[test]
+    fn readers_only_read() {
+        let tenants = tenants();
+        let bob = Caller::User(String::from("bob"));
+        assert!(tenants.authorize(&bob, Some("alice/web"), true).is_ok());
+        assert!(tenants.authorize(&bob, Some("alice/web"), false).is_err());
+    }
+}
@@ -0,0 +1,134 @@
+use crate::{protocol::Frame, replies::ReplyStream};
+
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+// Where the time of one request went, for --stats --verbose. Every server the
+// request passes through records its stages and sends them to the client as
+// Trace frames after its results. With --stats alone the servers only send
+// what they searched, as a QueryStats frame.
+
+pub fn now_micros() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_micros() as u64)
+}
+
+// What a search went through on one server.
+#[derive(Default)]
+pub struct QueryCounts {
+    pub scanned: u64,
+    // Files whose content isn't loaded or can't be read
+    pub skipped: u64,
+    pub matches: u64,
+}
+
+impl QueryCounts {
+    pub fn send(&self, id: u64, server: &str, start: Instant, replies: &mut ReplyStream) {
+        let _ = replies.send(Frame::QueryStats {
+            id,
+            server: server.to_string(),
+            scanned: self.scanned,
+            skipped: self.skipped,
+            matches: self.matches,
+            micros: start.elapsed().as_micros() as u64,
+        });
+    }
+}
+
+pub struct Trace {
+    id: u64,
+    server: String,
+    // (server, stage, microseconds)
+    stages: Vec<(String, String, u64)>,
+}
+
+impl Trace {
+    pub fn new(id: u64, server: String) -> Trace {
+        Trace {
+            id,
+            server,
+            stages: Vec::new(),
+        }
+    }
+
+    pub fn record(&mut self, stage: &str, start: Instant) {
+        self.stages.push((self.server.clone(), String::from(stage), start.elapsed().as_micros() as u64));
+    }
+
+    // Time the request spent between the sender and this server, waiting
+    // behind other requests and in the pipe.
+    pub fn record_queue_wait(&mut self, sent_at: u64) {
+        self.stages.push((self.server.clone(), String::from("queue_wait"), now_micros().saturating_sub(sent_at)));
+    }
+
+    // The round trip through a child server, its own stages included.
+    pub fn record_fan_out(&mut self, child: &str, start: Instant) {
+        self.stages.push((format!("{} -> {}", self.server, child), String::from("fan_out"), start.elapsed().as_micros() as u64));
+    }
+
+    pub fn send(&self, replies: &mut ReplyStream) {
+        for (server, stage, micros) in &self.stages {
+            let _ = replies.send(Frame::Trace {
+                id: self.id,
+                server: server.clone(),
+                stage: stage.clone(),
+                micros: *micros,
+            });
+        }
+    }
+}
+
+// Collects the stages sent back by the servers on the client.
+pub struct TraceReport {
+    id: u64,
+    servers: BTreeMap<String, Vec<(String, u64)>>,
+    // The search time of every server that searched
+    queries: BTreeMap<String, u64>,
+    counts: QueryCounts,
+}
+
+impl TraceReport {
+    pub fn new(id: u64) -> TraceReport {
+        TraceReport {
+            id,
+            servers: BTreeMap::new(),
+            queries: BTreeMap::new(),
+            counts: QueryCounts::default(),
+        }
+    }
+
+    pub fn add_query(&mut self, id: u64, server: String, counts: QueryCounts, micros: u64) {
+        if id == self.id {
+            self.counts.scanned += counts.scanned;
+            self.counts.skipped += counts.skipped;
+            self.counts.matches += counts.matches;
+            self.queries.insert(server, micros);
+        }
+    }
+
+    pub fn add(&mut self, id: u64, server: String, stage: String, micros: u64) {
+        if id == self.id {
+            self.servers.entry(server).or_default().push((stage, micros));
+        }
+    }
+
+    // The trailer of --stats, with the stages of every server when
+    // `verbose`.
+    pub fn print(&self, connect: Duration, total: Duration, verbose: bool) {
+        if !self.queries.is_empty() {
+            println!("files scanned: {}, skipped: {}, matches: {}, servers queried: {}", self.counts.scanned, self.counts.skipped, self.counts.matches, self.queries.len());
+            for (server, micros) in &self.queries {
+                println!("{}: {:?}", server, Duration::from_micros(*micros));
+            }
+        }
+        if verbose {
+            println!("connect: {:?}", connect);
+            for (server, stages) in &self.servers {
+                let stages: Vec<String> = stages.iter().map(|(stage, micros)| format!("{} {:?}", stage, Duration::from_micros(*micros))).collect();
+                println!("{}: {}", server, stages.join(", "));
+            }
+        }
+        println!("total: {:?}", total);
+    }
+}
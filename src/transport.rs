@@ -0,0 +1,252 @@
+use crate::{
+    codec::{Bincode, Encoding},
+    messages::message,
+    replies::ReplyStream,
+    protocol::{read_frame, Frame, Header, JsonRequest, Protocol, PROTOCOL_VERSION, RELEASE},
+    read_from_pipe, reads_only,
+    scheduler::Scheduler,
+    tenants::Tenants,
+    write_request, Args, Cli,
+};
+
+use interprocess::local_socket::LocalSocketStream;
+
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+// A byte stream requests and replies travel over: local sockets between the
+// processes on one machine, TCP between a --connect client and a --listen
+// server.
+pub trait Transport: Read + Write {
+    // Makes reads and writes that block longer than `timeout` fail instead
+    // of waiting forever for a peer that died.
+    fn set_timeout(&self, timeout: Duration) -> io::Result<()>;
+
+    // Another handle to the same stream, so one thread can read requests
+    // while others write replies.
+    fn duplicate(&self) -> io::Result<Self>
+    where
+        Self: Sized;
+}
+
+#[cfg(unix)]
+impl Transport for LocalSocketStream {
+    fn set_timeout(&self, timeout: Duration) -> io::Result<()> {
+        use std::os::unix::io::AsRawFd;
+
+        let time = libc::timeval {
+            tv_sec: timeout.as_secs() as libc::time_t,
+            tv_usec: timeout.subsec_micros() as libc::suseconds_t,
+        };
+        for option in [libc::SO_RCVTIMEO, libc::SO_SNDTIMEO] {
+            // SAFETY: the descriptor belongs to `self` and `time` outlives the call
+            let result = unsafe {
+                libc::setsockopt(self.as_raw_fd(), libc::SOL_SOCKET, option, &time as *const libc::timeval as *const libc::c_void, std::mem::size_of::<libc::timeval>() as libc::socklen_t)
+            };
+            if result != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+
+    fn duplicate(&self) -> io::Result<Self> {
+        use std::os::unix::io::{AsRawFd, BorrowedFd, FromRawFd, IntoRawFd};
+
+        // SAFETY: the descriptor belongs to `self`, which outlives the borrow
+        let fd = unsafe { BorrowedFd::borrow_raw(self.as_raw_fd()) }.try_clone_to_owned()?;
+        // SAFETY: the new descriptor is owned by nothing else
+        Ok(unsafe { LocalSocketStream::from_raw_fd(fd.into_raw_fd()) })
+    }
+}
+
+// Named pipes have no per operation timeout, so they keep blocking.
+#[cfg(not(unix))]
+impl Transport for LocalSocketStream {
+    fn set_timeout(&self, _timeout: Duration) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn duplicate(&self) -> io::Result<Self> {
+        use std::os::windows::io::{AsRawHandle, BorrowedHandle, FromRawHandle, IntoRawHandle};
+
+        // SAFETY: the handle belongs to `self`, which outlives the borrow
+        let handle = unsafe { BorrowedHandle::borrow_raw(self.as_raw_handle()) }.try_clone_to_owned()?;
+        // SAFETY: the new handle is owned by nothing else
+        Ok(unsafe { LocalSocketStream::from_raw_handle(handle.into_raw_handle()) })
+    }
+}
+
+impl Transport for TcpStream {
+    fn set_timeout(&self, timeout: Duration) -> io::Result<()> {
+        self.set_read_timeout(Some(timeout))?;
+        self.set_write_timeout(Some(timeout))
+    }
+
+    fn duplicate(&self) -> io::Result<Self> {
+        self.try_clone()
+    }
+}
+
+// How long a server waits on a client that stopped reading or writing before
+// dropping it, set from --pipe-timeout.
+static PIPE_TIMEOUT_MS: AtomicU64 = AtomicU64::new(10_000);
+
+pub fn set_pipe_timeout(timeout: Duration) {
+    PIPE_TIMEOUT_MS.store(timeout.as_millis() as u64, Ordering::Relaxed);
+}
+
+pub fn pipe_timeout() -> Duration {
+    Duration::from_millis(PIPE_TIMEOUT_MS.load(Ordering::Relaxed))
+}
+
+// Serves remote clients on `address` by relaying each of them to the server
+// at `local_address`, so the servers handle them like any other client.
+// Remote clients must present `token` or a token of `tenants`, and may speak
+// either protocol::Protocol. `scheduler` decides when their requests are
+// relayed.
+pub fn listen(address: &str, local_address: PathBuf, token: String, tenants: Arc<Tenants>, scheduler: Arc<Scheduler>) -> std::io::Result<()> {
+    let listener = TcpListener::bind(address)?;
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let local_address = local_address.clone();
+            let token = token.clone();
+            let tenants = Arc::clone(&tenants);
+            let scheduler = Arc::clone(&scheduler);
+            thread::spawn(move || relay(stream, &local_address, token, &tenants, &scheduler));
+        }
+    });
+    Ok(())
+}
+
+// Answers a remote client with `error` as its only reply.
+fn refuse(writer: &mut TcpStream, protocol: Protocol, encoding: Encoding, error: String) {
+    let _ = protocol.write_frame(writer, &encoding, &Frame::Error(error));
+    let _ = protocol.write_frame(writer, &encoding, &Frame::EndOfResults { last: true });
+}
+
+fn relay(stream: TcpStream, local_address: &Path, token: String, tenants: &Tenants, scheduler: &Scheduler) {
+    let _ = stream.set_timeout(pipe_timeout());
+    let Ok(client) = stream.peer_addr().map(|address| address.ip()) else {
+        return;
+    };
+    let mut remote_reader = BufReader::new(stream);
+    // A bincode request starts with the length of its header, which is never
+    // as long as the value of '{'
+    let protocol = match remote_reader.fill_buf() {
+        Ok([b'{', ..]) => Protocol::Json,
+        Ok([_, ..]) => Protocol::Bincode,
+        _ => return,
+    };
+    let (header, json_args) = match protocol {
+        Protocol::Bincode => {
+            match read_from_pipe::<Header, _>(&mut remote_reader, &Bincode) {
+                Ok(header) => (header, None),
+                Err(e) => return refuse(remote_reader.get_mut(), protocol, Encoding::Bincode, message!(ServerFailed, e)),
+            }
+        }
+        Protocol::Json => {
+            let mut line = String::new();
+            if remote_reader.read_line(&mut line).is_err() {
+                return;
+            }
+            match JsonRequest::parse(&line) {
+                Ok(request) => (Header { version: request.version, ..Header::new(true, request.trace_id, request.token) }, Some(request.args)),
+                Err(e) => return refuse(remote_reader.get_mut(), protocol, Encoding::Bincode, message!(InvalidRequest, e)),
+            }
+        }
+    };
+    if header.version_only {
+        let _ = protocol.write_frame(remote_reader.get_mut(), &Bincode, &Frame::Version { release: String::from(RELEASE), protocol: PROTOCOL_VERSION });
+        let _ = protocol.write_frame(remote_reader.get_mut(), &Bincode, &Frame::EndOfResults { last: true });
+        return;
+    }
+    if header.version != PROTOCOL_VERSION {
+        return refuse(remote_reader.get_mut(), protocol, Encoding::Bincode, message!(ProtocolMismatch, header.version, PROTOCOL_VERSION));
+    }
+    let encoding = header.encoding;
+    let mut args: Args = match json_args {
+        None => {
+            match read_from_pipe(&mut remote_reader, &encoding) {
+                Ok(args) => args,
+                Err(e) => return refuse(remote_reader.get_mut(), protocol, encoding, message!(ServerFailed, e)),
+            }
+        }
+        Some(json_args) => match Cli::try_parse_remote(json_args) {
+            Ok(args) => args,
+            Err(e) => return refuse(remote_reader.get_mut(), protocol, encoding, message!(InvalidRequest, e.to_string().trim_end())),
+        },
+    };
+    // Checked once all of the request is read, so the client isn't cut off
+    // while it is still sending. The local server sees the relay as its
+    // owner, so tenant access is decided here.
+    let Some(caller) = tenants.caller_of_token(&token, &header.token) else {
+        return refuse(remote_reader.get_mut(), protocol, encoding, message!(AccessDenied));
+    };
+    if let Err(e) = tenants.authorize(&caller, args.tenant.as_deref(), reads_only(&args)) {
+        return refuse(remote_reader.get_mut(), protocol, encoding, e);
+    }
+    let attached = stays_attached(&args);
+    let turn = if attached { None } else { scheduler.wait_turn(client) };
+    if !attached && turn.is_none() {
+        return refuse(remote_reader.get_mut(), protocol, encoding, message!(TooManyRequests));
+    }
+    // The local server only needs to read it back here
+    args.codec = Encoding::Bincode;
+    if args.compress && protocol == Protocol::Bincode {
+        let Ok(stream) = remote_reader.get_ref().try_clone() else {
+            return;
+        };
+        // Chunked and compressed the way the local server would
+        let mut replies = ReplyStream::new(stream, encoding).compressed(true);
+        forward(args, header.trace_id, local_address, token, |frame| match frame {
+            Frame::EndOfResults { last: true } => {
+                replies.end_all();
+                true
+            }
+            Frame::EndOfResults { last: false } => replies.end_batch().is_ok(),
+            frame => replies.send(frame.clone()).is_ok(),
+        });
+        return;
+    }
+    forward(args, header.trace_id, local_address, token, |frame| protocol.write_frame(remote_reader.get_mut(), &encoding, frame).is_ok());
+}
+
+// Clients following events, a published query or a watch stay attached
+// until they go away. They don't wait for a turn of the Scheduler, they
+// would hold on to it.
+pub fn stays_attached(args: &Args) -> bool {
+    args.events || args.subscribe.is_some() || args.watch
+}
+
+// Sends `args` to the server at `local_address` as if from a client of its
+// own, and passes every frame of the replies to `reply` until it returns
+// false or the server is done.
+pub fn forward(mut args: Args, trace_id: u64, local_address: &Path, token: String, mut reply: impl FnMut(&Frame) -> bool) {
+    args.main_server = true;
+    // Replies are compressed for the remote client, if at all, not here
+    args.compress = false;
+    args.session = false;
+    let Ok(server_pipe) = LocalSocketStream::connect(local_address) else {
+        return;
+    };
+    let mut server_reader = BufReader::new(server_pipe);
+    if let Err(e) = write_request(&mut server_reader, &args, trace_id, token) {
+        reply(&e.frame());
+        return;
+    }
+    while let Some(frame) = read_frame(&mut server_reader, &args.codec) {
+        if !reply(&frame) || frame == (Frame::EndOfResults { last: true }) {
+            return;
+        }
+    }
+}
@@ -0,0 +1,305 @@
+use crate::{
+    auth,
+    messages::message,
+    output::{label, split_match, to_ascii, OutputFormat, ResultKind},
+    preview::Previewer,
+    protocol::{decompress_frames, read_frame, Frame},
+    runtime, write_request, Args, EXIT_ERROR,
+};
+
+use interprocess::local_socket::LocalSocketStream;
+use rand::Rng;
+use ratatui::{
+    crossterm::{
+        event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+        execute,
+        terminal::{self, EnterAlternateScreen, LeaveAlternateScreen},
+    },
+    layout::{Constraint, Layout, Position},
+    style::{Modifier, Style},
+    widgets::{Block, Borders, List, ListState, Paragraph},
+    DefaultTerminal, Frame as Screen,
+};
+
+use std::{
+    env,
+    io::{self, BufReader, IsTerminal},
+    path::Path,
+    process::{Command, ExitCode},
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+    time::{Duration, Instant},
+};
+
+// How long typing has to pause before the term is searched.
+const DEBOUNCE: Duration = Duration::from_millis(150);
+// Results past this many are not shown, the term is too common to browse.
+const MAX_RESULTS: usize = 1000;
+
+enum Key {
+    Char(char),
+    Backspace,
+    ClearLine,
+    Up,
+    Down,
+    Enter,
+    Quit,
+}
+
+// Results of the search for the term typed at `generation`.
+struct Results {
+    generation: u64,
+    lines: Vec<String>,
+    message: Option<String>,
+}
+
+struct State {
+    query: String,
+    // Bumped with every edit, so results of older terms are dropped
+    generation: u64,
+    edited: Option<Instant>,
+    searching: bool,
+    lines: Vec<String>,
+    message: Option<String>,
+    // The selected result, and how far the list is scrolled to show it
+    list: ListState,
+}
+
+// How the screen is drawn for --accessible, --verbose-labels and --ascii:
+// the status line announces the selected result as the labels of the
+// client do, the selection is marked with "> " rather than by color alone
+// and the cursor stays on it, where screen readers follow it.
+struct Look {
+    labels: bool,
+    ascii: bool,
+}
+
+// Searches the server for `root` as the user types, with the lines around
+// the selected result below the list. Enter opens the result in $VISUAL or
+// $EDITOR, Esc and Ctrl-C quit.
+pub fn run(args: &Args, root: &Path) -> ExitCode {
+    if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+        eprintln!("{}", message!(TuiUnavailable));
+        return ExitCode::from(EXIT_ERROR);
+    }
+    // Also gives the terminal back before the message of a panic is printed
+    let Ok(mut terminal) = ratatui::try_init() else {
+        ratatui::restore();
+        eprintln!("{}", message!(TuiUnavailable));
+        return ExitCode::from(EXIT_ERROR);
+    };
+    let exit_code = search_as_you_type(&mut terminal, args, root);
+    ratatui::restore();
+    exit_code
+}
+
+fn search_as_you_type(terminal: &mut DefaultTerminal, args: &Args, root: &Path) -> ExitCode {
+    let look = Look { labels: args.verbose_labels || args.accessible, ascii: args.ascii || args.accessible };
+    let (sender, receiver) = mpsc::channel();
+    let mut state = State { query: String::new(), generation: 0, edited: None, searching: false, lines: Vec::new(), message: None, list: ListState::default() };
+    loop {
+        if terminal.draw(|screen| draw(screen, &mut state, root, &look)).is_err() {
+            return ExitCode::from(EXIT_ERROR);
+        }
+        for key in read_keys(Duration::from_millis(50)) {
+            match key {
+                Key::Quit => return ExitCode::SUCCESS,
+                Key::Char(c) => state.edit(|query| query.push(c)),
+                Key::Backspace => state.edit(|query| {
+                    query.pop();
+                }),
+                Key::ClearLine => state.edit(String::clear),
+                Key::Up => state.select(state.selected().saturating_sub(1)),
+                Key::Down => state.select(state.selected() + 1),
+                Key::Enter => {
+                    if let Some((path, line_num, _)) = state.lines.get(state.selected()).and_then(|line| split_match(line)) {
+                        open_in_editor(terminal, args.editor.as_deref(), path, line_num);
+                    }
+                }
+            }
+        }
+        if state.edited.is_some_and(|edited| edited.elapsed() >= DEBOUNCE) {
+            state.edited = None;
+            state.searching = true;
+            search_in_background(args, root, &state.query, state.generation, sender.clone());
+        }
+        state.receive(&receiver);
+    }
+}
+
+impl State {
+    fn edit(&mut self, edit: impl FnOnce(&mut String)) {
+        edit(&mut self.query);
+        self.generation += 1;
+        self.edited = Some(Instant::now());
+    }
+
+    fn selected(&self) -> usize {
+        self.list.selected().unwrap_or(0)
+    }
+
+    fn select(&mut self, index: usize) {
+        self.list.select((!self.lines.is_empty()).then(|| index.min(self.lines.len() - 1)));
+    }
+
+    fn receive(&mut self, receiver: &Receiver<Results>) {
+        while let Ok(results) = receiver.try_recv() {
+            if results.generation != self.generation {
+                continue;
+            }
+            self.searching = false;
+            self.lines = results.lines;
+            self.message = results.message;
+            self.list = ListState::default();
+            self.select(0);
+        }
+    }
+}
+
+fn search_in_background(args: &Args, root: &Path, term: &str, generation: u64, sender: Sender<Results>) {
+    let mut args = args.clone();
+    args.tui = false;
+    args.main_server = true;
+    args.compress = false;
+    args.session = false;
+    // Matches are read as "path:line: text", without the offsets of these
+    args.json = false;
+    args.fzf = false;
+    args.format = OutputFormat::Plain;
+    args.term = Some(term.to_string()).filter(|term| !term.is_empty());
+    let root = root.to_path_buf();
+    thread::spawn(move || {
+        let mut results = Results { generation, lines: Vec::new(), message: None };
+        if args.term.is_some() {
+            search(&args, &root, &mut results);
+        }
+        let _ = sender.send(results);
+    });
+}
+
+fn search(args: &Args, root: &Path, results: &mut Results) {
+    let Ok(stream) = LocalSocketStream::connect(runtime::socket_name(root)) else {
+        results.message = Some(message!(NoServer));
+        return;
+    };
+    let mut reader = BufReader::new(stream);
+    if let Err(e) = write_request(&mut reader, args, rand::thread_rng().gen(), auth::read_token(root)) {
+        results.message = Some(e.to_string());
+        return;
+    }
+    while let Some(frame) = read_frame(&mut reader, &args.codec) {
+        let frames = match frame {
+            Frame::Compressed(data) => decompress_frames(&data, &args.codec).unwrap_or_default(),
+            frame => vec![frame],
+        };
+        for frame in frames {
+            match frame {
+                Frame::EndOfResults { last: true } => return,
+                Frame::ResultLine(line) if !line.trim().is_empty() => results.lines.push(line.trim_end().to_string()),
+                Frame::Error(message) | Frame::Warning(message) => results.message = Some(message),
+                _ => {}
+            }
+            // Enough to browse, the rest is dropped with the connection
+            if results.lines.len() >= MAX_RESULTS {
+                return;
+            }
+        }
+    }
+}
+
+fn draw(screen: &mut Screen, state: &mut State, root: &Path, look: &Look) {
+    let shown = |text: &str| {
+        let text: String = text.chars().map(|c| if c.is_control() { ' ' } else { c }).collect();
+        if look.ascii {
+            to_ascii(&text)
+        } else {
+            text
+        }
+    };
+    let prefix = format!("{}/", root.display());
+    let relative = |line: &str| shown(line.strip_prefix(&prefix).unwrap_or(line));
+    let [input_area, status_area, list_area, preview_area] = Layout::vertical([Constraint::Length(1), Constraint::Length(1), Constraint::Fill(1), Constraint::Fill(1)]).areas(screen.area());
+
+    screen.render_widget(Paragraph::new(format!("> {}", shown(&state.query))), input_area);
+    let count = state.lines.len();
+    let status = match (&state.message, state.searching, state.lines.get(state.selected())) {
+        (_, true, _) => message!(TuiSearching),
+        (Some(message), false, _) => shown(message),
+        (None, false, Some(line)) if look.labels => label(ResultKind::Matches, state.selected(), count, &relative(line)),
+        (None, false, _) => message!(TuiResults, count, if count >= MAX_RESULTS { "+" } else { "" }),
+    };
+    screen.render_widget(Paragraph::new(status).style(Style::new().add_modifier(Modifier::DIM)), status_area);
+
+    let list = List::new(state.lines.iter().map(|line| relative(line))).highlight_style(Style::new().add_modifier(Modifier::REVERSED)).highlight_symbol(if look.labels { "> " } else { "" });
+    screen.render_stateful_widget(list, list_area, &mut state.list);
+
+    // Plain text, escapes would be cut off at the edge of the terminal
+    let previewer = Previewer::plain(preview_area.height.saturating_sub(1) as usize / 2);
+    let snippet = state
+        .lines
+        .get(state.selected())
+        .and_then(|line| split_match(line))
+        .and_then(|(path, line_num, _)| previewer.snippet(path, line_num.parse().ok()?))
+        .unwrap_or_default();
+    let snippet: Vec<String> = snippet.iter().map(|line| shown(line)).collect();
+    screen.render_widget(Paragraph::new(snippet.join("\n")).block(Block::new().borders(Borders::TOP)), preview_area);
+
+    let cursor = match state.list.selected() {
+        Some(selected) if look.labels => Position::new(list_area.x, list_area.y + (selected - state.list.offset()) as u16),
+        // Back at the end of the term
+        _ => Position::new((state.query.chars().count() as u16 + 2).min(input_area.width.saturating_sub(1)), input_area.y),
+    };
+    screen.set_cursor_position(cursor);
+}
+
+fn open_in_editor(terminal: &mut DefaultTerminal, editor: Option<&str>, path: &str, line_num: &str) {
+    let editor = editor.map(String::from).or_else(|| env::var("VISUAL").ok()).or_else(|| env::var("EDITOR").ok()).unwrap_or_else(|| String::from("vi"));
+    let mut words = editor.split_whitespace();
+    let Some(program) = words.next() else {
+        return;
+    };
+    // The editor gets the terminal as it was, and the TUI takes it back
+    let _ = terminal::disable_raw_mode();
+    let _ = execute!(io::stdout(), LeaveAlternateScreen);
+    let _ = Command::new(program).args(words).arg(format!("+{}", line_num)).arg(path).status();
+    let _ = terminal::enable_raw_mode();
+    let _ = execute!(io::stdout(), EnterAlternateScreen);
+    let _ = terminal.clear();
+}
+
+// Waits up to `timeout` for keys and returns the ones typed.
+fn read_keys(timeout: Duration) -> Vec<Key> {
+    let mut keys = Vec::new();
+    let mut timeout = timeout;
+    // Everything typed meanwhile is taken at once, without waiting again
+    while event::poll(timeout).unwrap_or(false) {
+        match event::read() {
+            Ok(Event::Key(key_event)) if key_event.kind != KeyEventKind::Release => keys.extend(key(key_event)),
+            Ok(_) => {}
+            Err(_) => {
+                keys.push(Key::Quit);
+                break;
+            }
+        }
+        timeout = Duration::ZERO;
+    }
+    keys
+}
+
+fn key(key_event: KeyEvent) -> Option<Key> {
+    let control = key_event.modifiers.contains(KeyModifiers::CONTROL);
+    match key_event.code {
+        KeyCode::Esc => Some(Key::Quit),
+        KeyCode::Char('c' | 'd') if control => Some(Key::Quit),
+        KeyCode::Char('u') if control => Some(Key::ClearLine),
+        KeyCode::Char('p') if control => Some(Key::Up),
+        KeyCode::Char('n') if control => Some(Key::Down),
+        KeyCode::Char(_) if control => None,
+        KeyCode::Char(c) => Some(Key::Char(c)),
+        KeyCode::Backspace => Some(Key::Backspace),
+        KeyCode::Enter => Some(Key::Enter),
+        KeyCode::Up => Some(Key::Up),
+        KeyCode::Down => Some(Key::Down),
+        _ => None,
+    }
+}
@@ -0,0 +1,49 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+// The files git tracks below a root, for tracked_only. Directories without
+// any are not walked, so build output and caches are never even listed.
+#[derive(Default, PartialEq)]
+pub struct Tracked {
+    files: HashSet<PathBuf>,
+    dirs: HashSet<PathBuf>,
+    // The index of the repository, rewritten by git add, checkout and the
+    // like. Only watched when it is below the root.
+    pub index: PathBuf,
+}
+
+impl Tracked {
+    // Fails with the complaint of git when `root` is not in a work tree.
+    pub fn read(root: &Path) -> Result<Tracked, String> {
+        let listed = git(root, &["ls-files", "-z", "--cached", "--recurse-submodules"])?;
+        let git_dir = git(root, &["rev-parse", "--absolute-git-dir"])?;
+        let mut tracked = Tracked { index: PathBuf::from(git_dir.trim_end()).join("index"), ..Tracked::default() };
+        // Paths are relative to the root, which may be deep in the work tree
+        for relative_path in listed.split('\0').filter(|path| !path.is_empty()) {
+            let path = root.join(relative_path);
+            tracked.dirs.extend(path.ancestors().skip(1).take_while(|dir| *dir != root).map(Path::to_path_buf));
+            tracked.files.insert(path);
+        }
+        Ok(tracked)
+    }
+
+    pub fn contains(&self, path: &Path) -> bool {
+        self.files.contains(path)
+    }
+
+    // Whether a tracked file is somewhere below `dir`.
+    pub fn has_below(&self, dir: &Path) -> bool {
+        self.dirs.contains(dir)
+    }
+}
+
+fn git(root: &Path, args: &[&str]) -> Result<String, String> {
+    let output = Command::new("git").arg("-C").arg(root).args(args).output().map_err(|e| format!("git: {}", e))?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
@@ -0,0 +1,302 @@
+use notify::{Event, RecursiveMode, Watcher};
+use notify_debouncer_full::{new_debouncer, DebounceEventResult};
+
+use std::{
+    collections::HashMap,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+pub struct VfsMetadata {
+    pub is_dir: bool,
+    pub is_file: bool,
+    pub is_symlink: bool,
+    pub len: u64,
+    pub modified: Option<SystemTime>,
+    // (device, inode) where the platform provides them
+    pub file_id: Option<(u64, u64)>,
+}
+
+// Receives each debounced batch of events, or the errors of a batch.
+pub type WatchHandler = Box<dyn FnMut(notify::Result<Vec<Event>>) + Send>;
+// Watching stops when the guard is dropped.
+pub type WatchGuard = Box<dyn Send>;
+
+// Everything the indexer, its jobs and the client need from a file system.
+// The server and the client use OsVfs, the tests a tree in memory. Other
+// providers implement all of it, the writes only keep the state of jobs.
+pub trait Vfs: Send + Sync {
+    fn read_to_string(&self, path: &Path) -> io::Result<String>;
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>>;
+    // Follows symlinks
+    fn metadata(&self, path: &Path) -> io::Result<VfsMetadata>;
+    // Describes the link itself rather than its target
+    fn symlink_metadata(&self, path: &Path) -> io::Result<VfsMetadata>;
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf>;
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>>;
+    // Events are coalesced over `debounce` so a burst of writes to one file
+    // reaches the handler once.
+    fn watch(&self, path: &Path, debounce: Duration, handler: WatchHandler) -> notify::Result<WatchGuard>;
+    fn create_dir_all(&self, path: &Path) -> io::Result<()>;
+    // Creates the file or replaces what it held
+    fn write(&self, path: &Path, contents: &[u8]) -> io::Result<()>;
+    // Creates the file if needed, and returns once `contents` are on disk
+    fn append(&self, path: &Path, contents: &[u8]) -> io::Result<()>;
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
+}
+
+#[derive(Default)]
+pub struct OsVfs;
+
+impl Vfs for OsVfs {
+    fn read_to_string(&self, path: &Path) -> io::Result<String> {
+        fs::read_to_string(path)
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        fs::read(path)
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<VfsMetadata> {
+        Ok(to_vfs_metadata(&fs::metadata(path)?))
+    }
+
+    fn symlink_metadata(&self, path: &Path) -> io::Result<VfsMetadata> {
+        Ok(to_vfs_metadata(&fs::symlink_metadata(path)?))
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        fs::canonicalize(path)
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        let mut paths = Vec::new();
+        for entry in fs::read_dir(path)? {
+            paths.push(entry?.path());
+        }
+        Ok(paths)
+    }
+
+    fn watch(&self, path: &Path, debounce: Duration, mut handler: WatchHandler) -> notify::Result<WatchGuard> {
+        let mut debouncer = new_debouncer(debounce, None, move |result: DebounceEventResult| {
+            match result {
+                Ok(events) => handler(Ok(coalesce(events.into_iter().map(|event| event.event).collect()))),
+                Err(errors) => {
+                    for error in errors {
+                        handler(Err(error));
+                    }
+                }
+            }
+        })?;
+        debouncer.watcher().watch(path, RecursiveMode::Recursive)?;
+        debouncer.cache().add_root(path, RecursiveMode::Recursive);
+        Ok(Box::new(debouncer))
+    }
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        fs::create_dir_all(path)
+    }
+
+    fn write(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+        fs::write(path, contents)
+    }
+
+    fn append(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+        let mut file = OpenOptions::new().append(true).create(true).open(path)?;
+        file.write_all(contents)?;
+        file.sync_data()
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        fs::rename(from, to)
+    }
+}
+
+fn to_vfs_metadata(metadata: &fs::Metadata) -> VfsMetadata {
+    #[cfg(unix)]
+    let file_id = {
+        use std::os::unix::fs::MetadataExt;
+        Some((metadata.dev(), metadata.ino()))
+    };
+    #[cfg(not(unix))]
+    let file_id = None;
+    VfsMetadata {
+        is_dir: metadata.is_dir(),
+        is_file: metadata.is_file(),
+        is_symlink: metadata.file_type().is_symlink(),
+        len: metadata.len(),
+        modified: metadata.modified().ok(),
+        file_id,
+    }
+}
+
+// Keeps only the last event of every single-path event in a batch, so a file
+// that was written several times within the window is re-read once. Access
+// events go first, or the close after a write would hide the write.
+fn coalesce(mut events: Vec<Event>) -> Vec<Event> {
+    events.retain(|event| !event.kind.is_access());
+    let mut last_index: HashMap<PathBuf, usize> = HashMap::new();
+    for (index, event) in events.iter().enumerate() {
+        if let [path] = event.paths.as_slice() {
+            last_index.insert(path.clone(), index);
+        }
+    }
+    events
+        .into_iter()
+        .enumerate()
+        .filter(|(index, event)| match event.paths.as_slice() {
+            [path] => last_index.get(path) == Some(index),
+            _ => true,
+        })
+        .map(|(_, event)| event)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{index::local_index, Cli};
+
+    use notify::event::{AccessKind, AccessMode, DataChange, EventKind, ModifyKind, RenameMode};
+
+    use std::{
+        collections::BTreeMap,
+        sync::{Arc, Mutex, MutexGuard},
+    };
+
+    // A tree of files kept in memory, by absolute path. Directories are
+    // the ones made with create_dir_all and the parents of every file.
+    // There are no symlinks, and nothing changes behind the back of a
+    // watcher.
+    #[derive(Default)]
+    struct MemoryVfs {
+        // None for directories
+        entries: Mutex<BTreeMap<PathBuf, Option<Vec<u8>>>>,
+    }
+
+    impl MemoryVfs {
+        fn entries(&self) -> MutexGuard<'_, BTreeMap<PathBuf, Option<Vec<u8>>>> {
+            self.entries.lock().unwrap_or_else(|e| e.into_inner())
+        }
+
+        fn not_found(path: &Path) -> io::Error {
+            io::Error::new(io::ErrorKind::NotFound, path.display().to_string())
+        }
+    }
+
+    impl Vfs for MemoryVfs {
+        fn read_to_string(&self, path: &Path) -> io::Result<String> {
+            String::from_utf8(self.read(path)?).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        }
+
+        fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+            match self.entries().get(path) {
+                Some(Some(contents)) => Ok(contents.clone()),
+                Some(None) => Err(io::Error::new(io::ErrorKind::IsADirectory, path.display().to_string())),
+                None => Err(Self::not_found(path)),
+            }
+        }
+
+        fn metadata(&self, path: &Path) -> io::Result<VfsMetadata> {
+            let entries = self.entries();
+            let entry = entries.get(path).ok_or_else(|| Self::not_found(path))?;
+            Ok(VfsMetadata {
+                is_dir: entry.is_none(),
+                is_file: entry.is_some(),
+                is_symlink: false,
+                len: entry.as_ref().map_or(0, |contents| contents.len() as u64),
+                modified: None,
+                file_id: None,
+            })
+        }
+
+        fn symlink_metadata(&self, path: &Path) -> io::Result<VfsMetadata> {
+            self.metadata(path)
+        }
+
+        fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+            self.metadata(path).map(|_| path.to_path_buf())
+        }
+
+        fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+            if !self.metadata(path)?.is_dir {
+                return Err(io::Error::new(io::ErrorKind::NotADirectory, path.display().to_string()));
+            }
+            Ok(self.entries().keys().filter(|entry| entry.parent() == Some(path)).cloned().collect())
+        }
+
+        fn watch(&self, _path: &Path, _debounce: Duration, _handler: WatchHandler) -> notify::Result<WatchGuard> {
+            Ok(Box::new(()))
+        }
+
+        fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+            let mut entries = self.entries();
+            for dir in path.ancestors() {
+                if let Some(Some(_)) = entries.get(dir) {
+                    return Err(io::Error::new(io::ErrorKind::AlreadyExists, dir.display().to_string()));
+                }
+                entries.insert(dir.to_path_buf(), None);
+            }
+            Ok(())
+        }
+
+        fn write(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+            self.create_dir_all(path.parent().ok_or_else(|| Self::not_found(path))?)?;
+            self.entries().insert(path.to_path_buf(), Some(contents.to_vec()));
+            Ok(())
+        }
+
+        fn append(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+            let mut appended = self.read(path).unwrap_or_default();
+            appended.extend_from_slice(contents);
+            self.write(path, &appended)
+        }
+
+        fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+            let contents = self.read(from)?;
+            self.write(to, &contents)?;
+            self.entries().remove(from);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn builds_an_index_from_memory() {
+        let vfs = MemoryVfs::default();
+        vfs.write(Path::new("/src/.hanoi"), b"[filters]\n*.rs\n").unwrap();
+        vfs.write(Path::new("/src/main.rs"), b"fn main() {\n    needle();\n}\n").unwrap();
+        vfs.write(Path::new("/src/lib/needle.rs"), b"pub fn needle() {}\n").unwrap();
+        vfs.write(Path::new("/src/notes.txt"), b"needle\n").unwrap();
+        let mut args = Cli::try_parse_remote(Vec::new()).unwrap();
+        let indexer = local_index(Arc::new(vfs), &mut args, Path::new("/src")).unwrap();
+        let mut files: Vec<&Path> = indexer.files.keys().map(|path| path.as_ref()).collect();
+        files.sort();
+        assert_eq!(files, [Path::new("/src/lib/needle.rs"), Path::new("/src/main.rs")]);
+        args.term = Some(String::from("needle"));
+        let mut found = Vec::new();
+        indexer.find(&args, &mut found);
+        let mut lines: Vec<&str> = std::str::from_utf8(&found).unwrap().lines().collect();
+        lines.sort();
+        assert_eq!(lines, ["/src/lib/needle.rs:1: pub fn needle() {}", "/src/main.rs:2:     needle();"]);
+    }
+
+    fn event(kind: EventKind, paths: &[&str]) -> Event {
+        paths.iter().fold(Event::new(kind), |event, path| event.add_path(PathBuf::from(path)))
+    }
+
+    #[test]
+    fn coalesce_keeps_the_write_before_a_close() {
+        let write = event(EventKind::Modify(ModifyKind::Data(DataChange::Content)), &["/src/main.rs"]);
+        let close = event(EventKind::Access(AccessKind::Close(AccessMode::Write)), &["/src/main.rs"]);
+        assert_eq!(coalesce(vec![write.clone(), close.clone(), write.clone(), close]), [write]);
+    }
+
+    #[test]
+    fn coalesce_passes_renames_through() {
+        let rename = event(EventKind::Modify(ModifyKind::Name(RenameMode::Both)), &["/src/old.rs", "/src/new.rs"]);
+        let write = event(EventKind::Modify(ModifyKind::Data(DataChange::Content)), &["/src/new.rs"]);
+        assert_eq!(coalesce(vec![rename.clone(), write.clone(), rename.clone()]), [rename.clone(), write, rename]);
+    }
+}
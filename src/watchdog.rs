@@ -0,0 +1,180 @@
+use crate::messages::message;
+
+use tracing::{error, warn};
+
+use std::{
+    collections::HashMap,
+    ops::{Deref, DerefMut},
+    sync::{Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard},
+    thread::{self, ThreadId},
+    time::{Duration, Instant},
+};
+
+// Server locks must be taken in this order. A thread holding "publications"
+// never waits for "indexer".
+const LOCK_ORDER: [&str; 2] = ["indexer", "publications"];
+
+struct ThreadState {
+    name: String,
+    activity: Option<(String, Instant)>,
+    holding: Vec<&'static str>,
+    waiting_for: Option<(&'static str, Instant)>,
+    reported: bool,
+}
+
+static THREADS: Mutex<Option<HashMap<ThreadId, ThreadState>>> = Mutex::new(None);
+
+fn with_current<R>(f: impl FnOnce(&mut ThreadState) -> R) -> R {
+    let mut threads = THREADS.lock().unwrap_or_else(|e| e.into_inner());
+    let current = thread::current();
+    let state = threads.get_or_insert_with(HashMap::new).entry(current.id()).or_insert_with(|| ThreadState {
+        name: current.name().map_or_else(|| format!("{:?}", current.id()), String::from),
+        activity: None,
+        holding: Vec::new(),
+        waiting_for: None,
+        reported: false,
+    });
+    f(state)
+}
+
+fn forget_if_idle() {
+    let mut threads = THREADS.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(threads) = threads.as_mut() {
+        let id = thread::current().id();
+        if threads.get(&id).is_some_and(|state| state.activity.is_none() && state.holding.is_empty()) {
+            threads.remove(&id);
+        }
+    }
+}
+
+// Marks the current thread as busy with `label` until the returned guard is
+// dropped. The watchdog reports activities running longer than the limit.
+pub struct Activity;
+
+pub fn track(label: impl Into<String>) -> Activity {
+    with_current(|state| {
+        state.activity = Some((label.into(), Instant::now()));
+        state.reported = false;
+    });
+    Activity
+}
+
+impl Drop for Activity {
+    fn drop(&mut self) {
+        with_current(|state| state.activity = None);
+        forget_if_idle();
+    }
+}
+
+pub struct Locked<G> {
+    name: &'static str,
+    guard: G,
+}
+
+// Locks `mutex` while recording that the current thread waits for and then
+// holds the lock called `name`, so a hang report shows who holds what.
+pub fn lock<'a, T>(name: &'static str, mutex: &'a Mutex<T>) -> Locked<MutexGuard<'a, T>> {
+    acquire(name, || mutex.lock().unwrap())
+}
+
+// Like `lock` for locks that any number of readers may hold at once.
+pub fn read<'a, T>(name: &'static str, lock: &'a RwLock<T>) -> Locked<RwLockReadGuard<'a, T>> {
+    acquire(name, || lock.read().unwrap())
+}
+
+pub fn write<'a, T>(name: &'static str, lock: &'a RwLock<T>) -> Locked<RwLockWriteGuard<'a, T>> {
+    acquire(name, || lock.write().unwrap())
+}
+
+fn acquire<G>(name: &'static str, take: impl FnOnce() -> G) -> Locked<G> {
+    with_current(|state| {
+        let rank = LOCK_ORDER.iter().position(|lock| *lock == name);
+        let out_of_order = |held: &&&str| rank.is_some() && LOCK_ORDER.iter().position(|lock| lock == *held) > rank;
+        if let Some(held) = state.holding.iter().find(out_of_order) {
+            warn!("lock order violation: thread {} takes \"{}\" while holding \"{}\"", state.name, name, held);
+        }
+        state.waiting_for = Some((name, Instant::now()));
+    });
+    let guard = take();
+    with_current(|state| {
+        state.waiting_for = None;
+        state.holding.push(name);
+    });
+    Locked { name, guard }
+}
+
+impl<G: Deref> Deref for Locked<G> {
+    type Target = G::Target;
+
+    fn deref(&self) -> &G::Target {
+        &self.guard
+    }
+}
+
+impl<G: DerefMut> DerefMut for Locked<G> {
+    fn deref_mut(&mut self) -> &mut G::Target {
+        &mut self.guard
+    }
+}
+
+impl<G> Drop for Locked<G> {
+    fn drop(&mut self) {
+        let name = self.name;
+        with_current(|state| {
+            if let Some(index) = state.holding.iter().rposition(|held| *held == name) {
+                state.holding.remove(index);
+            }
+        });
+        forget_if_idle();
+    }
+}
+
+// Checks the tracked threads every so often and dumps their states when an
+// activity runs past `limit`, instead of letting the server freeze silently.
+pub fn start(limit: Duration) {
+    let interval = Duration::max(limit / 4, Duration::from_millis(100));
+    thread::Builder::new()
+        .name(String::from("watchdog"))
+        .spawn(move || loop {
+            thread::sleep(interval);
+            check(limit);
+        })
+        .unwrap();
+}
+
+fn check(limit: Duration) {
+    let mut threads = THREADS.lock().unwrap_or_else(|e| e.into_inner());
+    let Some(threads) = threads.as_mut() else {
+        return;
+    };
+    let mut hung: Vec<(String, Duration)> = Vec::new();
+    for state in threads.values_mut() {
+        if let Some((label, started)) = &state.activity {
+            if !state.reported && started.elapsed() > limit {
+                state.reported = true;
+                hung.push((format!("{} on thread {}", label, state.name), started.elapsed()));
+            }
+        }
+    }
+    if hung.is_empty() {
+        return;
+    }
+    for (activity, elapsed) in &hung {
+        error!("{}", message!(Hang, activity, elapsed.as_secs()));
+    }
+    for state in threads.values() {
+        let activity = state.activity.as_ref().map_or(String::from("idle"), |(label, started)| format!("{} for {}s", label, started.elapsed().as_secs()));
+        let waiting = state.waiting_for.map_or(String::new(), |(lock, since)| format!(", waiting for \"{}\" for {}s", lock, since.elapsed().as_secs()));
+        error!("  thread {}: {}, holding {:?}{}", state.name, activity, state.holding, waiting);
+    }
+    // Two threads each waiting for a lock the other holds never recover
+    for a in threads.values() {
+        for b in threads.values() {
+            if let (Some((a_wants, _)), Some((b_wants, _))) = (a.waiting_for, b.waiting_for) {
+                if a.name < b.name && a.holding.contains(&b_wants) && b.holding.contains(&a_wants) {
+                    error!("  deadlock: thread {} and thread {} wait for each other (\"{}\", \"{}\")", a.name, b.name, a_wants, b_wants);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,33 @@
+use std::path::Path;
+
+// What --word takes to be part of an identifier besides letters, digits and
+// _, by the extension of the file. Files of other extensions only have those.
+const WORD_RULES: &[(&[&str], &str)] = &[
+    // (define (string->list s) ...), (defun my-list-p ...)
+    (&["el", "lisp", "lsp", "cl", "scm", "ss", "rkt", "clj", "cljs", "cljc", "edn", "fnl"], "-!?*+<>=/"),
+    (&["js", "jsx", "ts", "tsx", "mjs", "cjs"], "$"),
+    (&["sh", "bash", "zsh", "ksh", "php"], "$"),
+    (&["rb"], "?!"),
+    (&["css", "scss", "sass", "less", "html", "htm"], "-"),
+];
+
+#[derive(Clone, Copy)]
+pub struct WordChars(&'static str);
+
+impl WordChars {
+    pub fn for_path(path: &Path) -> WordChars {
+        let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or_default();
+        let extra = WORD_RULES.iter().find(|(extensions, _)| extensions.contains(&extension)).map_or("", |(_, extra)| *extra);
+        WordChars(extra)
+    }
+
+    pub fn contains(self, c: char) -> bool {
+        c.is_alphanumeric() || c == '_' || self.0.contains(c)
+    }
+
+    // Whether the `len` bytes at `offset` of `text` are not part of a longer
+    // identifier.
+    pub fn is_whole_word(self, text: &str, offset: usize, len: usize) -> bool {
+        !text[..offset].chars().next_back().is_some_and(|c| self.contains(c)) && !text[offset + len..].chars().next().is_some_and(|c| self.contains(c))
+    }
+}